@@ -0,0 +1,169 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use crate::crypto::{channel::{self, Direction, Session, SEQUENCE_HEADER, SESSION_HEADER}, signature::{self, SignatureParameters, SigningKey}, utils::Parameters};
+use crate::models::{HandshakeRequest, HandshakeResponse};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Sessions older than this must run a new handshake
+const SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Most sessions held at once; further handshakes are refused until old sessions expire
+const MAX_SESSIONS: usize = 10_000;
+
+/// Largest encrypted body the middleware will buffer, matching axum's default body limit
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// An open session and the lowest sequence number it still accepts
+struct OpenSession {
+    session: Session,
+    created: Instant,
+    next_sequence: u64,
+}
+
+/// Request extension marking a request that arrived sealed under a channel session
+#[derive(Clone, Copy)]
+pub struct Sealed;
+
+/// Static signing key and open sessions of the secure channel; without a signing key the
+/// channel is disabled
+#[derive(Clone)]
+pub struct ChannelState {
+    signing_key: Option<Arc<SigningKey>>,
+    sessions: Arc<Mutex<HashMap<String, OpenSession>>>,
+}
+
+impl ChannelState {
+    /// A channel signing with this key, whose verifying half clients pin out of band
+    pub fn new(signing_key: SigningKey) -> Self {
+        ChannelState { signing_key: Some(Arc::new(signing_key)), sessions: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// A channel that refuses handshakes and sealed requests
+    pub fn disabled() -> Self {
+        ChannelState { signing_key: None, sessions: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Load the static signing key from `CHANNEL_VERIFYING_KEY` and `CHANNEL_SIGNING_KEY`
+    ///
+    /// With neither set, the channel is disabled and an error is logged: a key generated at
+    /// startup could only be learned over the plaintext connection the channel protects, where
+    /// an active attacker substitutes their own. Panics when only one is set or they do not
+    /// match.
+    pub fn from_env() -> Self {
+        let sig_params = SignatureParameters::default();
+        let (verifying_key, signing_key) = match (std::env::var("CHANNEL_VERIFYING_KEY"), std::env::var("CHANNEL_SIGNING_KEY")) {
+            (Ok(verifying_key), Ok(signing_key)) => (verifying_key, signing_key),
+            (Err(_), Err(_)) => {
+                tracing::error!("secure channel disabled: set CHANNEL_VERIFYING_KEY and CHANNEL_SIGNING_KEY to a key pair from /sign/keygen");
+                return Self::disabled();
+            }
+            _ => panic!("set both CHANNEL_VERIFYING_KEY and CHANNEL_SIGNING_KEY, or neither"),
+        };
+        let signing_key = signature::signing_key_from_string(&signing_key, &sig_params).expect("CHANNEL_SIGNING_KEY is not a signing key");
+        if signature::verifying_key_to_string(&signing_key.verifying_key, &sig_params) != verifying_key.trim() {
+            panic!("CHANNEL_VERIFYING_KEY does not match CHANNEL_SIGNING_KEY");
+        }
+        Self::new(signing_key)
+    }
+
+    fn session(&self, id: &str) -> Option<(Session, u64)> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(id)
+            .filter(|open| open.created.elapsed() < SESSION_LIFETIME)
+            .map(|open| (open.session.clone(), open.next_sequence))
+    }
+
+    /// Mark a sequence number as used, failing when it or a later one already was
+    fn advance(&self, id: &str, sequence: u64) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        match (sessions.get_mut(id), sequence.checked_add(1)) {
+            (Some(open), Some(next)) if sequence >= open.next_sequence => {
+                open.next_sequence = next;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Answer for handshakes and sealed requests while the channel has no signing key
+fn disabled() -> (StatusCode, String) {
+    (StatusCode::SERVICE_UNAVAILABLE, String::from("the secure channel is not configured on this server"))
+}
+
+pub async fn handshake_handler(State(state): State<ChannelState>, Json(payload): Json<HandshakeRequest>) -> Result<Json<HandshakeResponse>, (StatusCode, String)> {
+    let signing_key = state.signing_key.clone().ok_or_else(disabled)?;
+    let full = || (StatusCode::SERVICE_UNAVAILABLE, String::from("too many open sessions, try again later"));
+    {
+        let mut sessions = state.sessions.lock().unwrap();
+        sessions.retain(|_, open| open.created.elapsed() < SESSION_LIFETIME);
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(full());
+        }
+    }
+    let (reply, session) = tokio::task::spawn_blocking(move || {
+        channel::server_accept(&signing_key, &payload.hello, &Parameters::default(), &SignatureParameters::default())
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut sessions = state.sessions.lock().unwrap();
+    if sessions.len() >= MAX_SESSIONS {
+        return Err(full());
+    }
+    sessions.insert(session.id.clone(), OpenSession { session, created: Instant::now(), next_sequence: 0 });
+    Ok(Json(HandshakeResponse { reply }))
+}
+
+/// Decrypt request bodies and encrypt response bodies of requests that name a session
+///
+/// Requests without the session header pass through untouched, so the channel stays optional.
+/// Requests that name a session must carry a sealed body, even an empty one, and a sequence
+/// number above every number the session has accepted; the response is sealed under the same
+/// number, so it answers exactly that request and neither can be replayed.
+pub async fn secure_channel(State(state): State<ChannelState>, request: Request, next: Next) -> Result<Response, (StatusCode, String)> {
+    let Some(id) = request.headers().get(SESSION_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    if state.signing_key.is_none() {
+        return Err(disabled());
+    }
+    let id = id.to_str().map_err(|_| (StatusCode::BAD_REQUEST, String::from("invalid session header")))?.to_string();
+    let sequence: u64 = request.headers().get(SEQUENCE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, String::from("missing or invalid sequence header")))?;
+    let (session, next_sequence) = state.session(&id)
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, String::from("unknown or expired session")))?;
+    let replayed = || (StatusCode::CONFLICT, String::from("sequence number already used"));
+    if sequence < next_sequence {
+        return Err(replayed());
+    }
+    let path = request.uri().path().to_string();
+
+    let (mut parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_BYTES).await.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let body = std::str::from_utf8(&body).map_err(|_| (StatusCode::BAD_REQUEST, String::from("encrypted body is not base64")))?;
+    let plaintext = session.open(Direction::Request, &path, sequence, body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    // only an authentic request uses up its sequence number
+    if !state.advance(&id, sequence) {
+        return Err(replayed());
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.extensions.insert(Sealed);
+    let response = next.run(Request::from_parts(parts, Body::from(plaintext))).await;
+
+    let (mut parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let sealed = session.seal(Direction::Response, &path, sequence, &body);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(SESSION_HEADER, HeaderValue::from_str(&id).unwrap());
+    parts.headers.insert(SEQUENCE_HEADER, HeaderValue::from(sequence));
+    Ok(Response::from_parts(parts, Body::from(sealed)))
+}
@@ -1,41 +1,167 @@
-use axum::Json;
-use crate::models::{KeygenResponse, EncryptRequest, EncryptResponse, DecryptRequest, DecryptResponse};
-use crate::crypto::{keygen, encrypt, decrypt, utils::Parameters};
+use axum::{body::Bytes, http::StatusCode, Json};
+use crate::models::{Scheme, KeygenRequest, KeygenResponse, EncryptRequest, EncryptResponse, DecryptRequest, DecryptResponse, RerandomizeRequest, SealRequest, SealResponse, OpenRequest, SignRequest, SignResponse, VerifyRequest, VerifyResponse};
+use crate::crypto::{keygen, encrypt, decrypt, hybrid, anonymous, seal, signature, secret_key::SecretKey, utils::Parameters, validate::validate_public_key};
+use crate::crypto::signature::SignatureParameters;
+use std::collections::HashMap;
 
 pub async fn health_check() -> &'static str {
     "OK"
 }
 
-pub async fn keygen_handler() -> Json<KeygenResponse> {
-    let keys = tokio::task::spawn_blocking(move || {
+/// Parse an optional JSON request body: only an empty body means the defaults, so a malformed
+/// request is refused rather than served as if no options were given
+fn optional_request(body: &Bytes) -> Result<KeygenRequest, (StatusCode, String)> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(KeygenRequest::default());
+    }
+    serde_json::from_slice(body).map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid request body: {}", e)))
+}
+
+pub async fn keygen_handler(body: Bytes) -> Result<Json<KeygenResponse>, (StatusCode, String)> {
+    let request = optional_request(&body)?;
+    if request.scheme == Scheme::Hybrid && request.password.is_some() {
+        return Err((StatusCode::BAD_REQUEST, String::from("password-protected keys are only available for ring-lwe")));
+    }
+    let response = tokio::task::spawn_blocking(move || {
         let params = Parameters::default();
-        keygen::keygen_string(&params, None)
+        let keys = match request.scheme {
+            Scheme::RingLwe | Scheme::Anonymous => keygen::keygen_string(&params, None),
+            Scheme::Hybrid => hybrid::hybrid_keygen_string(&params),
+        };
+        key_response(keys, request.password)
     }).await.unwrap();
-    
-    Json(KeygenResponse {
-        public_key: keys.get("public").unwrap().clone(),
-        secret_key: keys.get("secret").unwrap().clone(),
-    })
+
+    Ok(Json(response))
+}
+
+pub async fn sign_keygen_handler(body: Bytes) -> Result<Json<KeygenResponse>, (StatusCode, String)> {
+    let request = optional_request(&body)?;
+    let response = tokio::task::spawn_blocking(move || {
+        let params = SignatureParameters::default();
+        key_response(signature::sign_keygen_string(&params, None), request.password)
+    }).await.unwrap();
+
+    Ok(Json(response))
+}
+
+/// Build a keygen response, wrapping the secret key when a password is given
+fn key_response(keys: HashMap<String, String>, password: Option<String>) -> KeygenResponse {
+    let public_key = keys.get("public").unwrap().clone();
+    let secret_key = keys.get("secret").unwrap().clone();
+    match password {
+        // never hand out the raw secret key when the caller asked for it wrapped
+        Some(password) => KeygenResponse {
+            public_key,
+            secret_key: None,
+            encrypted_secret_key: Some(SecretKey::from_base64(&secret_key).export_encrypted(&password)),
+        },
+        None => KeygenResponse {
+            public_key,
+            secret_key: Some(secret_key),
+            encrypted_secret_key: None,
+        },
+    }
 }
 
-pub async fn encrypt_handler(Json(payload): Json<EncryptRequest>) -> Json<EncryptResponse> {
+pub async fn encrypt_handler(Json(payload): Json<EncryptRequest>) -> Result<Json<EncryptResponse>, (StatusCode, String)> {
     let ciphertext = tokio::task::spawn_blocking(move || {
         let params = Parameters::default();
-        encrypt::encrypt_string(&payload.public_key, &payload.message, &params, None)
-    }).await.unwrap();
-    
-    Json(EncryptResponse {
+        match payload.scheme {
+            Scheme::RingLwe => validate_public_key(&payload.public_key, &params)
+                .map(|_| encrypt::encrypt_string(&payload.public_key, &payload.message, &params, None)),
+            Scheme::Hybrid => hybrid::hybrid_encrypt_string(&payload.public_key, &payload.message, &params),
+            Scheme::Anonymous => anonymous::anonymous_encrypt(&payload.public_key, &payload.message, &params),
+        }
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(EncryptResponse {
         ciphertext,
-    })
+    }))
 }
 
-pub async fn decrypt_handler(Json(payload): Json<DecryptRequest>) -> Json<DecryptResponse> {
+pub async fn decrypt_handler(Json(payload): Json<DecryptRequest>) -> Result<Json<DecryptResponse>, (StatusCode, String)> {
     let message = tokio::task::spawn_blocking(move || {
         let params = Parameters::default();
-        decrypt::decrypt_string(&payload.secret_key, &payload.ciphertext, &params)
-    }).await.unwrap();
-    
-    Json(DecryptResponse {
+        let secret_key = match &payload.password {
+            Some(password) => SecretKey::import_encrypted(&payload.secret_key, password)?.to_base64(),
+            None => payload.secret_key,
+        };
+        match payload.scheme {
+            Scheme::RingLwe => Ok(decrypt::decrypt_string(&secret_key, &payload.ciphertext, &params)),
+            Scheme::Hybrid => hybrid::hybrid_decrypt_string(&secret_key, &payload.ciphertext, &params),
+            Scheme::Anonymous => anonymous::anonymous_decrypt(&secret_key, &payload.ciphertext, &params),
+        }
+    }).await.unwrap().map_err(|e: String| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(DecryptResponse {
         message,
+    }))
+}
+
+pub async fn rerandomize_handler(Json(payload): Json<RerandomizeRequest>) -> Result<Json<EncryptResponse>, (StatusCode, String)> {
+    let ciphertext = tokio::task::spawn_blocking(move || {
+        encrypt::rerandomize_string(&payload.public_key, &payload.ciphertext, &Parameters::default())
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(EncryptResponse {
+        ciphertext,
+    }))
+}
+
+pub async fn seal_handler(Json(payload): Json<SealRequest>) -> Result<Json<SealResponse>, (StatusCode, String)> {
+    if payload.public_keys.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, String::from("at least one public key is required")));
+    }
+    let sealed = tokio::task::spawn_blocking(move || {
+        let params = Parameters::default();
+        for public_key in &payload.public_keys {
+            validate_public_key(public_key, &params)?;
+        }
+        Ok(seal::seal(&payload.public_keys, &payload.message, &params))
+    }).await.unwrap().map_err(|e: String| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(SealResponse {
+        sealed,
+    }))
+}
+
+pub async fn open_handler(Json(payload): Json<OpenRequest>) -> Result<Json<DecryptResponse>, (StatusCode, String)> {
+    let message = tokio::task::spawn_blocking(move || {
+        let params = Parameters::default();
+        let secret_key = match &payload.password {
+            Some(password) => SecretKey::import_encrypted(&payload.secret_key, password)?.to_base64(),
+            None => payload.secret_key,
+        };
+        seal::open(&secret_key, &payload.sealed, &params)
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(DecryptResponse {
+        message,
+    }))
+}
+
+pub async fn sign_handler(Json(payload): Json<SignRequest>) -> Result<Json<SignResponse>, (StatusCode, String)> {
+    let signature = tokio::task::spawn_blocking(move || {
+        let params = SignatureParameters::default();
+        let secret_key = match &payload.password {
+            Some(password) => SecretKey::import_encrypted(&payload.secret_key, password)?.to_base64(),
+            None => payload.secret_key,
+        };
+        signature::sign_string(&secret_key, &payload.message, &params)
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(SignResponse {
+        signature,
+    }))
+}
+
+pub async fn verify_handler(Json(payload): Json<VerifyRequest>) -> Json<VerifyResponse> {
+    let valid = tokio::task::spawn_blocking(move || {
+        let params = SignatureParameters::default();
+        signature::verify_string(&payload.public_key, &payload.message, &payload.signature, &params)
+    }).await.unwrap();
+
+    Json(VerifyResponse {
+        valid,
     })
 }
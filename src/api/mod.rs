@@ -1,2 +1,5 @@
+pub mod channel;
 pub mod handlers;
+pub mod prekeys;
+pub mod registry;
 pub mod routes;
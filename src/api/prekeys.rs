@@ -0,0 +1,142 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use crate::api::channel::Sealed;
+use crate::crypto::{armor::fingerprint, prekey, secret_key::SecretKey, signature::SignatureParameters, utils::Parameters};
+use crate::models::{DecryptResponse, EncryptResponse, PrekeyDecryptRequest, PrekeyEncryptRequest, PrekeyFetchRequest, PrekeyFetchResponse, PrekeyUploadRequest, PrekeyUploadResponse};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Length of the window in which one owner's one-time prekeys are rate limited
+const FETCH_WINDOW: Duration = Duration::from_secs(60);
+
+/// One-time prekeys handed out per owner and window; later fetches get the last-resort
+/// prekey, so draining an owner's prekeys takes time instead of a burst of requests
+const FETCHES_PER_WINDOW: usize = 10;
+
+/// Oldest last-resort prekey accepted, so one replayed after a restart of the in-memory store
+/// has to be recent
+const MAX_LAST_RESORT_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Published prekeys of one owner: (id, signed prekey) pairs not yet fetched, the id of every
+/// one-time prekey ever accepted, and the last-resort prekey with its signed creation time.
+/// Only public halves are stored; the owner keeps the secrets.
+#[derive(Default)]
+struct OwnerPrekeys {
+    available: VecDeque<(String, String)>,
+    accepted: HashSet<String>,
+    last_resort: Option<(u64, String)>,
+    window_start: Option<Instant>,
+    fetched_in_window: usize,
+}
+
+/// Prekeys of every owner, keyed by the fingerprint of their verifying key
+#[derive(Clone, Default)]
+pub struct PrekeyStore {
+    owners: Arc<Mutex<HashMap<String, OwnerPrekeys>>>,
+}
+
+impl PrekeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+pub async fn upload_handler(State(store): State<PrekeyStore>, Json(payload): Json<PrekeyUploadRequest>) -> Result<Json<PrekeyUploadResponse>, (StatusCode, String)> {
+    if payload.prekeys.is_empty() && payload.last_resort.is_none() {
+        return Err((StatusCode::BAD_REQUEST, String::from("no prekeys to upload")));
+    }
+    let verifying_key = payload.verifying_key.clone();
+    // only prekeys signed by the owner are accepted, so nobody can publish prekeys for someone else
+    let (prekeys, last_resort) = tokio::task::spawn_blocking(move || {
+        let sig_params = SignatureParameters::default();
+        let prekeys = payload.prekeys.into_iter()
+            .map(|uploaded| Ok((prekey::verify_prekey(&verifying_key, &uploaded, &sig_params)?, uploaded)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let last_resort = match payload.last_resort {
+            Some(last_resort) => Some((prekey::verify_last_resort_prekey(&verifying_key, &last_resort, &sig_params)?.1, last_resort)),
+            None => None,
+        };
+        Ok((prekeys, last_resort))
+    }).await.unwrap().map_err(|e: String| (StatusCode::BAD_REQUEST, e))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("system clock before 1970").as_secs();
+    if last_resort.as_ref().is_some_and(|(created, _)| now.saturating_sub(*created) > MAX_LAST_RESORT_AGE.as_secs()) {
+        return Err((StatusCode::BAD_REQUEST, String::from("the last-resort prekey is too old")));
+    }
+
+    // a prekey that was ever accepted, or a last-resort prekey older than the current one, is a
+    // replay: its secret may be deleted already, so serving it again would lose mail
+    let owner_fingerprint = fingerprint(&payload.verifying_key).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut owners = store.owners.lock().unwrap();
+    if let Some(owner) = owners.get(&owner_fingerprint) {
+        if prekeys.iter().any(|(id, _)| owner.accepted.contains(id)) {
+            return Err((StatusCode::CONFLICT, String::from("a prekey was already uploaded")));
+        }
+        if let (Some((created, _)), Some((current, _))) = (&last_resort, &owner.last_resort) {
+            if created < current {
+                return Err((StatusCode::CONFLICT, String::from("the last-resort prekey is older than the current one")));
+            }
+        }
+    }
+    let owner = owners.entry(owner_fingerprint).or_default();
+    for (id, uploaded) in prekeys {
+        if owner.accepted.insert(id.clone()) {
+            owner.available.push_back((id, uploaded));
+        }
+    }
+    if last_resort.is_some() {
+        owner.last_resort = last_resort;
+    }
+    Ok(Json(PrekeyUploadResponse { available: owner.available.len() }))
+}
+
+pub async fn fetch_handler(State(store): State<PrekeyStore>, Json(payload): Json<PrekeyFetchRequest>) -> Result<Json<PrekeyFetchResponse>, (StatusCode, String)> {
+    let owner = fingerprint(&payload.verifying_key).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut owners = store.owners.lock().unwrap();
+    let owner = owners.get_mut(&owner)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, String::from("no prekeys for this key")))?;
+    if owner.window_start.is_none_or(|start| start.elapsed() >= FETCH_WINDOW) {
+        owner.window_start = Some(Instant::now());
+        owner.fetched_in_window = 0;
+    }
+    // each one-time prekey is handed out once, at most FETCHES_PER_WINDOW per window
+    let limited = owner.fetched_in_window >= FETCHES_PER_WINDOW;
+    if let Some((_, prekey)) = if limited { None } else { owner.available.pop_front() } {
+        owner.fetched_in_window += 1;
+        return Ok(Json(PrekeyFetchResponse { prekey, last_resort: false }));
+    }
+    match &owner.last_resort {
+        Some((_, prekey)) => Ok(Json(PrekeyFetchResponse { prekey: prekey.clone(), last_resort: true })),
+        None if limited => Err((StatusCode::TOO_MANY_REQUESTS, String::from("too many prekeys fetched for this key, try again later"))),
+        None => Err((StatusCode::NOT_FOUND, String::from("no prekeys left for this key"))),
+    }
+}
+
+pub async fn encrypt_handler(Json(payload): Json<PrekeyEncryptRequest>) -> Result<Json<EncryptResponse>, (StatusCode, String)> {
+    let ciphertext = tokio::task::spawn_blocking(move || {
+        prekey::prekey_encrypt(&payload.public_key, &payload.verifying_key, &payload.prekey, &payload.message, &Parameters::default(), &SignatureParameters::default())
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(EncryptResponse {
+        ciphertext,
+    }))
+}
+
+/// Decrypt a prekey message with the prekey secret the owner supplies
+///
+/// Only served over the secure channel, since the request carries both secrets. The service
+/// keeps neither and does not track which prekeys were used, so forward secrecy rests on the
+/// owner deleting a one-time secret after its message decrypts.
+pub async fn decrypt_handler(sealed: Option<Extension<Sealed>>, Json(payload): Json<PrekeyDecryptRequest>) -> Result<Json<DecryptResponse>, (StatusCode, String)> {
+    if sealed.is_none() {
+        return Err((StatusCode::FORBIDDEN, String::from("prekey secrets are only accepted over the secure channel")));
+    }
+    let message = tokio::task::spawn_blocking(move || {
+        let secret_key = match &payload.password {
+            Some(password) => SecretKey::import_encrypted(&payload.secret_key, password)?.to_base64(),
+            None => payload.secret_key,
+        };
+        prekey::prekey_decrypt(&secret_key, &payload.prekey_secret_key, &payload.ciphertext, &Parameters::default())
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(DecryptResponse { message }))
+}
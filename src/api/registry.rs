@@ -0,0 +1,119 @@
+use axum::{extract::State, http::StatusCode, Json};
+use crate::crypto::{armor::fingerprint, possession, utils::Parameters};
+use crate::models::{RegisterChallengeRequest, RegisterChallengeResponse, RegisterCompleteRequest, RegisterCompleteResponse};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Challenges not answered within this time have to be requested again
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+const CHALLENGE_ID_BYTES: usize = 16;
+
+/// Unanswered challenges held at once; a new challenge beyond that evicts the oldest, so a flood
+/// of challenges for many keys shortens how long others stay open instead of refusing everyone
+const MAX_PENDING: usize = 10_000;
+
+/// Length of the window in which challenges for one public key are rate limited
+const ISSUE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Challenges issued per public key and window, so one key cannot fill the pending table or
+/// keep the server encrypting
+const CHALLENGES_PER_WINDOW: usize = 5;
+
+/// A public key waiting for its owner to answer the challenge
+struct PendingRegistration {
+    public_key: String,
+    expected: String,
+    issued: Instant,
+}
+
+/// Public keys whose owners proved possession of the secret key, keyed by fingerprint
+///
+/// Registered only means that whoever answered the challenge held the matching secret key at
+/// the time. The key is not bound to an account, address or mailbox, and no other route looks
+/// keys up here: prekeys, the channel and the encrypt and decrypt routes take any key. QMail has
+/// to tie fingerprints to its own accounts before trusting a key for a user.
+#[derive(Clone)]
+pub struct KeyRegistry {
+    max_pending: usize,
+    pending: Arc<Mutex<HashMap<String, PendingRegistration>>>,
+    /// start of the current window and challenges issued in it, per key fingerprint
+    issued: Arc<Mutex<HashMap<String, (Instant, usize)>>>,
+    keys: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl KeyRegistry {
+    pub fn new() -> Self {
+        Self::with_max_pending(MAX_PENDING)
+    }
+
+    /// A registry holding at most `max_pending` unanswered challenges
+    pub fn with_max_pending(max_pending: usize) -> Self {
+        KeyRegistry {
+            max_pending: max_pending.max(1),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            issued: Arc::new(Mutex::new(HashMap::new())),
+            keys: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The registered public key with this fingerprint
+    pub fn get(&self, fingerprint: &str) -> Option<String> {
+        self.keys.lock().unwrap().get(fingerprint).cloned()
+    }
+}
+
+impl Default for KeyRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub async fn challenge_handler(State(registry): State<KeyRegistry>, Json(payload): Json<RegisterChallengeRequest>) -> Result<Json<RegisterChallengeResponse>, (StatusCode, String)> {
+    let public_key = payload.public_key.trim().to_string();
+    let key_fingerprint = fingerprint(&public_key).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    {
+        let mut issued = registry.issued.lock().unwrap();
+        issued.retain(|_, (start, _)| start.elapsed() < ISSUE_WINDOW);
+        let (_, count) = issued.entry(key_fingerprint).or_insert_with(|| (Instant::now(), 0));
+        if *count >= CHALLENGES_PER_WINDOW {
+            return Err((StatusCode::TOO_MANY_REQUESTS, String::from("too many challenges for this key, try again later")));
+        }
+        *count += 1;
+    }
+
+    let key = public_key.clone();
+    let (challenge, expected) = tokio::task::spawn_blocking(move || {
+        possession::possession_challenge(&key, &Parameters::default())
+    }).await.unwrap().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut id = [0u8; CHALLENGE_ID_BYTES];
+    OsRng.fill_bytes(&mut id);
+    let id: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+    let mut pending = registry.pending.lock().unwrap();
+    pending.retain(|_, registration| registration.issued.elapsed() < CHALLENGE_LIFETIME);
+    while pending.len() >= registry.max_pending {
+        let oldest = pending.iter().min_by_key(|(_, registration)| registration.issued).map(|(id, _)| id.clone()).unwrap();
+        pending.remove(&oldest);
+    }
+    pending.insert(id.clone(), PendingRegistration { public_key, expected, issued: Instant::now() });
+    Ok(Json(RegisterChallengeResponse { id, challenge }))
+}
+
+pub async fn complete_handler(State(registry): State<KeyRegistry>, Json(payload): Json<RegisterCompleteRequest>) -> Result<Json<RegisterCompleteResponse>, (StatusCode, String)> {
+    // each challenge gets one answer, right or wrong
+    let registration = registry.pending.lock().unwrap()
+        .remove(&payload.id)
+        .filter(|registration| registration.issued.elapsed() < CHALLENGE_LIFETIME)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, String::from("unknown or expired challenge")))?;
+    if !possession::possession_verified(&registration.expected, &payload.response) {
+        return Err((StatusCode::UNAUTHORIZED, String::from("wrong response to the challenge")));
+    }
+
+    let fingerprint = fingerprint(&registration.public_key).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    registry.keys.lock().unwrap().insert(fingerprint.clone(), registration.public_key);
+    Ok(Json(RegisterCompleteResponse { fingerprint }))
+}
@@ -1,15 +1,45 @@
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
-use crate::api::handlers;
+use crate::api::{channel, handlers, prekeys, registry};
 use tower_http::trace::TraceLayer;
 
 pub fn app() -> Router {
+    router(channel::ChannelState::from_env())
+}
+
+/// The service with the given secure channel state
+pub fn router(channel_state: channel::ChannelState) -> Router {
+    let handshake = Router::new()
+        .route("/handshake", post(channel::handshake_handler))
+        .with_state(channel_state.clone());
+    let prekey_routes = Router::new()
+        .route("/prekeys/upload", post(prekeys::upload_handler))
+        .route("/prekeys/fetch", post(prekeys::fetch_handler))
+        .route("/prekeys/decrypt", post(prekeys::decrypt_handler))
+        .with_state(prekeys::PrekeyStore::new());
+    let registry_routes = Router::new()
+        .route("/keys/register/challenge", post(registry::challenge_handler))
+        .route("/keys/register/complete", post(registry::complete_handler))
+        .with_state(registry::KeyRegistry::new());
+
     Router::new()
         .route("/health", get(handlers::health_check))
         .route("/keygen", post(handlers::keygen_handler))
         .route("/encrypt", post(handlers::encrypt_handler))
         .route("/decrypt", post(handlers::decrypt_handler))
+        .route("/rerandomize", post(handlers::rerandomize_handler))
+        .route("/seal", post(handlers::seal_handler))
+        .route("/open", post(handlers::open_handler))
+        .route("/sign/keygen", post(handlers::sign_keygen_handler))
+        .route("/sign", post(handlers::sign_handler))
+        .route("/verify", post(handlers::verify_handler))
+        .route("/prekeys/encrypt", post(prekeys::encrypt_handler))
+        .merge(handshake)
+        .merge(prekey_routes)
+        .merge(registry_routes)
+        .layer(middleware::from_fn_with_state(channel_state, channel::secure_channel))
         .layer(TraceLayer::new_for_http())
 }
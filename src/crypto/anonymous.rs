@@ -0,0 +1,135 @@
+use crate::crypto::decrypt::decrypt;
+use crate::crypto::encrypt::encrypt;
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::keygen::public_key_from_coeffs;
+use crate::crypto::seal::NONCE_BYTES;
+use crate::crypto::utils::{Parameters, pad_coeffs, try_decompress};
+use crate::crypto::validate::validate_public_key;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use polynomial_ring::Polynomial;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Length of the tag that lets a candidate key be rejected without touching the body
+const TAG_BYTES: usize = 16;
+
+/// A message for one recipient that names no key: a ring-LWE encryption of the message key,
+/// whose coefficients look uniform mod q under any public key, and the encrypted body
+#[derive(Serialize, Deserialize)]
+struct AnonymousMessage {
+    /// c0 || c1 encrypting the 256 bits of the message key
+    kem: Vec<i64>,
+    tag: [u8; TAG_BYTES],
+    nonce: [u8; NONCE_BYTES],
+    body: Vec<u8>,
+}
+
+fn key_tag(message_key: &[u8], kem: &[i64]) -> [u8; TAG_BYTES] {
+    let kem_bytes = bincode::serialize(kem).expect("Failed to serialize data");
+    derive_bytes("ring-lwe anonymous tag", &[message_key, &kem_bytes])
+}
+
+fn body_key(message_key: &[u8], kem: &[i64]) -> [u8; KEY_BYTES] {
+    let kem_bytes = bincode::serialize(kem).expect("Failed to serialize data");
+    derive_bytes("ring-lwe anonymous body", &[message_key, &kem_bytes])
+}
+
+/// Encrypt a message without revealing which public key it is for
+/// # Arguments:
+/// * `pk` - recipient public key from `keygen_string`
+/// * `message` - message to encrypt
+/// * `params` - ring-LWE parameters with n >= 256
+/// # Returns:
+/// base64 encoded message with no recipient identifier, or an error for a malformed key
+/// # Example:
+/// ```
+/// use ring_lwe::anonymous::{anonymous_encrypt, trial_decrypt};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let alice = ring_lwe::keygen::keygen_string(&params, None);
+/// let bob = ring_lwe::keygen::keygen_string(&params, None);
+/// let ciphertext = anonymous_encrypt(bob.get("public").unwrap(), "hi bob", &params).unwrap();
+///
+/// let candidates = [alice.get("secret").unwrap().clone(), bob.get("secret").unwrap().clone()];
+/// assert_eq!(trial_decrypt(&candidates, &ciphertext, &params), Ok(Some((1, String::from("hi bob")))));
+/// ```
+pub fn anonymous_encrypt(pk: &str, message: &str, params: &Parameters) -> Result<String, String> {
+    let n = params.n;
+    if params.k > 1 || n < 8 * KEY_BYTES {
+        return Err(String::from("anonymous messages need ring-LWE parameters with n >= 256"));
+    }
+    validate_public_key(pk, params)?;
+    let pk = public_key_from_coeffs(&try_decompress(pk)?, params)?;
+
+    let mut message_key = [0u8; KEY_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut message_key);
+    OsRng.fill_bytes(&mut nonce);
+    let bits: Vec<i64> = message_key.iter().flat_map(|byte| (0..8).rev().map(move |i| ((byte >> i) & 1) as i64)).collect();
+    let ct = encrypt(&pk, &Polynomial::new(bits), params, None);
+    let mut kem = pad_coeffs(&ct[0], n);
+    kem.extend(pad_coeffs(&ct[1], n));
+
+    let body = Aes256Gcm::new(&body_key(&message_key, &kem).into())
+        .encrypt(Nonce::from_slice(&nonce), message.as_bytes())
+        .expect("Failed to encrypt message");
+    let envelope = AnonymousMessage { tag: key_tag(&message_key, &kem), kem, nonce, body };
+    Ok(general_purpose::STANDARD.encode(bincode::serialize(&envelope).expect("Failed to serialize data")))
+}
+
+/// Decrypt the message key with one candidate secret key, or None when the tag shows the
+/// message is not for this key
+fn try_key(sk: &Polynomial<i64>, envelope: &AnonymousMessage, params: &Parameters) -> Option<[u8; KEY_BYTES]> {
+    let n = params.n;
+    let ct = [Polynomial::new(envelope.kem[..n].to_vec()), Polynomial::new(envelope.kem[n..].to_vec())];
+    let bits = pad_coeffs(&decrypt(sk, &ct, params), n);
+    let mut message_key = [0u8; KEY_BYTES];
+    for (byte, chunk) in message_key.iter_mut().zip(bits.chunks(8)) {
+        *byte = chunk.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8);
+    }
+    (key_tag(&message_key, &envelope.kem) == envelope.tag).then_some(message_key)
+}
+
+/// Find which of several secret keys an anonymous message is for, and decrypt it
+/// # Arguments:
+/// * `secret_keys` - candidate secret keys from `keygen_string`
+/// * `ciphertext` - base64 message from `anonymous_encrypt`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (index of the matching key, message), None when no candidate matches, or an error for a
+/// malformed message or key
+///
+/// Each candidate costs one polynomial multiplication and a hash; the body is only decrypted
+/// for the key whose tag matches.
+pub fn trial_decrypt(secret_keys: &[String], ciphertext: &str, params: &Parameters) -> Result<Option<(usize, String)>, String> {
+    let bytes = general_purpose::STANDARD.decode(ciphertext.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    let envelope: AnonymousMessage = bincode::deserialize(&bytes).map_err(|_| String::from("malformed anonymous message"))?;
+    if envelope.kem.len() != 2 * params.n {
+        return Err(String::from("malformed anonymous message"));
+    }
+    for (index, sk) in secret_keys.iter().enumerate() {
+        let sk = Polynomial::new(try_decompress(sk)?);
+        let Some(message_key) = try_key(&sk, &envelope, params) else { continue };
+        let plaintext = Aes256Gcm::new(&body_key(&message_key, &envelope.kem).into())
+            .decrypt(Nonce::from_slice(&envelope.nonce), envelope.body.as_slice())
+            .map_err(|_| String::from("the message body is corrupted"))?;
+        let message = String::from_utf8(plaintext).map_err(|e| format!("message is not UTF-8: {}", e))?;
+        return Ok(Some((index, message)));
+    }
+    Ok(None)
+}
+
+/// Decrypt an anonymous message with a single secret key
+/// # Arguments:
+/// * `sk` - secret key from `keygen_string`
+/// * `ciphertext` - base64 message from `anonymous_encrypt`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the message, or an error when it is malformed or not for this key
+pub fn anonymous_decrypt(sk: &str, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+    trial_decrypt(&[sk.to_string()], ciphertext, params)?
+        .map(|(_, message)| message)
+        .ok_or_else(|| String::from("the message is not for this key"))
+}
@@ -0,0 +1,212 @@
+use crate::crypto::kdf::derive_bytes;
+use crate::crypto::utils::Parameters;
+use base64::{engine::general_purpose, Engine as _};
+
+/// Number of base64 characters per line of an armored payload
+const LINE_WIDTH: usize = 64;
+/// Length in bytes of key and ciphertext fingerprints
+const FINGERPRINT_BYTES: usize = 16;
+/// CRC-24 initial value and generator polynomial, as in OpenPGP armor
+const CRC24_INIT: u32 = 0xb704ce;
+const CRC24_POLY: u32 = 0x1864cfb;
+
+/// Object types an armored block can hold, named on its BEGIN and END lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmorKind {
+    PublicKey,
+    SecretKey,
+    EncryptedSecretKey,
+    Ciphertext,
+}
+
+impl ArmorKind {
+    const ALL: [ArmorKind; 4] = [ArmorKind::PublicKey, ArmorKind::SecretKey, ArmorKind::EncryptedSecretKey, ArmorKind::Ciphertext];
+
+    /// Label written after BEGIN and END
+    pub fn label(&self) -> &'static str {
+        match self {
+            ArmorKind::PublicKey => "RING-LWE PUBLIC KEY",
+            ArmorKind::SecretKey => "RING-LWE SECRET KEY",
+            ArmorKind::EncryptedSecretKey => "RING-LWE ENCRYPTED SECRET KEY",
+            ArmorKind::Ciphertext => "RING-LWE CIPHERTEXT",
+        }
+    }
+}
+
+/// Contents of an armored block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Armored {
+    pub kind: ArmorKind,
+    /// parameter set from the `Parameters` header
+    pub n: usize,
+    pub q: i64,
+    pub t: i64,
+    pub k: usize,
+    /// hex fingerprint of the payload, checked against the `Fingerprint` header when present
+    pub fingerprint: String,
+    /// the base64 string produced by `compress` or `keygen_string`
+    pub payload: String,
+}
+
+impl Armored {
+    /// Whether the block was produced under the given parameters
+    pub fn matches(&self, params: &Parameters) -> bool {
+        (self.n, self.q, self.t, self.k) == (params.n, params.q, params.t, params.k)
+    }
+
+    /// Build the parameter set named in the header
+    pub fn params(&self) -> Parameters {
+        Parameters::new(self.n, self.q, self.t).with_rank(self.k)
+    }
+}
+
+/// CRC-24 checksum of OpenPGP armor (RFC 4880, section 6.1)
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xffffff
+}
+
+/// Fingerprint of a base64 encoded key or ciphertext
+/// # Arguments:
+/// * `payload` - base64 string as produced by `keygen_string` or `encrypt_string`
+/// # Returns:
+/// 32 hex digits of SHAKE-256 over the decoded bytes, or an error for invalid base64
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// assert_eq!(ring_lwe::armor::fingerprint(keys.get("public").unwrap()).unwrap().len(), 32);
+/// assert!(ring_lwe::armor::fingerprint("not base64!").is_err());
+/// ```
+pub fn fingerprint(payload: &str) -> Result<String, String> {
+    let bytes = general_purpose::STANDARD.decode(payload).map_err(|e| format!("invalid base64: {}", e))?;
+    let digest: [u8; FINGERPRINT_BYTES] = derive_bytes("ring-lwe fingerprint", &[&bytes]);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Whether a string looks like an armored block rather than bare base64
+pub fn is_armored(text: &str) -> bool {
+    text.trim_start().starts_with("-----BEGIN ")
+}
+
+/// Wrap a key or ciphertext in ASCII armor
+/// # Arguments:
+/// * `kind` - object type named on the BEGIN and END lines
+/// * `payload` - base64 string as produced by `keygen_string` or `encrypt_string`
+/// * `params` - parameters the object was produced under
+/// # Returns:
+/// armored text with `Parameters` and `Fingerprint` headers, the payload wrapped at 64
+/// columns and a CRC-24 checksum line, or an error when the payload is not base64
+/// # Example:
+/// ```
+/// use ring_lwe::armor::{armor, dearmor, ArmorKind};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let armored = armor(ArmorKind::PublicKey, keys.get("public").unwrap(), &params).unwrap();
+/// assert!(armored.starts_with("-----BEGIN RING-LWE PUBLIC KEY-----"));
+/// assert_eq!(&dearmor(&armored).unwrap().payload, keys.get("public").unwrap());
+/// ```
+pub fn armor(kind: ArmorKind, payload: &str, params: &Parameters) -> Result<String, String> {
+    let bytes = general_purpose::STANDARD.decode(payload).map_err(|e| format!("invalid base64: {}", e))?;
+    let body = general_purpose::STANDARD.encode(&bytes);
+    let checksum = general_purpose::STANDARD.encode(&crc24(&bytes).to_be_bytes()[1..]);
+
+    let mut text = format!("-----BEGIN {}-----\n", kind.label());
+    text += &format!("Parameters: n={} q={} t={} k={}\n", params.n, params.q, params.t, params.k);
+    text += &format!("Fingerprint: {}\n\n", fingerprint(payload)?);
+    for line in body.as_bytes().chunks(LINE_WIDTH) {
+        text += std::str::from_utf8(line).unwrap();
+        text.push('\n');
+    }
+    text += &format!("={}\n-----END {}-----\n", checksum, kind.label());
+    Ok(text)
+}
+
+/// Parse the `Parameters` header, rejecting values the NTT cannot use
+fn parse_parameters(value: &str) -> Result<(usize, i64, i64, usize), String> {
+    let (mut n, mut q, mut t, mut k): (Option<usize>, Option<i64>, Option<i64>, Option<usize>) = (None, None, None, None);
+    for field in value.split_whitespace() {
+        let (name, number) = field.split_once('=').ok_or_else(|| format!("malformed parameter {:?}", field))?;
+        let bad = |_| format!("malformed parameter {:?}", field);
+        match name {
+            "n" => n = Some(number.parse().map_err(bad)?),
+            "q" => q = Some(number.parse().map_err(bad)?),
+            "t" => t = Some(number.parse().map_err(bad)?),
+            "k" => k = Some(number.parse().map_err(bad)?),
+            _ => return Err(format!("unknown parameter {:?}", name)),
+        }
+    }
+    let (Some(n), Some(q), Some(t), Some(k)) = (n, q, t, k) else {
+        return Err(String::from("Parameters header must give n, q, t and k"));
+    };
+    if !n.is_power_of_two() || q <= 2 * n as i64 || q % (2 * n as i64) != 1 || t < 2 || t >= q || k == 0 {
+        return Err(format!("unusable parameters n={} q={} t={} k={}", n, q, t, k));
+    }
+    Ok((n, q, t, k))
+}
+
+/// Unwrap an armored key or ciphertext
+/// # Arguments:
+/// * `text` - armored block, possibly surrounded by other text or with CRLF line endings
+/// # Returns:
+/// the object type, header fields and base64 payload, or an error when the block is
+/// malformed, its BEGIN and END lines disagree, or the checksum or fingerprint does not match
+pub fn dearmor(text: &str) -> Result<Armored, String> {
+    let mut lines = text.lines().map(str::trim).skip_while(|line| !line.starts_with("-----BEGIN "));
+    let begin = lines.next().ok_or_else(|| String::from("no BEGIN line"))?;
+    let label = begin.strip_prefix("-----BEGIN ").and_then(|l| l.strip_suffix("-----"))
+        .ok_or_else(|| format!("malformed BEGIN line {:?}", begin))?;
+    let kind = ArmorKind::ALL.into_iter().find(|kind| kind.label() == label)
+        .ok_or_else(|| format!("unknown armor type {:?}", label))?;
+
+    let (mut params, mut expected_fingerprint) = (None, None);
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(": ").ok_or_else(|| format!("malformed header {:?}", line))?;
+        match name {
+            "Parameters" => params = Some(parse_parameters(value)?),
+            "Fingerprint" => expected_fingerprint = Some(value.to_lowercase()),
+            // unknown headers are informational
+            _ => {}
+        }
+    }
+    let (n, q, t, k) = params.ok_or_else(|| String::from("missing Parameters header"))?;
+
+    let (mut body, mut checksum) = (String::new(), None);
+    let end = loop {
+        let line = lines.next().ok_or_else(|| String::from("no END line"))?;
+        if line.starts_with("-----END ") {
+            break line;
+        }
+        match line.strip_prefix('=') {
+            Some(crc) => checksum = Some(crc.to_string()),
+            None => body += line,
+        }
+    };
+    if end != format!("-----END {}-----", label) {
+        return Err(format!("END line {:?} does not match BEGIN line {:?}", end, begin));
+    }
+
+    let bytes = general_purpose::STANDARD.decode(&body).map_err(|e| format!("invalid base64: {}", e))?;
+    let checksum = checksum.ok_or_else(|| String::from("missing checksum"))?;
+    if checksum != general_purpose::STANDARD.encode(&crc24(&bytes).to_be_bytes()[1..]) {
+        return Err(String::from("checksum mismatch, the armored text is corrupted"));
+    }
+    let payload = general_purpose::STANDARD.encode(&bytes);
+    let fingerprint = fingerprint(&payload)?;
+    if expected_fingerprint.is_some_and(|expected| expected != fingerprint) {
+        return Err(String::from("fingerprint does not match the payload"));
+    }
+    Ok(Armored { kind, n, q, t, k, fingerprint, payload })
+}
@@ -0,0 +1,88 @@
+use crate::crypto::decrypt::bits_to_message;
+use crate::crypto::encrypt::encrypt;
+use crate::crypto::keygen::public_key_from_coeffs;
+use crate::crypto::utils::{Parameters, polyadd, gen_binary_poly, pad_coeffs, compress, try_decompress};
+use crate::crypto::validate::validate_public_key;
+use polynomial_ring::Polynomial;
+
+/// Random plaintext bits added to a ciphertext by `blind`, kept by the client to remove them
+/// from the decryption
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlindingMask {
+    bits: Vec<i64>,
+}
+
+/// Add an encryption of a random mask to a ciphertext, so a decryption service only sees the
+/// plaintext XOR the mask
+/// # Arguments:
+/// * `pk` - public key the ciphertext was encrypted to
+/// * `ciphertext` - ciphertext from `encrypt_string`
+/// * `params` - ring-LWE parameters with t = 2
+/// # Returns:
+/// (blinded ciphertext to send to `/decrypt`, mask for `unblind`), or an error for a malformed
+/// key or ciphertext
+///
+/// Each block gets a fresh uniformly random mask, so the blinded plaintext is a one-time pad of
+/// the message; the blinded ciphertext carries the noise of two encryptions. The blinded
+/// ciphertext minus the original is an encryption of the mask, so whoever decrypts the blinded
+/// ciphertext must never see the original.
+/// # Example:
+/// ```
+/// use ring_lwe::blind::{blind, unblind};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+/// let ciphertext = ring_lwe::encrypt::encrypt_string(pk, &String::from("hello"), &params, None);
+///
+/// let (blinded, mask) = blind(pk, &ciphertext, &params).unwrap();
+/// // what the decryption service computes and returns
+/// let masked = ring_lwe::decrypt::decrypt_string(sk, &blinded, &params);
+/// assert_ne!(masked, "hello");
+/// assert_eq!(unblind(&masked, &mask), Ok(String::from("hello")));
+/// ```
+pub fn blind(pk: &str, ciphertext: &str, params: &Parameters) -> Result<(String, BlindingMask), String> {
+    let (n, q, f) = (params.n, params.q, &params.f);
+    if params.k > 1 || params.t != 2 {
+        return Err(String::from("blind decryption needs ring-LWE parameters with t = 2"));
+    }
+    validate_public_key(pk, params)?;
+    let pk = public_key_from_coeffs(&try_decompress(pk)?, params)?;
+    let coeffs = try_decompress(ciphertext)?;
+    if coeffs.is_empty() || coeffs.len() % (2 * n) != 0 {
+        return Err(String::from("wrong length for a ciphertext"));
+    }
+
+    let mut blinded = Vec::with_capacity(coeffs.len());
+    let mut bits = Vec::with_capacity(coeffs.len() / 2);
+    for block in coeffs.chunks(2 * n) {
+        let mask = gen_binary_poly(n, None);
+        let masked = encrypt(&pk, &mask, params, None);
+        for (c, m) in [&block[..n], &block[n..]].into_iter().zip(&masked) {
+            blinded.extend(pad_coeffs(&polyadd(&Polynomial::new(c.to_vec()), m, q, f), n));
+        }
+        bits.extend(pad_coeffs(&mask, n));
+    }
+    Ok((compress(&blinded), BlindingMask { bits }))
+}
+
+/// Remove the mask from the decryption of a blinded ciphertext
+/// # Arguments:
+/// * `masked_message` - message `decrypt_string` or `/decrypt` returned for the blinded ciphertext
+/// * `mask` - mask from `blind`
+/// # Returns:
+/// the message, or an error when `masked_message` does not come from a ciphertext blinded with
+/// this mask
+pub fn unblind(masked_message: &str, mask: &BlindingMask) -> Result<String, String> {
+    // decrypt_string maps each byte to one char and drops trailing zero bytes
+    let mut bits: Vec<i64> = Vec::with_capacity(mask.bits.len());
+    for c in masked_message.chars() {
+        let byte = u8::try_from(c).map_err(|_| String::from("the masked message is not a blinded decryption"))?;
+        bits.extend((0..8).rev().map(|i| ((byte >> i) & 1) as i64));
+    }
+    if bits.len() > mask.bits.len() {
+        return Err(String::from("the masked message is longer than the mask"));
+    }
+    bits.resize(mask.bits.len(), 0);
+    let unmasked: Vec<i64> = bits.iter().zip(&mask.bits).map(|(b, m)| b ^ m).collect();
+    Ok(bits_to_message(&unmasked))
+}
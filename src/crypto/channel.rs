@@ -0,0 +1,208 @@
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::key_exchange::{initiate, respond, finish, InitiatorMessage, InitiatorState, ResponderMessage};
+use crate::crypto::seal::NONCE_BYTES;
+use crate::crypto::signature::{sign, verify, signature_from_string, signature_to_string, SignatureParameters, SigningKey, VerifyingKey};
+use crate::crypto::utils::Parameters;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// HTTP header carrying the session id of an encrypted request or response
+pub const SESSION_HEADER: &str = "x-ring-lwe-session";
+
+/// HTTP header carrying the sequence number of an encrypted request and of its response
+pub const SEQUENCE_HEADER: &str = "x-ring-lwe-sequence";
+
+const SESSION_ID_BYTES: usize = 16;
+
+/// First handshake message: an ephemeral key exchange message
+#[derive(Serialize, Deserialize)]
+struct ClientHello {
+    message: String,
+}
+
+/// Server answer: the session id, the key exchange reply and the server's signature over the
+/// whole transcript
+#[derive(Serialize, Deserialize)]
+struct ServerReply {
+    session: String,
+    message: String,
+    signature: String,
+}
+
+/// Which way a body travels; each direction is authenticated separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    fn label(self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Response => "response",
+        }
+    }
+}
+
+/// An established channel: a session id and the key both sides derived for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub id: String,
+    key: [u8; KEY_BYTES],
+}
+
+/// What the client keeps between sending its hello and receiving the server reply
+pub struct ClientHandshake {
+    state: InitiatorState,
+    hello: ClientHello,
+    server: VerifyingKey,
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    general_purpose::STANDARD.encode(bincode::serialize(value).expect("Failed to serialize data"))
+}
+
+fn decode<T: DeserializeOwned>(base64_str: &str, what: &str) -> Result<T, String> {
+    let bytes = general_purpose::STANDARD.decode(base64_str.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    bincode::deserialize(&bytes).map_err(|_| format!("malformed {}", what))
+}
+
+/// Bytes the server signs: the hello, the session id and the key exchange reply
+fn transcript(hello: &ClientHello, session: &str, message: &str) -> Vec<u8> {
+    bincode::serialize(&("ring-lwe channel", &hello.message, session, message)).expect("Failed to serialize data")
+}
+
+/// Mix the ephemeral key and the whole transcript into the session key
+fn session_key(ephemeral: &[u8], hello: &ClientHello, reply: &ServerReply) -> [u8; KEY_BYTES] {
+    derive_bytes("ring-lwe channel", &[ephemeral, hello.message.as_bytes(), reply.session.as_bytes(), reply.message.as_bytes()])
+}
+
+/// Start a handshake with a server whose verifying key the client already knows
+/// # Arguments:
+/// * `server` - the server's static verifying key, pinned by the client
+/// * `params` - ring-LWE parameters of the key exchange
+/// # Returns:
+/// (state for `ClientHandshake::finish`, base64 hello to send to the server)
+/// # Example:
+/// ```
+/// use ring_lwe::channel::{client_hello, server_accept, Direction};
+/// use ring_lwe::signature::{sign_keygen, SignatureParameters};
+/// let (params, sig_params) = (ring_lwe::utils::Parameters::default(), SignatureParameters::default());
+/// let (server_vk, server_sk) = sign_keygen(&sig_params, None);
+/// let (handshake, hello) = client_hello(&server_vk, &params);
+/// let (reply, server_session) = server_accept(&server_sk, &hello, &params, &sig_params).unwrap();
+/// let session = handshake.finish(&reply, &params, &sig_params).unwrap();
+///
+/// let body = session.seal(Direction::Request, "/encrypt", 0, b"{\"message\":\"hi\"}");
+/// assert_eq!(server_session.open(Direction::Request, "/encrypt", 0, &body), Ok(b"{\"message\":\"hi\"}".to_vec()));
+/// ```
+pub fn client_hello(server: &VerifyingKey, params: &Parameters) -> (ClientHandshake, String) {
+    let (state, first) = initiate(params, None);
+    let hello = ClientHello { message: first.to_base64(params) };
+    let encoded = encode(&hello);
+    (ClientHandshake { state, hello, server: server.clone() }, encoded)
+}
+
+/// Answer a client hello on the server
+/// # Arguments:
+/// * `identity` - the server's static signing key
+/// * `hello` - base64 hello from `client_hello`
+/// * `params` - ring-LWE parameters of the key exchange
+/// * `sig_params` - signature parameters of the server key
+/// # Returns:
+/// (base64 reply for the client, the new session), or an error for a malformed hello
+///
+/// The handshake runs the reconciliation key exchange rather than encapsulating to a static
+/// ring-LWE public key. The ring-LWE scheme is only secure against passive attackers, so a server
+/// decapsulating attacker-chosen ciphertexts under a long-term key would leak that key bit by bit
+/// through how it reacts. Here the key exchange secret is fresh for every handshake and the
+/// static key only signs, so crafted hellos probe nothing that outlives the handshake, and a
+/// later compromise of the static key does not open recorded sessions.
+pub fn server_accept(identity: &SigningKey, hello: &str, params: &Parameters, sig_params: &SignatureParameters) -> Result<(String, Session), String> {
+    let hello: ClientHello = decode(hello, "handshake hello")?;
+    let first = InitiatorMessage::from_base64(&hello.message, params)?;
+    let (message, ephemeral) = respond(&first, params, None);
+
+    let mut id = [0u8; SESSION_ID_BYTES];
+    OsRng.fill_bytes(&mut id);
+    let session: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+    let message = message.to_base64(params);
+    let signature = sign(identity, &transcript(&hello, &session, &message), sig_params);
+    let reply = ServerReply { session, message, signature: signature_to_string(&signature, sig_params) };
+    let key = session_key(&ephemeral, &hello, &reply);
+    Ok((encode(&reply), Session { id: reply.session.clone(), key }))
+}
+
+impl ClientHandshake {
+    /// Complete the handshake with the server reply
+    /// # Arguments:
+    /// * `reply` - base64 reply from `server_accept`
+    /// * `params` - ring-LWE parameters of the key exchange
+    /// * `sig_params` - signature parameters of the server key
+    /// # Returns:
+    /// the session, or an error for a malformed reply or one not signed by the pinned key
+    pub fn finish(self, reply: &str, params: &Parameters, sig_params: &SignatureParameters) -> Result<Session, String> {
+        let reply: ServerReply = decode(reply, "handshake reply")?;
+        let signature = signature_from_string(&reply.signature, sig_params)?;
+        if !verify(&self.server, &transcript(&self.hello, &reply.session, &reply.message), &signature, sig_params) {
+            return Err(String::from("the handshake reply is not signed by the server key"));
+        }
+        let message = ResponderMessage::from_base64(&reply.message, params)?;
+        let ephemeral = finish(self.state, &message, params);
+        let key = session_key(&ephemeral, &self.hello, &reply);
+        Ok(Session { id: reply.session, key })
+    }
+}
+
+impl Session {
+    /// Associated data of a body: the session, the direction, the request path and the sequence
+    /// number, so a body cannot be replayed the other way, against another endpoint or as the
+    /// answer to a different request
+    fn aad(&self, direction: Direction, path: &str, sequence: u64) -> Vec<u8> {
+        bincode::serialize(&(&self.id, direction.label(), path, sequence)).expect("Failed to serialize data")
+    }
+
+    /// Encrypt a request or response body for this session
+    /// # Arguments:
+    /// * `direction` - `Request` on the client, `Response` on the server
+    /// * `path` - path of the request the body belongs to
+    /// * `sequence` - number of the request; the client counts up from 0 and the response
+    ///   reuses the number of the request it answers
+    /// * `body` - plaintext body
+    /// # Returns:
+    /// base64 encoded nonce and AES-256-GCM ciphertext
+    pub fn seal(&self, direction: Direction, path: &str, sequence: u64, body: &[u8]) -> String {
+        let mut nonce = [0u8; NONCE_BYTES];
+        OsRng.fill_bytes(&mut nonce);
+        let aad = self.aad(direction, path, sequence);
+        let ciphertext = Aes256Gcm::new(&self.key.into())
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: body, aad: &aad })
+            .expect("Failed to encrypt message");
+        general_purpose::STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypt a body sealed with `seal` by the other side
+    /// # Arguments:
+    /// * `direction` - `Request` on the server, `Response` on the client
+    /// * `path` - path of the request the body belongs to
+    /// * `sequence` - number of the request the body belongs to
+    /// * `body` - base64 body from `seal`
+    /// # Returns:
+    /// the plaintext body, or an error when it is malformed or fails authentication
+    pub fn open(&self, direction: Direction, path: &str, sequence: u64, body: &str) -> Result<Vec<u8>, String> {
+        let bytes = general_purpose::STANDARD.decode(body.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+        if bytes.len() < NONCE_BYTES {
+            return Err(String::from("encrypted body is too short"));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_BYTES);
+        let aad = self.aad(direction, path, sequence);
+        Aes256Gcm::new(&self.key.into())
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| String::from("the body does not decrypt under this session"))
+    }
+}
@@ -1,4 +1,5 @@
-use crate::crypto::utils::{Parameters, polymul_fast, polyadd, nearest_int, decompress};
+use crate::crypto::utils::{Parameters, mod_coeffs, polymul_fast, polyadd, polysub, nearest_int, decompress};
+use crate::crypto::module_lwe::module_decrypt_string;
 use polynomial_ring::Polynomial;
 
 /// Decrypt a ciphertext using the secret key
@@ -32,6 +33,42 @@ pub fn decrypt(
     Polynomial::new(decrypted_coeffs)
 }
 
+/// Decrypt a ciphertext and measure the noise it carries
+/// # Arguments:
+/// * `sk` - secret key
+/// * `ct` - array of ciphertext polynomials
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (decrypted polynomial, infinity norm of the noise term)
+///
+/// The noise is measured against the decrypted plaintext, so it is only meaningful while it
+/// stays below `params.noise_bound()`; beyond that the ciphertext has already failed. Panics
+/// for module rank k > 1, use `module_decrypt_with_noise`.
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let (pk, sk) = ring_lwe::keygen::keygen(&params, None);
+/// let m = polynomial_ring::Polynomial::new(vec![1, 0, 1]);
+/// let ct = ring_lwe::encrypt::encrypt(&pk, &m, &params, None);
+/// let (decrypted_m, noise) = ring_lwe::decrypt::decrypt_with_noise(&sk, &ct, &params);
+/// assert_eq!(decrypted_m, m);
+/// assert!(noise <= params.noise_bound());
+/// ```
+pub fn decrypt_with_noise(
+    sk: &Polynomial<i64>,    // Secret key
+    ct: &[Polynomial<i64>; 2],        // Array of ciphertext polynomials
+    params: &Parameters
+) -> (Polynomial<i64>, i64) {
+    assert_eq!(params.k, 1, "ring-LWE ciphertexts need k = 1, use module_decrypt_with_noise for module-LWE");
+    let (q,t,f,omega) = (params.q, params.t, &params.f, params.omega);
+    let m = decrypt(sk, ct, params);
+    let scaled_pt = polyadd(&polymul_fast(&ct[1], sk, q, f, omega),&ct[0], q, f);
+    // remove the scaled plaintext floor(m*q/t) to isolate the noise term
+    let noise = polysub(&scaled_pt, &mod_coeffs(&m * q / t, q), q, f);
+    let noise_norm = noise.coeffs().iter().map(|c| c.abs()).max().unwrap_or(0);
+    (m, noise_norm)
+}
+
 /// Decrypt a ciphertext string using the secret key
 /// # Arguments:
 /// * `sk_string` - secret key as a base64 encoded string
@@ -50,6 +87,10 @@ pub fn decrypt(
 /// let decrypted_message = ring_lwe::decrypt::decrypt_string(sk_string, &ciphertext_string, &params);
 /// ```
 pub fn decrypt_string(sk_base64: &String, ciphertext_base64: &String, params: &Parameters) -> String {
+    if params.k > 1 {
+        return module_decrypt_string(sk_base64, ciphertext_base64, params);
+    }
+
     // Decode the base64 secret key string and deserialize into a vector of i64 coefficients
     let sk = Polynomial::new(decompress(sk_base64));
 
@@ -70,8 +111,16 @@ pub fn decrypt_string(sk_base64: &String, ciphertext_base64: &String, params: &P
         decrypted_bits.extend(coeffs);
     }
 
-    // Convert decrypted bits into a string
-    let decrypted_message: String = decrypted_bits
+    bits_to_message(&decrypted_bits)
+}
+
+/// Convert decrypted bits back into a string
+/// # Arguments:
+/// * `bits` - decrypted bits, each byte MSB first
+/// # Returns:
+/// message with the zero padding of the last block removed
+pub(crate) fn bits_to_message(bits: &[i64]) -> String {
+    let decrypted_message: String = bits
         .chunks(8)
         .map(|byte| {
             let bit_str: String = byte.iter().map(|&b| (b as u8 + b'0') as char).collect();
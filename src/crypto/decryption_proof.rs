@@ -0,0 +1,367 @@
+use crate::crypto::decrypt::decrypt;
+use crate::crypto::encrypt::message_blocks;
+use crate::crypto::kdf::derive_bytes;
+use crate::crypto::keygen::public_key_from_coeffs;
+use crate::crypto::security::DEFAULT_MIN_SECURITY_BITS;
+use crate::crypto::signature::{inf_norm, CHALLENGE_BYTES};
+use crate::crypto::utils::{Parameters, mod_coeffs, polymul_fast, polyadd, polysub, polyinv, pad_coeffs, try_decompress};
+use base64::{engine::general_purpose, Engine as _};
+use polynomial_ring::Polynomial;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Uniform};
+use serde::{Deserialize, Serialize};
+
+/// Number of parallel repetitions, each with a one-bit challenge, for a soundness error of 2^-128
+const REPETITIONS: usize = 128;
+
+/// Masks are uniform in [-GAMMA_FACTOR*REPETITIONS*n*beta, GAMMA_FACTOR*REPETITIONS*n*beta] for
+/// a secret of norm beta, so each of s, e and d lets a proof through with probability about
+/// exp(-1/GAMMA_FACTOR)
+const GAMMA_FACTOR: i64 = 4;
+
+/// Standard deviations of decryption noise a provable ciphertext may carry; honest
+/// ciphertexts exceed it with probability below 2^-140
+const TAIL_SIGMAS: f64 = 14.0;
+
+/// Responses of one repetition: the masked s and e, and the masked decryption noise of every block
+#[derive(Serialize, Deserialize)]
+struct Response {
+    z_s: Vec<i32>,
+    z_e: Vec<i32>,
+    z_d: Vec<Vec<i32>>,
+}
+
+/// The challenge hash, whose first REPETITIONS bits are the challenges, and the responses
+#[derive(Serialize, Deserialize)]
+struct Proof {
+    challenge: [u8; CHALLENGE_BYTES],
+    responses: Vec<Response>,
+}
+
+/// Bounds of one parameter set
+struct Bounds {
+    /// largest decryption noise a ciphertext may carry to be provable
+    noise: i64,
+    /// mask range for s and e
+    gamma_key: i64,
+    /// mask range for the decryption noise
+    gamma_noise: i64,
+}
+
+impl Bounds {
+    /// Bounds for these parameters, or an error when they fall below `min_security_bits` or the
+    /// noise extracted from two accepting proofs would no longer fit in the decryption margin
+    /// and so would not pin down the plaintext
+    fn new(params: &Parameters, min_security_bits: f64) -> Result<Self, String> {
+        if params.k > 1 {
+            return Err(String::from("decryption proofs support ring-LWE keys only"));
+        }
+        params.check_security(min_security_bits)?;
+        let n = params.n as i64;
+        let noise = (TAIL_SIGMAS * params.noise_variance().sqrt()).ceil() as i64;
+        let bounds = Bounds {
+            noise,
+            gamma_key: GAMMA_FACTOR * REPETITIONS as i64 * n,
+            gamma_noise: GAMMA_FACTOR * REPETITIONS as i64 * n * noise,
+        };
+        if bounds.gamma_noise > i32::MAX as i64 || 2 * bounds.noise_bound() > params.noise_bound() {
+            return Err(format!(
+                "q = {} is too small for decryption proofs; the extracted noise needs q > {}",
+                params.q, 2 * params.t * (2 * bounds.noise_bound() + 1)
+            ));
+        }
+        Ok(bounds)
+    }
+
+    /// Largest coefficient of an accepted z_s or z_e
+    fn key_bound(&self) -> i64 {
+        self.gamma_key - 1
+    }
+
+    /// Largest coefficient of an accepted z_d
+    fn noise_bound(&self) -> i64 {
+        self.gamma_noise - self.noise
+    }
+}
+
+/// The public statement of one block: the block (c0, c1) and floor(m*q/t)
+struct Statement {
+    c0: Polynomial<i64>,
+    c1: Polynomial<i64>,
+    scaled_m: Polynomial<i64>,
+}
+
+/// Hash the public key, ciphertext, claimed plaintext and the commitments w1 = a*y_s + y_e and
+/// w2 = c1*y_s - y_d of every repetition and block
+fn challenge(
+    public_key: &str,
+    ciphertext: &str,
+    statements: &[Statement],
+    w1: &[Polynomial<i64>],
+    w2: &[Vec<Polynomial<i64>>],
+    n: usize,
+) -> [u8; CHALLENGE_BYTES] {
+    let scaled_m: Vec<Vec<i64>> = statements.iter().map(|statement| pad_coeffs(&statement.scaled_m, n)).collect();
+    let w1: Vec<Vec<i64>> = w1.iter().map(|w| pad_coeffs(w, n)).collect();
+    let w2: Vec<Vec<Vec<i64>>> = w2.iter().map(|ws| ws.iter().map(|w| pad_coeffs(w, n)).collect()).collect();
+    let bytes = bincode::serialize(&(scaled_m, w1, w2)).expect("Failed to serialize data");
+    derive_bytes("ring-lwe decryption proof", &[public_key.as_bytes(), ciphertext.as_bytes(), &bytes])
+}
+
+/// The one-bit challenge of a repetition
+fn challenge_bit(challenge: &[u8; CHALLENGE_BYTES], repetition: usize) -> bool {
+    (challenge[repetition / 8] >> (repetition % 8)) & 1 == 1
+}
+
+/// Split the ciphertext into per-block statements for the claimed plaintext bits
+fn statements(ciphertext: &str, blocks: Vec<Polynomial<i64>>, params: &Parameters) -> Result<Vec<Statement>, String> {
+    let (n, q, t) = (params.n, params.q, params.t);
+    let coeffs = try_decompress(ciphertext)?;
+    if coeffs.is_empty() || coeffs.len() % (2 * n) != 0 {
+        return Err(String::from("wrong length for a ciphertext"));
+    }
+    let num_blocks = coeffs.len() / (2 * n);
+    if blocks.len() > num_blocks {
+        return Err(String::from("the message is longer than the ciphertext"));
+    }
+    let mut blocks = blocks.into_iter();
+    Ok(coeffs.chunks(2 * n).map(|block| {
+        let m = blocks.next().unwrap_or_else(|| Polynomial::new(vec![]));
+        Statement {
+            c0: Polynomial::new(block[..n].to_vec()),
+            c1: Polynomial::new(block[n..].to_vec()),
+            scaled_m: mod_coeffs(&m * q / t, q),
+        }
+    }).collect())
+}
+
+fn parse_public_key(public_key: &str, params: &Parameters) -> Result<[Polynomial<i64>; 2], String> {
+    public_key_from_coeffs(&try_decompress(public_key)?, params)
+}
+
+/// The secret s, the key noise e = -(b + a*s) and the public a, or an error when the keys do
+/// not match
+fn open_keys(sk: &str, pk: &str, params: &Parameters) -> Result<[Polynomial<i64>; 3], String> {
+    let (q, f, omega) = (params.q, &params.f, params.omega);
+    let s = Polynomial::new(try_decompress(sk)?);
+    let pk_polys = parse_public_key(pk, params)?;
+    let e = polyinv(&polyadd(&pk_polys[0], &polymul_fast(&pk_polys[1], &s, q, f, omega), q, f), q);
+    if inf_norm(&s) > 1 || inf_norm(&e) > 1 {
+        return Err(String::from("the secret key does not match the public key"));
+    }
+    let [_, a] = pk_polys;
+    Ok([s, e, a])
+}
+
+/// The noise d = c0 + c1*s - floor(m*q/t) of every block
+fn decryption_noise(s: &Polynomial<i64>, statements: &[Statement], params: &Parameters) -> Vec<Polynomial<i64>> {
+    let (q, f, omega) = (params.q, &params.f, params.omega);
+    statements.iter().map(|statement| {
+        polysub(&polyadd(&statement.c0, &polymul_fast(&statement.c1, s, q, f, omega), q, f), &statement.scaled_m, q, f)
+    }).collect()
+}
+
+/// One run of the prover: the proof, and whether every response is independent of s, e and d
+/// so that it may be released
+#[allow(clippy::too_many_arguments)]
+fn attempt(
+    public_key: &str,
+    ciphertext: &str,
+    [s, e, a]: &[Polynomial<i64>; 3],
+    statements: &[Statement],
+    d: &[Polynomial<i64>],
+    bounds: &Bounds,
+    params: &Parameters,
+    rng: &mut StdRng,
+) -> (Proof, bool) {
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let key_mask = Uniform::new_inclusive(-bounds.gamma_key, bounds.gamma_key);
+    let noise_mask = Uniform::new_inclusive(-bounds.gamma_noise, bounds.gamma_noise);
+    let mut sample = |mask: &Uniform<i64>| Polynomial::new((0..n).map(|_| mask.sample(rng)).collect());
+
+    let (mut y_s, mut y_e, mut y_d, mut w1, mut w2) = (vec![], vec![], vec![], vec![], vec![]);
+    for _ in 0..REPETITIONS {
+        let (ys, ye) = (sample(&key_mask), sample(&key_mask));
+        let yd: Vec<Polynomial<i64>> = statements.iter().map(|_| sample(&noise_mask)).collect();
+        w1.push(polyadd(&polymul_fast(a, &ys, q, f, omega), &ye, q, f));
+        w2.push(statements.iter().zip(&yd).map(|(statement, yd)| polysub(&polymul_fast(&statement.c1, &ys, q, f, omega), yd, q, f)).collect());
+        y_s.push(ys);
+        y_e.push(ye);
+        y_d.push(yd);
+    }
+    let challenge = challenge(public_key, ciphertext, statements, &w1, &w2, n);
+
+    let mut accepted = true;
+    let mut respond = |y: &Polynomial<i64>, secret: &Polynomial<i64>, c: bool, bound: i64| {
+        let z = if c { polyadd(y, secret, q, f) } else { y.clone() };
+        // reject unless the response is independent of the secret
+        accepted &= inf_norm(&z) <= bound;
+        pad_coeffs(&z, n).into_iter().map(|coeff| coeff as i32).collect()
+    };
+    let responses = (0..REPETITIONS).map(|r| {
+        let c = challenge_bit(&challenge, r);
+        Response {
+            z_s: respond(&y_s[r], s, c, bounds.key_bound()),
+            z_e: respond(&y_e[r], e, c, bounds.key_bound()),
+            z_d: y_d[r].iter().zip(d).map(|(yd, d)| respond(yd, d, c, bounds.noise_bound())).collect(),
+        }
+    }).collect();
+    (Proof { challenge, responses }, accepted)
+}
+
+fn encode(proof: &Proof) -> String {
+    general_purpose::STANDARD.encode(bincode::serialize(proof).expect("Failed to serialize data"))
+}
+
+/// Prove that a ciphertext decrypts to the message `decrypt_string` returns for it, without
+/// revealing the secret key
+/// # Arguments:
+/// * `sk` - secret key from `keygen_string`
+/// * `pk` - the matching public key, which commits to the secret key
+/// * `ciphertext` - ciphertext from `encrypt_string`
+/// * `params` - ring-LWE parameters of at least `DEFAULT_MIN_SECURITY_BITS` whose q leaves room
+///   for the masked noise, see below
+/// # Returns:
+/// base64 encoded proof of about 2 MB per ciphertext block, or an error when the keys do not
+/// match, the ciphertext is malformed or too noisy, the parameters fall below 128 bits of
+/// security, or q is too small
+///
+/// No parameter set this crate computes with passes both checks, so for now every call returns
+/// an error. The masks have to grow with n, and at n = 2048, the smallest ring degree that keeps
+/// a large q above 128 bits, the extracted noise needs q above 5 * 10^9, while the arithmetic
+/// here and the 32-bit proof encoding need q below 2^31. n = 1024 with q = 2147473409 fits the
+/// noise but estimates at only about 75 bits, and such a key is not worth proving anything
+/// about. Keys have to be generated under a separate parameter set of at least 128 bits with a
+/// modulus large enough for proofs, and mail to be proven later encrypted under it; keys made
+/// with the default parameters can never be proven.
+///
+/// The proof is a Fiat-Shamir with aborts proof of knowledge of short s, e and d with
+/// a*s + e = -b and c0 + c1*s = floor(m*q/t) + d for every block, the same relations that keygen
+/// and decryption rely on, run as 128 parallel repetitions with one-bit challenges. Two accepting
+/// transcripts that differ in one challenge bit subtract to short s*, e* and d* satisfying both
+/// relations with |d*| <= 2*(largest accepted z_d). That is below q/(2t), so s* decrypts the
+/// block to m, and s* is the key behind pk unless someone found a short M-SIS solution for a.
+///
+/// Larger challenge sets would need challenge differences c' with c'*floor(m*q/t) never short
+/// mod q, which fails for t = 2: 2*floor(q/2) = -1 mod q, so a difference with an even
+/// coefficient sum lets the bitwise complement of m through. One-bit challenges differ by +-1.
+/// The masks on d are about 4*128*n times larger than the noise and their difference still has
+/// to stay within q/(2t), so proofs need q of about 2^31 for n = 1024; the default q = 12289 is
+/// refused. A proof carries 128*(2 + blocks) polynomials of 4*n bytes, about 2 MB of base64 for
+/// a single block at n = 1024 and twice that at n = 2048.
+/// # Example:
+/// ```
+/// use ring_lwe::decryption_proof::prove_decryption;
+/// // large enough q for the noise, but about 75 bits of security
+/// let params = ring_lwe::utils::Parameters::new(1024, 2147473409, 2);
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+/// let ciphertext = ring_lwe::encrypt::encrypt_string(pk, &String::from("hello"), &params, None);
+/// assert!(prove_decryption(sk, pk, &ciphertext, &params).is_err());
+/// ```
+pub fn prove_decryption(sk: &str, pk: &str, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+    prove(sk, pk, ciphertext, params, DEFAULT_MIN_SECURITY_BITS)
+}
+
+/// `prove_decryption` without the security floor, for testing the proof itself at the
+/// parameters this crate can compute with
+#[cfg(test)]
+pub(crate) fn prove_decryption_unchecked(sk: &str, pk: &str, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+    prove(sk, pk, ciphertext, params, 0.0)
+}
+
+fn prove(sk: &str, pk: &str, ciphertext: &str, params: &Parameters, min_security_bits: f64) -> Result<String, String> {
+    let bounds = Bounds::new(params, min_security_bits)?;
+    let keys = open_keys(sk, pk, params)?;
+    let s = &keys[0];
+
+    // the claimed plaintext is whatever the secret key decrypts each block to
+    let blocks = statements(ciphertext, vec![], params)?.iter()
+        .map(|statement| decrypt(s, &[statement.c0.clone(), statement.c1.clone()], params))
+        .collect();
+    let statements = statements(ciphertext, blocks, params)?;
+    let d = decryption_noise(s, &statements, params);
+    if d.iter().any(|d| inf_norm(d) > bounds.noise) {
+        return Err(String::from("the ciphertext is too noisy to prove its decryption"));
+    }
+
+    let mut rng = StdRng::from_entropy();
+    loop {
+        let (proof, accepted) = attempt(pk, ciphertext, &keys, &statements, &d, &bounds, params, &mut rng);
+        if accepted {
+            return Ok(encode(&proof));
+        }
+    }
+}
+
+/// What a key holder who claims another plaintext gets from running the prover anyway: the
+/// noise is not checked and the responses are released even where they should be rejected
+#[cfg(test)]
+pub(crate) fn prove_claim(sk: &str, pk: &str, ciphertext: &str, message: &str, params: &Parameters) -> Result<String, String> {
+    let bounds = Bounds::new(params, 0.0)?;
+    let keys = open_keys(sk, pk, params)?;
+    let statements = statements(ciphertext, message_blocks(message, params.n), params)?;
+    let d = decryption_noise(&keys[0], &statements, params);
+    let (proof, _) = attempt(pk, ciphertext, &keys, &statements, &d, &bounds, params, &mut StdRng::from_entropy());
+    Ok(encode(&proof))
+}
+
+/// Check a proof from `prove_decryption`
+/// # Arguments:
+/// * `pk` - public key the ciphertext was encrypted to
+/// * `ciphertext` - ciphertext from `encrypt_string`
+/// * `message` - claimed plaintext
+/// * `proof` - base64 proof from `prove_decryption`
+/// * `params` - ring-LWE parameters of at least `DEFAULT_MIN_SECURITY_BITS`
+/// # Returns:
+/// whether the proof shows that the secret key behind `pk` decrypts `ciphertext` to `message`;
+/// always false below 128 bits of security, where a proof says nothing about who holds the key
+pub fn verify_decryption(pk: &str, ciphertext: &str, message: &str, proof: &str, params: &Parameters) -> bool {
+    verify(pk, ciphertext, message, proof, params, DEFAULT_MIN_SECURITY_BITS)
+}
+
+/// `verify_decryption` without the security floor, see `prove_decryption_unchecked`
+#[cfg(test)]
+pub(crate) fn verify_decryption_unchecked(pk: &str, ciphertext: &str, message: &str, proof: &str, params: &Parameters) -> bool {
+    verify(pk, ciphertext, message, proof, params, 0.0)
+}
+
+fn verify(pk: &str, ciphertext: &str, message: &str, proof: &str, params: &Parameters, min_security_bits: f64) -> bool {
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let Ok(bounds) = Bounds::new(params, min_security_bits) else { return false };
+    let Ok([b, a]) = parse_public_key(pk, params) else { return false };
+    let Ok(statements) = statements(ciphertext, message_blocks(message, n), params) else { return false };
+    let proof: Proof = match general_purpose::STANDARD.decode(proof.trim()).ok().and_then(|bytes| bincode::deserialize(&bytes).ok()) {
+        Some(proof) => proof,
+        None => return false,
+    };
+    if proof.responses.len() != REPETITIONS {
+        return false;
+    }
+    let short = |z: &Vec<i32>, bound: i64| -> Option<Polynomial<i64>> {
+        let z = (z.len() <= n).then(|| Polynomial::new(z.iter().map(|&coeff| coeff as i64).collect()))?;
+        (inf_norm(&z) <= bound).then_some(z)
+    };
+
+    let (mut w1, mut w2) = (vec![], vec![]);
+    for (r, response) in proof.responses.iter().enumerate() {
+        let c = challenge_bit(&proof.challenge, r);
+        let (Some(z_s), Some(z_e)) = (short(&response.z_s, bounds.key_bound()), short(&response.z_e, bounds.key_bound())) else { return false };
+        if response.z_d.len() != statements.len() {
+            return false;
+        }
+        // w1 = a*z_s + z_e + c*b
+        let a_z = polyadd(&polymul_fast(&a, &z_s, q, f, omega), &z_e, q, f);
+        w1.push(if c { polyadd(&a_z, &b, q, f) } else { a_z });
+        let mut block_w2 = Vec::with_capacity(statements.len());
+        for (statement, z_d) in statements.iter().zip(&response.z_d) {
+            let Some(z_d) = short(z_d, bounds.noise_bound()) else { return false };
+            // w2 = c1*z_s - z_d - c*(floor(m*q/t) - c0)
+            let w = polysub(&polymul_fast(&statement.c1, &z_s, q, f, omega), &z_d, q, f);
+            block_w2.push(if c { polysub(&w, &polysub(&statement.scaled_m, &statement.c0, q, f), q, f) } else { w });
+        }
+        w2.push(block_w2);
+    }
+    challenge(pk, ciphertext, &statements, &w1, &w2, n) == proof.challenge
+}
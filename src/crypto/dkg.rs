@@ -0,0 +1,312 @@
+use crate::crypto::kdf::{derive_bytes, sub_seed, KEY_BYTES};
+use crate::crypto::keygen::expand_a;
+use crate::crypto::security::DEFAULT_MIN_SECURITY_BITS;
+use crate::crypto::threshold::{share_secret_key, smudging_bits, KeyShare, MIN_SMUDGING_BITS};
+use crate::crypto::utils::{Parameters, polymul_fast, polyadd, polyinv, gen_ternary_poly, gen_seed, pad_coeffs, compress, seed_to_words, SEED_BYTES};
+use polynomial_ring::Polynomial;
+use std::collections::{BTreeMap, VecDeque};
+
+/// A protocol message; everything but `Deal` goes to all parties
+#[derive(Debug, Clone, PartialEq)]
+pub enum DkgMessage {
+    /// round 1: a hash of a seed contribution, so no party can pick its contribution after
+    /// seeing the others and steer the common `a`
+    SeedCommitment { from: i64, digest: [u8; KEY_BYTES] },
+    /// round 2: a contribution to the seed of the common `a`
+    Seed { from: i64, seed: [u8; SEED_BYTES] },
+    /// round 3: a hash of b_i, so no party can pick its b_i after seeing the others
+    Commitment { from: i64, digest: [u8; KEY_BYTES] },
+    /// round 4: b_i = -a*s_i - e_i
+    Reveal { from: i64, b: Vec<i64> },
+    /// round 4: a Shamir share of s_i for one party; must travel over a confidential channel
+    Deal { from: i64, to: i64, share: Vec<i64> },
+}
+
+impl DkgMessage {
+    /// Recipient of a point-to-point message, or None for a broadcast
+    pub fn recipient(&self) -> Option<i64> {
+        match self {
+            DkgMessage::Deal { to, .. } => Some(*to),
+            _ => None,
+        }
+    }
+}
+
+/// What one party ends up with
+#[derive(Debug, Clone, PartialEq)]
+pub struct DkgOutput {
+    /// joint public key in the format of `keygen_string`
+    pub public_key: String,
+    /// this party's Shamir share of the joint secret key, for the `threshold` module
+    pub share: KeyShare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    SeedCommitments,
+    Seeds,
+    Commitments,
+    Reveals,
+    Done,
+}
+
+/// One party of the distributed key generation
+///
+/// Each party samples its own s_i and e_i. The joint secret s = sum s_i is never assembled;
+/// each party only learns its Shamir share of it.
+pub struct DkgParty {
+    index: i64,
+    parties: usize,
+    threshold: usize,
+    phase: Phase,
+    seed: Option<u64>,
+    s: Polynomial<i64>,
+    e: Polynomial<i64>,
+    seed_a: Option<[u8; SEED_BYTES]>,
+    seed_commitments: BTreeMap<i64, [u8; KEY_BYTES]>,
+    seeds: BTreeMap<i64, [u8; SEED_BYTES]>,
+    commitments: BTreeMap<i64, [u8; KEY_BYTES]>,
+    reveals: BTreeMap<i64, Vec<i64>>,
+    deals: BTreeMap<i64, Vec<i64>>,
+    output: Option<DkgOutput>,
+}
+
+fn commitment(from: i64, b: &[i64]) -> [u8; KEY_BYTES] {
+    let bytes = bincode::serialize(&(from, b)).expect("Failed to serialize data");
+    derive_bytes("ring-lwe dkg commitment", &[&bytes])
+}
+
+fn seed_commitment(from: i64, seed: &[u8; SEED_BYTES]) -> [u8; KEY_BYTES] {
+    derive_bytes("ring-lwe dkg seed commitment", &[&from.to_le_bytes(), seed])
+}
+
+impl DkgParty {
+    /// Create party `index` of `parties`, any `threshold` of which can later decrypt together
+    /// # Arguments:
+    /// * `index` - this party's index, from 1 to `parties`
+    /// * `parties` - number of parties
+    /// * `threshold` - number of shares needed to decrypt
+    /// * `params` - ring-LWE parameters reaching `DEFAULT_MIN_SECURITY_BITS`
+    /// * `seed` - random seed
+    /// # Returns:
+    /// the party, or an error for an impossible index or threshold, for parameters below 128
+    /// bits of security, or for so many parties that the joint key's noise leaves no room to
+    /// decrypt with `threshold` shares
+    ///
+    /// The joint key sums one secret and error per party, so its decryption noise has `parties`
+    /// times the variance of a single key's; the security and smudging checks of
+    /// `threshold::partial_decrypt` are applied here up front, before any party does work.
+    /// # Example:
+    /// ```
+    /// use ring_lwe::dkg::DkgParty;
+    /// use ring_lwe::utils::Parameters;
+    /// // about 192 bits of classical security, with room for smudging noise
+    /// let params = Parameters::with_min_security(2048, 2147389441, 2, 128.0).unwrap();
+    /// assert!(DkgParty::new(1, 3, 2, &params, None).is_ok());
+    /// // the same q at n = 1024 estimates at about 75 bits
+    /// assert!(DkgParty::new(1, 3, 2, &Parameters::new(1024, 2147473409, 2), None).is_err());
+    /// ```
+    pub fn new(index: i64, parties: usize, threshold: usize, params: &Parameters, seed: Option<u64>) -> Result<Self, String> {
+        if index < 1 || index > parties as i64 {
+            return Err(format!("party index {} is outside 1..={}", index, parties));
+        }
+        if threshold == 0 || threshold > parties || parties as i64 >= params.q {
+            return Err(format!("cannot share with threshold {} among {}", threshold, parties));
+        }
+        params.check_security(DEFAULT_MIN_SECURITY_BITS)?;
+        let bits = smudging_bits(params, threshold, parties);
+        if bits < MIN_SMUDGING_BITS {
+            return Err(format!(
+                "a key from {} parties leaves smudging noise of only 2^{:.1} noise deviations for {} shares, below 2^{}; use a larger q or fewer parties",
+                parties, bits, threshold, MIN_SMUDGING_BITS
+            ));
+        }
+        Ok(DkgParty {
+            index,
+            parties,
+            threshold,
+            phase: Phase::SeedCommitments,
+            seed,
+            s: gen_ternary_poly(params.n, sub_seed(seed, "dkg secret", 0)),
+            e: gen_ternary_poly(params.n, sub_seed(seed, "dkg error", 0)),
+            seed_a: None,
+            seed_commitments: BTreeMap::new(),
+            seeds: BTreeMap::new(),
+            commitments: BTreeMap::new(),
+            reveals: BTreeMap::new(),
+            deals: BTreeMap::new(),
+            output: None,
+        })
+    }
+
+    pub fn index(&self) -> i64 {
+        self.index
+    }
+
+    /// The first round of messages
+    pub fn start(&mut self) -> Vec<DkgMessage> {
+        let seed = gen_seed(sub_seed(self.seed, "dkg seed contribution", 0));
+        let digest = seed_commitment(self.index, &seed);
+        self.seeds.insert(self.index, seed);
+        self.seed_commitments.insert(self.index, digest);
+        vec![DkgMessage::SeedCommitment { from: self.index, digest }]
+    }
+
+    /// Process a message from another party
+    /// # Arguments:
+    /// * `message` - a message addressed to this party or broadcast
+    /// * `params` - ring-LWE parameters
+    /// # Returns:
+    /// the messages to send once a round completes, or an error when the message is out of
+    /// place, duplicated, or a seed or reveal does not match its commitment
+    pub fn handle(&mut self, message: DkgMessage, params: &Parameters) -> Result<Vec<DkgMessage>, String> {
+        let from = match &message {
+            DkgMessage::SeedCommitment { from, .. } | DkgMessage::Seed { from, .. } | DkgMessage::Commitment { from, .. }
+            | DkgMessage::Reveal { from, .. } | DkgMessage::Deal { from, .. } => *from,
+        };
+        if from < 1 || from > self.parties as i64 {
+            return Err(format!("message from unknown party {}", from));
+        }
+        let duplicate = match message {
+            DkgMessage::SeedCommitment { digest, .. } => self.seed_commitments.insert(from, digest).is_some(),
+            DkgMessage::Seed { seed, .. } => self.seeds.insert(from, seed).is_some(),
+            DkgMessage::Commitment { digest, .. } => self.commitments.insert(from, digest).is_some(),
+            DkgMessage::Reveal { b, .. } => {
+                if b.len() != params.n {
+                    return Err(format!("party {} revealed a malformed b", from));
+                }
+                self.reveals.insert(from, b).is_some()
+            }
+            DkgMessage::Deal { to, share, .. } => {
+                if to != self.index || share.len() != params.n {
+                    return Err(format!("misaddressed or malformed deal from party {}", from));
+                }
+                self.deals.insert(from, share).is_some()
+            }
+        };
+        if duplicate {
+            return Err(format!("duplicate message from party {}", from));
+        }
+        // messages of a later round may already be in, so keep going while rounds complete
+        let mut outgoing = Vec::new();
+        loop {
+            let phase = self.phase;
+            outgoing.extend(self.advance(params)?);
+            if self.phase == phase {
+                return Ok(outgoing);
+            }
+        }
+    }
+
+    /// Move to the next round once every party's message for the current one is in
+    fn advance(&mut self, params: &Parameters) -> Result<Vec<DkgMessage>, String> {
+        let (q, f) = (params.q, &params.f);
+        match self.phase {
+            Phase::SeedCommitments if self.seed_commitments.len() == self.parties => {
+                self.phase = Phase::Seeds;
+                Ok(vec![DkgMessage::Seed { from: self.index, seed: self.seeds[&self.index] }])
+            }
+            Phase::Seeds if self.seeds.len() == self.parties => {
+                for (from, seed) in &self.seeds {
+                    if seed_commitment(*from, seed) != self.seed_commitments[from] {
+                        return Err(format!("party {} revealed a seed that does not match its commitment", from));
+                    }
+                }
+                let seeds: Vec<i64> = self.seeds.values().flat_map(seed_to_words).collect();
+                let seed_a = derive_bytes("ring-lwe dkg seed", &[&bincode::serialize(&seeds).expect("Failed to serialize data")]);
+                self.seed_a = Some(seed_a);
+                let b = self.own_b(params);
+                let digest = commitment(self.index, &b);
+                self.commitments.insert(self.index, digest);
+                self.phase = Phase::Commitments;
+                Ok(vec![DkgMessage::Commitment { from: self.index, digest }])
+            }
+            Phase::Commitments if self.commitments.len() == self.parties => {
+                let b = self.own_b(params);
+                self.reveals.insert(self.index, b.clone());
+                let shares = share_secret_key(&self.s, self.threshold, self.parties, params, sub_seed(self.seed, "dkg shares", 0))?;
+                let mut messages = vec![DkgMessage::Reveal { from: self.index, b }];
+                for share in shares {
+                    let coeffs = pad_coeffs(&share.share, params.n);
+                    if share.index == self.index {
+                        self.deals.insert(self.index, coeffs);
+                    } else {
+                        messages.push(DkgMessage::Deal { from: self.index, to: share.index, share: coeffs });
+                    }
+                }
+                self.phase = Phase::Reveals;
+                Ok(messages)
+            }
+            Phase::Reveals if self.reveals.len() == self.parties && self.deals.len() == self.parties => {
+                for (from, b) in &self.reveals {
+                    if commitment(*from, b) != self.commitments[from] {
+                        return Err(format!("party {} revealed a b that does not match its commitment", from));
+                    }
+                }
+                let b = self.reveals.values()
+                    .fold(Polynomial::new(vec![]), |acc, b| polyadd(&acc, &Polynomial::new(b.clone()), q, f));
+                let share: Vec<i64> = (0..params.n)
+                    .map(|j| self.deals.values().map(|deal| deal[j]).sum::<i64>().rem_euclid(q))
+                    .collect();
+
+                let seed_a = self.seed_a.unwrap();
+                let mut public_key = pad_coeffs(&b, params.n);
+                public_key.extend(seed_to_words(&seed_a));
+                self.output = Some(DkgOutput {
+                    public_key: compress(&public_key),
+                    share: KeyShare { index: self.index, threshold: self.threshold, key_parties: self.parties, share: Polynomial::new(share) },
+                });
+                self.phase = Phase::Done;
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// b_i = -a*s_i - e_i under the common a
+    fn own_b(&self, params: &Parameters) -> Vec<i64> {
+        let (q, f, omega) = (params.q, &params.f, params.omega);
+        let a = expand_a(&self.seed_a.unwrap(), params);
+        let b = polyadd(&polymul_fast(&polyinv(&a, q), &self.s, q, f, omega), &polyinv(&self.e, q), q, f);
+        pad_coeffs(&b, params.n)
+    }
+
+    /// The joint public key and this party's share, once the protocol has finished
+    pub fn output(&self) -> Option<&DkgOutput> {
+        self.output.as_ref()
+    }
+}
+
+/// Run the protocol among parties in one process, delivering every message in order
+/// # Arguments:
+/// * `parties` - parties created with `DkgParty::new`, with indices 1 to n
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// every party's output, or the first error a party reports
+/// # Example:
+/// ```
+/// use ring_lwe::dkg::{DkgParty, run_in_process};
+/// let params = ring_lwe::utils::Parameters::new(2048, 2147389441, 2);
+/// let parties: Vec<DkgParty> = (1..=3).map(|i| DkgParty::new(i, 3, 2, &params, None).unwrap()).collect();
+/// let outputs = run_in_process(parties, &params).unwrap();
+/// assert!(outputs.iter().all(|output| output.public_key == outputs[0].public_key));
+/// ```
+pub fn run_in_process(mut parties: Vec<DkgParty>, params: &Parameters) -> Result<Vec<DkgOutput>, String> {
+    let mut queue: VecDeque<(i64, DkgMessage)> = VecDeque::new();
+    for party in parties.iter_mut() {
+        let index = party.index;
+        queue.extend(party.start().into_iter().map(|message| (index, message)));
+    }
+    while let Some((sender, message)) = queue.pop_front() {
+        for party in parties.iter_mut() {
+            let addressed = message.recipient().map_or(party.index != sender, |to| to == party.index);
+            if addressed {
+                let index = party.index;
+                queue.extend(party.handle(message.clone(), params)?.into_iter().map(|reply| (index, reply)));
+            }
+        }
+    }
+    parties.iter()
+        .map(|party| party.output().cloned().ok_or_else(|| format!("party {} did not finish", party.index)))
+        .collect()
+}
@@ -1,4 +1,7 @@
-use crate::crypto::utils::{Parameters, mod_coeffs, polymul_fast, polyadd, gen_ternary_poly,compress,decompress};
+use crate::crypto::utils::{Parameters, mod_coeffs, polymul_fast, polyadd, gen_ternary_poly, pad_coeffs, compress, decompress, try_decompress};
+use crate::crypto::keygen::public_key_from_coeffs;
+use crate::crypto::module_lwe::{module_encrypt_string, module_rerandomize_string};
+use crate::crypto::validate::validate_public_key;
 use polynomial_ring::Polynomial;
 
 /// Encrypt a polynomial using the public key
@@ -22,22 +25,56 @@ pub fn encrypt(
     params: &Parameters,       //parameters (n,q,t,f)
     seed: Option<u64>            // Seed for random number generator
 ) -> [Polynomial<i64>; 2] {
-    let (n,q,t,f,omega) = (params.n, params.q, params.t, &params.f, params.omega);
-    // Scale the plaintext polynomial. use floor(m*q/t) rather than floor (q/t)*m
-    let scaled_m = mod_coeffs(m * q / t, q);
+    let n = params.n;
 
     // Generate random polynomials
     let e1 = gen_ternary_poly(n, seed);
     let e2 = gen_ternary_poly(n, seed);
     let u = gen_ternary_poly(n, seed);
 
+    encrypt_with(pk, m, [&u, &e1, &e2], params)
+}
+
+/// Encrypt a polynomial with given randomness, so a ciphertext can be recomputed from it
+/// # Arguments:
+/// * `pk` - public key (b, a)
+/// * `m` - plaintext polynomial
+/// * `[u, e1, e2]` - ternary randomness
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (b*u + e1 + floor(m*q/t), a*u + e2)
+pub(crate) fn encrypt_with(pk: &[Polynomial<i64>; 2], m: &Polynomial<i64>, [u, e1, e2]: [&Polynomial<i64>; 3], params: &Parameters) -> [Polynomial<i64>; 2] {
+    let (q,t,f,omega) = (params.q, params.t, &params.f, params.omega);
+    // Scale the plaintext polynomial. use floor(m*q/t) rather than floor (q/t)*m
+    let scaled_m = mod_coeffs(m * q / t, q);
+
     // Compute ciphertext components
-    let ct0 = polyadd(&polyadd(&polymul_fast(&pk[0], &u, q, f, omega), &e1, q, f),&scaled_m,q,f);
-    let ct1 = polyadd(&polymul_fast(&pk[1], &u, q, f, omega), &e2, q, f);
+    let ct0 = polyadd(&polyadd(&polymul_fast(&pk[0], u, q, f, omega), e1, q, f),&scaled_m,q,f);
+    let ct1 = polyadd(&polymul_fast(&pk[1], u, q, f, omega), e2, q, f);
 
     [ct0, ct1]
 }
 
+/// Split a message into plaintext polynomials of n bits each
+/// # Arguments:
+/// * `message` - message to encode
+/// * `n` - number of bits per polynomial
+/// # Returns:
+/// polynomials holding the message bits, each byte MSB first
+pub(crate) fn message_blocks(message: &str, n: usize) -> Vec<Polynomial<i64>> {
+    // Convert each byte into its 8-bit representation (MSB first)
+    let message_bits: Vec<i64> = message
+        .bytes()
+        .flat_map(|byte| (0..8).rev().map(move |i| ((byte >> i) & 1) as i64))
+        .collect();
+
+    // Convert bits into a vector of Polynomials
+    message_bits
+        .chunks(n) // Pack bits into polynomials of size `n`
+        .map(|chunk| Polynomial::new(chunk.to_vec()))
+        .collect()
+}
+
 /// Encrypt a string using the public key
 /// # Arguments:
 /// * `pk_string` - public key as a base64 encoded string
@@ -55,29 +92,19 @@ pub fn encrypt(
 /// let ciphertext_string = ring_lwe::encrypt::encrypt_string(pk_string, &message, &params, None);
 /// ```
 pub fn encrypt_string(pk_base64: &String, message: &String, params: &Parameters, seed: Option<u64>) -> String {
+    if params.k > 1 {
+        return module_encrypt_string(pk_base64, message, params, seed);
+    }
+
     // Decode the Base64 public key string
     let pk_arr: Vec<i64> = decompress(pk_base64);
 
-    // Split the public key into two polynomials
-    let pk_b = Polynomial::new(pk_arr[..params.n].to_vec());
-    let pk_a = Polynomial::new(pk_arr[params.n..].to_vec());
-    let pk = [pk_b, pk_a];
-
-    // Convert each byte into its 8-bit representation (MSB first)
-    let message_bits: Vec<i64> = message
-        .bytes()
-        .flat_map(|byte| (0..8).rev().map(move |i| ((byte >> i) & 1) as i64))
-        .collect();
-
-    // Convert bits into a vector of Polynomials
-    let message_blocks: Vec<Polynomial<i64>> = message_bits
-        .chunks(params.n) // Pack bits into polynomials of size `n`
-        .map(|chunk| Polynomial::new(chunk.to_vec()))
-        .collect();
+    // Split the public key into b and a, regenerating a from its seed
+    let pk = public_key_from_coeffs(&pk_arr, params).expect("invalid public key");
 
     // Encrypt each integer message block
     let mut ciphertext_list: Vec<i64> = Vec::new();
-    for message_block in message_blocks {
+    for message_block in message_blocks(message, params.n) {
         let ciphertext = encrypt(&pk, &message_block, &params, seed);
         ciphertext_list.extend(ciphertext[0].coeffs());
         ciphertext_list.extend(ciphertext[1].coeffs());
@@ -85,4 +112,61 @@ pub fn encrypt_string(pk_base64: &String, message: &String, params: &Parameters,
 
     // Serialize the ciphertext list to binary and encode as Base64
     compress(&ciphertext_list)
-}
\ No newline at end of file
+}
+
+/// Rerandomize a ciphertext by adding a fresh encryption of zero
+/// # Arguments:
+/// * `pk` - public key the ciphertext was encrypted to
+/// * `ct` - ciphertext from `encrypt`
+/// * `params` - ring-LWE parameters
+/// * `seed` - random seed
+/// # Returns:
+/// a ciphertext of the same plaintext that cannot be linked to `ct` without the secret key; it
+/// carries the noise of both encryptions
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let (pk, sk) = ring_lwe::keygen::keygen(&params, None);
+/// let m = polynomial_ring::Polynomial::new(vec![1, 0, 1]);
+/// let ct = ring_lwe::encrypt::encrypt(&pk, &m, &params, None);
+/// let fresh = ring_lwe::encrypt::rerandomize(&pk, &ct, &params, None);
+/// assert_ne!(fresh, ct);
+/// assert_eq!(ring_lwe::decrypt::decrypt(&sk, &fresh, &params), m);
+/// ```
+pub fn rerandomize(pk: &[Polynomial<i64>; 2], ct: &[Polynomial<i64>; 2], params: &Parameters, seed: Option<u64>) -> [Polynomial<i64>; 2] {
+    let (q, f) = (params.q, &params.f);
+    let zero = encrypt(pk, &Polynomial::new(vec![]), params, seed);
+    [polyadd(&ct[0], &zero[0], q, f), polyadd(&ct[1], &zero[1], q, f)]
+}
+
+/// Rerandomize a ciphertext string so stored or forwarded copies cannot be correlated
+/// # Arguments:
+/// * `pk_base64` - public key the ciphertext was encrypted to
+/// * `ciphertext_base64` - ciphertext from `encrypt_string`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// a new ciphertext of the same message, or an error for a malformed key or ciphertext
+///
+/// Each rerandomization adds the noise of one encryption, so a ciphertext can only be
+/// rerandomized a limited number of times before it fails to decrypt; with the default
+/// parameters the noise stays far below `noise_bound()` for dozens of rounds.
+pub fn rerandomize_string(pk_base64: &str, ciphertext_base64: &str, params: &Parameters) -> Result<String, String> {
+    validate_public_key(pk_base64, params)?;
+    if params.k > 1 {
+        return module_rerandomize_string(pk_base64, ciphertext_base64, params);
+    }
+    let n = params.n;
+    let pk = public_key_from_coeffs(&decompress(pk_base64), params)?;
+    let coeffs = try_decompress(ciphertext_base64)?;
+    if coeffs.is_empty() || coeffs.len() % (2 * n) != 0 {
+        return Err(String::from("wrong length for a ciphertext"));
+    }
+    let mut ciphertext_list: Vec<i64> = Vec::with_capacity(coeffs.len());
+    for block in coeffs.chunks(2 * n) {
+        let ct = [Polynomial::new(block[..n].to_vec()), Polynomial::new(block[n..].to_vec())];
+        let fresh = rerandomize(&pk, &ct, params, None);
+        ciphertext_list.extend(pad_coeffs(&fresh[0], n));
+        ciphertext_list.extend(pad_coeffs(&fresh[1], n));
+    }
+    Ok(compress(&ciphertext_list))
+}
@@ -0,0 +1,250 @@
+use crate::crypto::decrypt::decrypt;
+use crate::crypto::encrypt::encrypt_with;
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::keygen::{keygen_string, public_key_from_coeffs, ternary_from_key};
+use crate::crypto::seal::NONCE_BYTES;
+use crate::crypto::utils::{Parameters, compress, pad_coeffs, try_decompress};
+use crate::crypto::validate::validate_public_key;
+use polynomial_ring::Polynomial;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Public key of the hybrid KEM: an X25519 point and a ring-LWE public key
+#[derive(Serialize, Deserialize)]
+struct HybridPublicKey {
+    x25519: [u8; 32],
+    ring_lwe: String,
+}
+
+/// Secret key of the hybrid KEM; the ring-LWE public key is kept to bind it into the KDF and
+/// to re-encrypt, and the reject seed keys the pseudorandom answer to invalid ciphertexts
+#[derive(Serialize, Deserialize)]
+struct HybridSecretKey {
+    x25519: [u8; 32],
+    ring_lwe: String,
+    ring_lwe_public: String,
+    reject_seed: [u8; KEY_BYTES],
+}
+
+/// Ciphertext of the hybrid KEM: an ephemeral X25519 point and a ring-LWE ciphertext
+#[derive(Serialize, Deserialize)]
+struct HybridCiphertext {
+    x25519: [u8; 32],
+    ring_lwe: String,
+}
+
+/// A message encrypted under a hybrid KEM key
+#[derive(Serialize, Deserialize)]
+struct HybridMessage {
+    kem: HybridCiphertext,
+    nonce: [u8; NONCE_BYTES],
+    body: Vec<u8>,
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    general_purpose::STANDARD.encode(bincode::serialize(value).expect("Failed to serialize data"))
+}
+
+fn decode<T: DeserializeOwned>(base64_str: &str, what: &str) -> Result<T, String> {
+    let bytes = general_purpose::STANDARD.decode(base64_str.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    bincode::deserialize(&bytes).map_err(|_| format!("not a hybrid {}", what))
+}
+
+/// Combine both shared secrets with a KDF over both ciphertexts and both public keys, so
+/// the key stays secret while either X25519 or ring-LWE does
+fn combine(x25519_secret: &[u8], ring_lwe_secret: &[u8], ct: &HybridCiphertext, x25519_pk: &[u8; 32], ring_lwe_pk: &str) -> [u8; KEY_BYTES] {
+    derive_bytes("ring-lwe hybrid kem", &[
+        x25519_secret,
+        ring_lwe_secret,
+        &ct.x25519,
+        ct.ring_lwe.as_bytes(),
+        x25519_pk,
+        ring_lwe_pk.as_bytes(),
+    ])
+}
+
+/// Check that the parameters carry a 256-bit ring-LWE message
+fn check_params(params: &Parameters) -> Result<(), String> {
+    if params.k > 1 || params.n < 8 * KEY_BYTES {
+        return Err(String::from("the hybrid KEM needs ring-LWE parameters with n >= 256"));
+    }
+    Ok(())
+}
+
+/// Encrypt a 256-bit message to a ring-LWE public key with randomness hashed from the message
+/// and the key, so the recipient can recompute the ciphertext from what it decrypts
+fn encrypt_deterministic(pk: &[Polynomial<i64>; 2], pk_string: &str, message: &[u8; KEY_BYTES], params: &Parameters) -> Vec<i64> {
+    let n = params.n;
+    let coins: [u8; KEY_BYTES] = derive_bytes("ring-lwe hybrid coins", &[message, pk_string.as_bytes()]);
+    let u = ternary_from_key(n, "ring-lwe hybrid u", &coins);
+    let e1 = ternary_from_key(n, "ring-lwe hybrid e1", &coins);
+    let e2 = ternary_from_key(n, "ring-lwe hybrid e2", &coins);
+    let bits: Vec<i64> = message.iter().flat_map(|byte| (0..8).rev().map(move |i| ((byte >> i) & 1) as i64)).collect();
+    let ct = encrypt_with(pk, &Polynomial::new(bits), [&u, &e1, &e2], params);
+    [pad_coeffs(&ct[0], n), pad_coeffs(&ct[1], n)].concat()
+}
+
+/// Generate a hybrid X25519 + ring-LWE key pair as base64 encoded strings
+/// # Arguments:
+/// * `params` - ring-LWE parameters with n >= 256
+/// # Returns:
+/// HashMap with the "public" and "secret" hybrid keys; panics for module-LWE parameters or
+/// n < 256
+pub fn hybrid_keygen_string(params: &Parameters) -> HashMap<String, String> {
+    check_params(params).unwrap_or_else(|e| panic!("{}", e));
+    let x25519_secret = StaticSecret::random_from_rng(OsRng);
+    let x25519_public = PublicKey::from(&x25519_secret);
+    let ring_lwe = keygen_string(params, None);
+    let ring_lwe_public = ring_lwe.get("public").unwrap().clone();
+    let mut reject_seed = [0u8; KEY_BYTES];
+    OsRng.fill_bytes(&mut reject_seed);
+
+    let mut keys: HashMap<String, String> = HashMap::new();
+    keys.insert(String::from("public"), encode(&HybridPublicKey { x25519: x25519_public.to_bytes(), ring_lwe: ring_lwe_public.clone() }));
+    keys.insert(String::from("secret"), encode(&HybridSecretKey {
+        x25519: x25519_secret.to_bytes(),
+        ring_lwe: ring_lwe.get("secret").unwrap().clone(),
+        ring_lwe_public,
+        reject_seed,
+    }));
+    keys
+}
+
+/// Encapsulate a fresh shared key to a hybrid public key
+/// # Arguments:
+/// * `public_key` - hybrid public key from `hybrid_keygen_string`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (base64 encoded hybrid ciphertext, 32-byte shared key), or an error for a malformed key
+/// # Example:
+/// ```
+/// use ring_lwe::hybrid::{hybrid_keygen_string, encapsulate, decapsulate};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = hybrid_keygen_string(&params);
+/// let (ct, key) = encapsulate(keys.get("public").unwrap(), &params).unwrap();
+/// assert_eq!(decapsulate(keys.get("secret").unwrap(), &ct, &params), Ok(key));
+/// ```
+pub fn encapsulate(public_key: &str, params: &Parameters) -> Result<(String, [u8; KEY_BYTES]), String> {
+    let (ct, key) = encapsulate_parts(&decode(public_key, "public key")?, params)?;
+    Ok((encode(&ct), key))
+}
+
+fn encapsulate_parts(pk: &HybridPublicKey, params: &Parameters) -> Result<(HybridCiphertext, [u8; KEY_BYTES]), String> {
+    check_params(params)?;
+    validate_public_key(&pk.ring_lwe, params)?;
+    let ring_lwe_pk = public_key_from_coeffs(&try_decompress(&pk.ring_lwe)?, params)?;
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let x25519_secret = ephemeral.diffie_hellman(&PublicKey::from(pk.x25519));
+    if !x25519_secret.was_contributory() {
+        return Err(String::from("the X25519 public key is a low-order point"));
+    }
+
+    let mut message = [0u8; KEY_BYTES];
+    OsRng.fill_bytes(&mut message);
+    let ring_lwe_ct = encrypt_deterministic(&ring_lwe_pk, &pk.ring_lwe, &message, params);
+    let ring_lwe_secret: [u8; KEY_BYTES] = derive_bytes("ring-lwe hybrid accept", &[&message, &bincode::serialize(&ring_lwe_ct).expect("Failed to serialize data")]);
+    let ct = HybridCiphertext {
+        x25519: PublicKey::from(&ephemeral).to_bytes(),
+        ring_lwe: compress(&ring_lwe_ct),
+    };
+    let key = combine(x25519_secret.as_bytes(), &ring_lwe_secret, &ct, &pk.x25519, &pk.ring_lwe);
+    Ok((ct, key))
+}
+
+/// Recover the shared key from a hybrid ciphertext
+/// # Arguments:
+/// * `secret_key` - hybrid secret key from `hybrid_keygen_string`
+/// * `ciphertext` - base64 encoded hybrid ciphertext from `encapsulate`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the shared key, or an error for malformed input; a ciphertext for another key yields an
+/// unrelated key, which the AEAD layer above then rejects
+///
+/// The ring-LWE half is a Fujisaki-Okamoto transform of the CPA-secure encryption: the
+/// recipient decrypts the 256-bit message, re-encrypts it with the coins hashed from it and
+/// only derives the key from the message when the ciphertext comes out identical. Any other
+/// ciphertext gets a key hashed from the secret reject seed and the ciphertext, so a modified
+/// ciphertext fails the AEAD the same way whatever it decrypts to, and accept or reject says
+/// nothing about the ring-LWE secret even to someone who broke X25519.
+pub fn decapsulate(secret_key: &str, ciphertext: &str, params: &Parameters) -> Result<[u8; KEY_BYTES], String> {
+    let sk: HybridSecretKey = decode(secret_key, "secret key")?;
+    let ct: HybridCiphertext = decode(ciphertext, "ciphertext")?;
+    decapsulate_parts(&sk, &ct, params)
+}
+
+fn decapsulate_parts(sk: &HybridSecretKey, ct: &HybridCiphertext, params: &Parameters) -> Result<[u8; KEY_BYTES], String> {
+    let x25519_sk = StaticSecret::from(sk.x25519);
+    let x25519_secret = x25519_sk.diffie_hellman(&PublicKey::from(ct.x25519));
+    if !x25519_secret.was_contributory() {
+        return Err(String::from("the X25519 ciphertext is a low-order point"));
+    }
+    check_params(params)?;
+    let n = params.n;
+    let s = Polynomial::new(try_decompress(&sk.ring_lwe)?);
+    let ring_lwe_pk = public_key_from_coeffs(&try_decompress(&sk.ring_lwe_public)?, params)?;
+    let ring_lwe_ct = try_decompress(&ct.ring_lwe)?;
+    if ring_lwe_ct.len() != 2 * n {
+        return Err(String::from("wrong length for a ring-LWE ciphertext"));
+    }
+    let bits = pad_coeffs(&decrypt(&s, &[Polynomial::new(ring_lwe_ct[..n].to_vec()), Polynomial::new(ring_lwe_ct[n..].to_vec())], params), n);
+    let mut message = [0u8; KEY_BYTES];
+    for (byte, chunk) in message.iter_mut().zip(bits.chunks(8)) {
+        *byte = chunk.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8);
+    }
+    // re-encrypt, and answer anything but the exact ciphertext with a pseudorandom key
+    let ct_bytes = bincode::serialize(&ring_lwe_ct).expect("Failed to serialize data");
+    let ring_lwe_secret: [u8; KEY_BYTES] = if encrypt_deterministic(&ring_lwe_pk, &sk.ring_lwe_public, &message, params) == ring_lwe_ct {
+        derive_bytes("ring-lwe hybrid accept", &[&message, &ct_bytes])
+    } else {
+        derive_bytes("ring-lwe hybrid reject", &[&sk.reject_seed, &ct_bytes])
+    };
+    Ok(combine(x25519_secret.as_bytes(), &ring_lwe_secret, ct, PublicKey::from(&x25519_sk).as_bytes(), &sk.ring_lwe_public))
+}
+
+/// Encrypt a string with the hybrid KEM and AES-256-GCM
+/// # Arguments:
+/// * `public_key` - hybrid public key from `hybrid_keygen_string`
+/// * `message` - message to encrypt
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// base64 encoded hybrid ciphertext and encrypted body, or an error for a malformed key
+/// # Example:
+/// ```
+/// use ring_lwe::hybrid::{hybrid_keygen_string, hybrid_encrypt_string, hybrid_decrypt_string};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = hybrid_keygen_string(&params);
+/// let ciphertext = hybrid_encrypt_string(keys.get("public").unwrap(), "hello", &params).unwrap();
+/// assert_eq!(hybrid_decrypt_string(keys.get("secret").unwrap(), &ciphertext, &params), Ok(String::from("hello")));
+/// ```
+pub fn hybrid_encrypt_string(public_key: &str, message: &str, params: &Parameters) -> Result<String, String> {
+    let (kem, key) = encapsulate_parts(&decode(public_key, "public key")?, params)?;
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let body = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), message.as_bytes())
+        .expect("Failed to encrypt message");
+    Ok(encode(&HybridMessage { kem, nonce, body }))
+}
+
+/// Decrypt a string encrypted with `hybrid_encrypt_string`
+/// # Arguments:
+/// * `secret_key` - hybrid secret key from `hybrid_keygen_string`
+/// * `ciphertext` - base64 encoded ciphertext
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the message, or an error when the input is malformed or was encrypted to another key
+pub fn hybrid_decrypt_string(secret_key: &str, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+    let sk: HybridSecretKey = decode(secret_key, "secret key")?;
+    let message: HybridMessage = decode(ciphertext, "ciphertext")?;
+    let key = decapsulate_parts(&sk, &message.kem, params)?;
+    let plaintext = Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(&message.nonce), message.body.as_slice())
+        .map_err(|_| String::from("the ciphertext does not decrypt with this key"))?;
+    String::from_utf8(plaintext).map_err(|e| format!("message is not UTF-8: {}", e))
+}
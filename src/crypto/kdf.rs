@@ -0,0 +1,65 @@
+use sha3::Shake256;
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+
+/// Length in bytes of derived keys
+pub const KEY_BYTES: usize = 32;
+
+/// Derive bytes from labelled inputs with SHAKE-256
+/// # Arguments:
+/// * `label` - domain separation label, distinct for every use
+/// * `inputs` - input strings, each length-prefixed so the encoding is unambiguous
+/// # Returns:
+/// N bytes of SHAKE-256(label || inputs)
+/// # Example:
+/// ```
+/// let key: [u8; 32] = ring_lwe::kdf::derive_bytes("example", &[b"input"]);
+/// assert_ne!(key, ring_lwe::kdf::derive_bytes::<32>("other", &[b"input"]));
+/// ```
+pub fn derive_bytes<const N: usize>(label: &str, inputs: &[&[u8]]) -> [u8; N] {
+    let mut out = [0u8; N];
+    derive_reader(label, inputs).read(&mut out);
+    out
+}
+
+/// SHAKE-256 output stream over labelled inputs, encoded as in `derive_bytes`, for callers
+/// that need an unbounded number of bytes
+pub(crate) fn derive_reader(label: &str, inputs: &[&[u8]]) -> impl XofReader {
+    let mut hasher = Shake256::default();
+    for input in std::iter::once(label.as_bytes()).chain(inputs.iter().copied()) {
+        hasher.update(&(input.len() as u64).to_le_bytes());
+        hasher.update(input);
+    }
+    hasher.finalize_xof()
+}
+
+/// Derive an independent sampler seed for one polynomial from a caller seed
+/// # Arguments:
+/// * `seed` - caller seed, None for OS randomness
+/// * `label` - what the polynomial is, distinct for every sampler call site
+/// * `index` - position of the polynomial among those with the same label
+/// # Returns:
+/// hash of the label, seed and index, so streams never overlap across polynomials or calls
+pub(crate) fn sub_seed(seed: Option<u64>, label: &str, index: usize) -> Option<u64> {
+    seed.map(|seed| u64::from_le_bytes(derive_bytes(label, &[&seed.to_le_bytes(), &(index as u64).to_le_bytes()])))
+}
+
+/// Derive the key of a node in a hierarchy of keys
+/// # Arguments:
+/// * `master_seed` - root secret
+/// * `path` - slash-separated path such as `user/epoch/3`; empty components are skipped
+/// # Returns:
+/// 32-byte node key, derived one path component at a time so every prefix of the path names
+/// its own node
+/// # Example:
+/// ```
+/// let master_seed = [0u8; 32];
+/// let key = ring_lwe::kdf::derive_path_key(&master_seed, "user/epoch/3");
+/// assert_eq!(key, ring_lwe::kdf::derive_path_key(&master_seed, "/user/epoch/3/"));
+/// ```
+pub fn derive_path_key(master_seed: &[u8], path: &str) -> [u8; KEY_BYTES] {
+    path.split('/')
+        .filter(|component| !component.is_empty())
+        .fold(derive_bytes("ring-lwe master key", &[master_seed]), |key, component| {
+            derive_bytes("ring-lwe child key", &[&key, component.as_bytes()])
+        })
+}
@@ -0,0 +1,306 @@
+use crate::crypto::armor::fingerprint;
+use crate::crypto::kdf::{derive_bytes, sub_seed, KEY_BYTES};
+use crate::crypto::keygen::expand_a;
+use crate::crypto::signature::{sign, verify, verifying_key_to_string, Signature, SignatureParameters, SigningKey, VerifyingKey};
+use crate::crypto::utils::{Parameters, polymul_fast, polyadd, gen_ternary_poly, gen_seed, pad_coeffs, seed_to_words, words_to_seed, compress, try_decompress, SEED_BYTES, SEED_WORDS};
+use polynomial_ring::Polynomial;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// First message, from the initiator: b = a*s + e and the seed of a
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitiatorMessage {
+    pub b: Polynomial<i64>,
+    pub seed_a: [u8; SEED_BYTES],
+}
+
+/// Reply from the responder: b' = a*s' + e' and one reconciliation bit per coefficient
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponderMessage {
+    pub b: Polynomial<i64>,
+    pub hint: Vec<u8>,
+}
+
+/// What the initiator keeps between sending its message and receiving the reply
+pub struct InitiatorState {
+    s: Polynomial<i64>,
+    message: InitiatorMessage,
+}
+
+impl InitiatorMessage {
+    /// Encode as base64 coefficients b || seed_a
+    pub fn to_base64(&self, params: &Parameters) -> String {
+        let mut coeffs = pad_coeffs(&self.b, params.n);
+        coeffs.extend(seed_to_words(&self.seed_a));
+        compress(&coeffs)
+    }
+
+    /// Decode a message written by `to_base64`
+    pub fn from_base64(message: &str, params: &Parameters) -> Result<Self, String> {
+        let coeffs = try_decompress(message)?;
+        if coeffs.len() != params.n + SEED_WORDS {
+            return Err(String::from("wrong length for an initiator message"));
+        }
+        Ok(InitiatorMessage { b: Polynomial::new(coeffs[..params.n].to_vec()), seed_a: words_to_seed(&coeffs[params.n..]) })
+    }
+}
+
+impl ResponderMessage {
+    /// Encode as base64 coefficients b || hint bits
+    pub fn to_base64(&self, params: &Parameters) -> String {
+        let mut coeffs = pad_coeffs(&self.b, params.n);
+        coeffs.extend(self.hint.iter().map(|&bit| bit as i64));
+        compress(&coeffs)
+    }
+
+    /// Decode a message written by `to_base64`
+    pub fn from_base64(message: &str, params: &Parameters) -> Result<Self, String> {
+        let coeffs = try_decompress(message)?;
+        if coeffs.len() != 2 * params.n || coeffs[params.n..].iter().any(|&bit| bit != 0 && bit != 1) {
+            return Err(String::from("malformed responder message"));
+        }
+        Ok(ResponderMessage { b: Polynomial::new(coeffs[..params.n].to_vec()), hint: coeffs[params.n..].iter().map(|&bit| bit as u8).collect() })
+    }
+}
+
+/// Randomized doubling of v from Z_q to Z_2q, so the rounded bits are unbiased for odd q
+fn double(v: &Polynomial<i64>, params: &Parameters, seed: Option<u64>) -> Vec<i64> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let two_q = 2 * params.q;
+    pad_coeffs(v, params.n).iter().map(|&c| {
+        // e is -1, 0, 0 or 1 with equal probability
+        let e = [-1, 0, 0, 1][rng.gen_range(0..4)];
+        (2 * c - e).rem_euclid(two_q)
+    }).collect()
+}
+
+/// Key bit of a doubled coefficient v in [0, 2q): the rounding of v/q mod 2
+fn key_bit(v: i64, q: i64) -> u8 {
+    (q <= 2 * v && 2 * v < 3 * q) as u8
+}
+
+/// Hint bit of a doubled coefficient v in [0, 2q): the cross-rounding of 2v/q mod 2
+fn hint_bit(v: i64, q: i64) -> u8 {
+    ((2 * v / q) % 2) as u8
+}
+
+/// Recover a key bit from w = 2*x mod 2q, with x within q/8 of the responder's value
+///
+/// The hint says which half of each quarter of Z_2q the responder's value lies in, which
+/// leaves two candidates q apart; the nearer one decides the bit.
+fn reconcile(w: i64, hint: u8, q: i64) -> u8 {
+    let w4 = 4 * w;
+    match hint {
+        0 => !(w4 < 3 * q || w4 >= 7 * q) as u8,
+        _ => (q <= w4 && w4 < 5 * q) as u8,
+    }
+}
+
+/// Hash the reconciled bits with the transcript and any identities into the session key
+fn session_key(bits: &[u8], first: &InitiatorMessage, reply: &ResponderMessage, identities: &[u8], params: &Parameters) -> [u8; KEY_BYTES] {
+    let transcript = bincode::serialize(&(pad_coeffs(&first.b, params.n), first.seed_a, pad_coeffs(&reply.b, params.n), &reply.hint))
+        .expect("Failed to serialize data");
+    derive_bytes("ring-lwe key exchange", &[bits, &transcript, identities])
+}
+
+/// Start a key exchange
+/// # Arguments:
+/// * `params` - ring-LWE parameters
+/// * `seed` - random seed
+/// # Returns:
+/// (state to keep for `finish`, message to send to the responder)
+/// # Example:
+/// ```
+/// use ring_lwe::key_exchange::{initiate, respond, finish};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let (state, first) = initiate(&params, None);
+/// let (reply, responder_key) = respond(&first, &params, None);
+/// assert_eq!(finish(state, &reply, &params), responder_key);
+/// ```
+pub fn initiate(params: &Parameters, seed: Option<u64>) -> (InitiatorState, InitiatorMessage) {
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let seed_a = gen_seed(sub_seed(seed, "key exchange matrix", 0));
+    let a = expand_a(&seed_a, params);
+    let s = gen_ternary_poly(n, sub_seed(seed, "key exchange initiator secret", 0));
+    let e = gen_ternary_poly(n, sub_seed(seed, "key exchange initiator error", 0));
+    let b = polyadd(&polymul_fast(&a, &s, q, f, omega), &e, q, f);
+    let message = InitiatorMessage { b, seed_a };
+    (InitiatorState { s, message: message.clone() }, message)
+}
+
+/// Answer an initiator message, fixing the shared key
+/// # Arguments:
+/// * `first` - message from `initiate`
+/// * `params` - ring-LWE parameters
+/// * `seed` - random seed
+/// # Returns:
+/// (reply to send back, 32-byte session key)
+pub fn respond(first: &InitiatorMessage, params: &Parameters, seed: Option<u64>) -> (ResponderMessage, [u8; KEY_BYTES]) {
+    respond_with_identities(first, &[], params, seed)
+}
+
+fn respond_with_identities(first: &InitiatorMessage, identities: &[u8], params: &Parameters, seed: Option<u64>) -> (ResponderMessage, [u8; KEY_BYTES]) {
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let a = expand_a(&first.seed_a, params);
+    let s = gen_ternary_poly(n, sub_seed(seed, "key exchange responder secret", 0));
+    let e = gen_ternary_poly(n, sub_seed(seed, "key exchange responder error", 0));
+    let e2 = gen_ternary_poly(n, sub_seed(seed, "key exchange responder error", 1));
+    let b = polyadd(&polymul_fast(&a, &s, q, f, omega), &e, q, f);
+    let v = polyadd(&polymul_fast(&first.b, &s, q, f, omega), &e2, q, f);
+
+    let doubled = double(&v, params, sub_seed(seed, "key exchange doubling", 0));
+    let hint = doubled.iter().map(|&c| hint_bit(c, q)).collect();
+    let bits: Vec<u8> = doubled.iter().map(|&c| key_bit(c, q)).collect();
+    let reply = ResponderMessage { b, hint };
+    let key = session_key(&bits, first, &reply, identities, params);
+    (reply, key)
+}
+
+/// Complete a key exchange on the initiator side
+/// # Arguments:
+/// * `state` - state from `initiate`
+/// * `reply` - message from `respond`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the 32-byte session key, equal to the responder's unless decryption noise exceeded q/8
+pub fn finish(state: InitiatorState, reply: &ResponderMessage, params: &Parameters) -> [u8; KEY_BYTES] {
+    finish_with_identities(state, reply, &[], params)
+}
+
+fn finish_with_identities(state: InitiatorState, reply: &ResponderMessage, identities: &[u8], params: &Parameters) -> [u8; KEY_BYTES] {
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let x = polymul_fast(&reply.b, &state.s, q, f, omega);
+    let bits: Vec<u8> = pad_coeffs(&x, n).iter().zip(&reply.hint)
+        .map(|(&c, &hint)| reconcile((2 * c).rem_euclid(2 * q), hint, q))
+        .collect();
+    session_key(&bits, &state.message, reply, identities, params)
+}
+
+/// Initiator message signed with a long-term key
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedInitiatorMessage {
+    pub message: InitiatorMessage,
+    pub signature: Signature,
+}
+
+/// Responder reply signed with a long-term key
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedResponderMessage {
+    pub message: ResponderMessage,
+    pub signature: Signature,
+}
+
+/// What the initiator of an authenticated exchange keeps until the reply arrives
+pub struct AuthenticatedInitiatorState {
+    state: InitiatorState,
+    identities: Vec<u8>,
+    peer: VerifyingKey,
+}
+
+/// Fingerprints of the initiator and responder long-term keys, in that order
+fn identities(initiator: &VerifyingKey, responder: &VerifyingKey, sig_params: &SignatureParameters) -> Vec<u8> {
+    [initiator, responder].iter()
+        .flat_map(|vk| fingerprint(&verifying_key_to_string(vk, sig_params)).expect("verifying keys serialize to base64").into_bytes())
+        .collect()
+}
+
+/// Bytes each side signs: a role label, both identities and the messages so far
+fn signed_transcript(role: &str, identities: &[u8], first: &InitiatorMessage, reply: Option<&ResponderMessage>, params: &Parameters) -> Vec<u8> {
+    let reply = reply.map(|r| (pad_coeffs(&r.b, params.n), &r.hint));
+    let mut bytes = role.as_bytes().to_vec();
+    bytes.extend(bincode::serialize(&(identities, pad_coeffs(&first.b, params.n), first.seed_a, reply)).expect("Failed to serialize data"));
+    bytes
+}
+
+/// Start a key exchange authenticated by long-term signing keys
+/// # Arguments:
+/// * `identity` - initiator signing key
+/// * `peer` - verifying key of the expected responder
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// * `seed` - random seed for the ephemeral ring-LWE values only, never reused across sessions;
+///   the signature masks are always derived from the signing key and transcript
+/// # Returns:
+/// (state to keep for `finish_authenticated`, signed message to send to the responder)
+/// # Example:
+/// ```
+/// use ring_lwe::key_exchange::{initiate_authenticated, respond_authenticated, finish_authenticated};
+/// use ring_lwe::signature::{sign_keygen, SignatureParameters};
+/// let (params, sig_params) = (ring_lwe::utils::Parameters::default(), SignatureParameters::default());
+/// let (alice_vk, alice) = sign_keygen(&sig_params, None);
+/// let (bob_vk, bob) = sign_keygen(&sig_params, None);
+/// let (state, first) = initiate_authenticated(&alice, &bob_vk, &params, &sig_params, None);
+/// let (reply, bob_key) = respond_authenticated(&bob, &alice_vk, &first, &params, &sig_params, None).unwrap();
+/// assert_eq!(finish_authenticated(state, &reply, &params, &sig_params), Ok(bob_key));
+/// ```
+pub fn initiate_authenticated(
+    identity: &SigningKey,
+    peer: &VerifyingKey,
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+    seed: Option<u64>,
+) -> (AuthenticatedInitiatorState, AuthenticatedInitiatorMessage) {
+    let identities = identities(&identity.verifying_key, peer, sig_params);
+    let (state, message) = initiate(params, seed);
+    let transcript = signed_transcript("initiator", &identities, &message, None, params);
+    let signature = sign(identity, &transcript, sig_params);
+    (
+        AuthenticatedInitiatorState { state, identities, peer: peer.clone() },
+        AuthenticatedInitiatorMessage { message, signature },
+    )
+}
+
+/// Answer an authenticated initiator message
+/// # Arguments:
+/// * `identity` - responder signing key
+/// * `peer` - verifying key of the expected initiator
+/// * `first` - signed message from `initiate_authenticated`
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// * `seed` - random seed for the ephemeral ring-LWE values only, never reused across sessions;
+///   the signature masks are always derived from the signing key and transcript
+/// # Returns:
+/// (signed reply, session key bound to both long-term keys), or an error when the initiator
+/// signature does not verify under `peer`
+pub fn respond_authenticated(
+    identity: &SigningKey,
+    peer: &VerifyingKey,
+    first: &AuthenticatedInitiatorMessage,
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+    seed: Option<u64>,
+) -> Result<(AuthenticatedResponderMessage, [u8; KEY_BYTES]), String> {
+    let identities = identities(peer, &identity.verifying_key, sig_params);
+    let transcript = signed_transcript("initiator", &identities, &first.message, None, params);
+    if !verify(peer, &transcript, &first.signature, sig_params) {
+        return Err(String::from("the initiator signature does not verify"));
+    }
+    let (message, key) = respond_with_identities(&first.message, &identities, params, seed);
+    let transcript = signed_transcript("responder", &identities, &first.message, Some(&message), params);
+    let signature = sign(identity, &transcript, sig_params);
+    Ok((AuthenticatedResponderMessage { message, signature }, key))
+}
+
+/// Complete an authenticated key exchange on the initiator side
+/// # Arguments:
+/// * `state` - state from `initiate_authenticated`
+/// * `reply` - signed reply from `respond_authenticated`
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// the session key, or an error when the reply is not signed by the expected responder
+pub fn finish_authenticated(
+    state: AuthenticatedInitiatorState,
+    reply: &AuthenticatedResponderMessage,
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+) -> Result<[u8; KEY_BYTES], String> {
+    let transcript = signed_transcript("responder", &state.identities, &state.state.message, Some(&reply.message), params);
+    if !verify(&state.peer, &transcript, &reply.signature, sig_params) {
+        return Err(String::from("the responder signature does not verify"));
+    }
+    Ok(finish_with_identities(state.state, &reply.message, &state.identities, params))
+}
@@ -1,10 +1,13 @@
-use crate::crypto::utils::{Parameters, polymul_fast, polyadd, polyinv, gen_ternary_poly, gen_uniform_poly,compress};
+use crate::crypto::utils::{Parameters, polymul_fast, polyadd, polyinv, gen_ternary_poly, gen_uniform_poly_from_seed, gen_seed, pad_coeffs, seed_to_words, words_to_seed, compress, SEED_BYTES, SEED_WORDS};
+use crate::crypto::kdf::{derive_bytes, derive_path_key, derive_reader, sub_seed};
+use sha3::digest::XofReader;
+use crate::crypto::module_lwe::module_keygen_string;
 use polynomial_ring::Polynomial;
 use std::collections::HashMap;
 
 /// Generate a public and secret key pair
 /// # Arguments:
-///	* `params` - ring-LWE parameters
+///	* `params` - ring-LWE parameters; panics for module rank k > 1, use `module_keygen`
 /// * `seed` - random seed
 /// # Returns:
 ///	(public key, secret key)
@@ -14,18 +17,143 @@ use std::collections::HashMap;
 /// let (pk, sk) = ring_lwe::keygen::keygen(&params, None);
 /// ```
 pub fn keygen(params: &Parameters, seed: Option<u64>) -> ([Polynomial<i64>; 2], Polynomial<i64>) {
+    let ((b, seed_a), sk) = keygen_compact(params, seed);
+
+    // Return public key (b, a) as an array and secret key (sk)
+    ([b, expand_a(&seed_a, params)], sk)
+}
+
+/// Generate a key pair whose public polynomial `a` is expanded from a seed
+/// # Arguments:
+/// * `params` - ring-LWE parameters
+/// * `seed` - random seed
+/// # Returns:
+/// (compact public key (b, seed_a), secret key)
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let ((b, seed_a), sk) = ring_lwe::keygen::keygen_compact(&params, None);
+/// let a = ring_lwe::keygen::expand_a(&seed_a, &params);
+/// ```
+pub fn keygen_compact(params: &Parameters, seed: Option<u64>) -> ((Polynomial<i64>, [u8; SEED_BYTES]), Polynomial<i64>) {
+    // the seed of a, the secret and the error each come from their own stream
+    let seed_a = gen_seed(sub_seed(seed, "keygen matrix", 0));
+    let sk = gen_ternary_poly(params.n, sub_seed(seed, "keygen secret", 0));
+    let e = gen_ternary_poly(params.n, sub_seed(seed, "keygen error", 0));
+    keygen_from_parts(params, seed_a, sk, e)
+}
+
+/// Build a compact key pair from the seed of `a` and the secret and error polynomials
+fn keygen_from_parts(params: &Parameters, seed_a: [u8; SEED_BYTES], sk: Polynomial<i64>, e: Polynomial<i64>) -> ((Polynomial<i64>, [u8; SEED_BYTES]), Polynomial<i64>) {
+
+    assert_eq!(params.k, 1, "ring-LWE keygen needs k = 1, use module_keygen for module-LWE keys");
 
     //rename parameters
-    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let (q, f, omega) = (params.q, &params.f, params.omega);
 
     // Generate a public and secret key
-    let sk = gen_ternary_poly(n, seed);
-    let a = gen_uniform_poly(n, q, seed);
-    let e = gen_ternary_poly(n, seed);
-    let b = polyadd(&polymul_fast(&polyinv(&a,q), &sk, q, &f, omega), &polyinv(&e,q), q, &f); // b = -a*sk - e
-    
-    // Return public key (b, a) as an array and secret key (sk)
-    ([b, a], sk)
+    let a = expand_a(&seed_a, params);
+    let b = polyadd(&polymul_fast(&polyinv(&a,q), &sk, q, f, omega), &polyinv(&e,q), q, f); // b = -a*sk - e
+
+    ((b, seed_a), sk)
+}
+
+/// Deterministically derive a key pair from a master seed and a hierarchical path
+/// # Arguments:
+/// * `master_seed` - root secret, e.g. derived from the user's master password
+/// * `path` - slash-separated derivation path such as `user/epoch/3`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (compact public key (b, seed_a), secret key), identical every time for the same inputs
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let master_seed = [42u8; 32];
+/// let (pk, sk) = ring_lwe::keygen::keygen_from_seed(&master_seed, "alice/epoch/3", &params);
+/// assert_eq!(sk, ring_lwe::keygen::keygen_from_seed(&master_seed, "alice/epoch/3", &params).1);
+/// ```
+pub fn keygen_from_seed(master_seed: &[u8], path: &str, params: &Parameters) -> ((Polynomial<i64>, [u8; SEED_BYTES]), Polynomial<i64>) {
+    // keys at different paths are independent, and each part of a key has its own seed
+    let node_key = derive_path_key(master_seed, path);
+    let seed_a = derive_bytes("ring-lwe public seed", &[&node_key]);
+    let sk = ternary_from_key(params.n, "ring-lwe secret key", &node_key);
+    let e = ternary_from_key(params.n, "ring-lwe key error", &node_key);
+    keygen_from_parts(params, seed_a, sk, e)
+}
+
+/// Sample a uniform ternary polynomial from SHAKE-256(label || key), reading two bits per
+/// coefficient and rejecting the value 3, so it carries the full entropy of the key
+pub(crate) fn ternary_from_key(n: usize, label: &str, key: &[u8]) -> Polynomial<i64> {
+    let mut reader = derive_reader(label, &[key]);
+    let mut coeffs = Vec::with_capacity(n);
+    let mut byte = [0u8; 1];
+    while coeffs.len() < n {
+        reader.read(&mut byte);
+        for shift in (0..8).step_by(2) {
+            let bits = (byte[0] >> shift) & 3;
+            if bits < 3 && coeffs.len() < n {
+                coeffs.push(bits as i64 - 1);
+            }
+        }
+    }
+    Polynomial::new(coeffs)
+}
+
+/// Deterministically derive a key pair and return it as base64 encoded strings
+/// # Arguments:
+/// * `master_seed` - root secret
+/// * `path` - slash-separated derivation path such as `user/epoch/3`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// HashMap containing public and secret keys in the format of `keygen_string`
+pub fn keygen_from_seed_string(master_seed: &[u8], path: &str, params: &Parameters) -> HashMap<String, String> {
+    assert_eq!(params.k, 1, "hierarchical derivation produces ring-LWE keys");
+    let ((b, seed_a), sk) = keygen_from_seed(master_seed, path, params);
+    key_strings(&b, &seed_a, &sk, params)
+}
+
+/// Serialize a compact key pair into the HashMap returned by `keygen_string`
+fn key_strings(b: &Polynomial<i64>, seed_a: &[u8; SEED_BYTES], sk: &Polynomial<i64>, params: &Parameters) -> HashMap<String, String> {
+    // Publish b and the seed of a rather than all coefficients of a
+    let mut pk_coeffs: Vec<i64> = Vec::with_capacity(params.n + SEED_WORDS);
+    pk_coeffs.extend(pad_coeffs(b, params.n));
+    pk_coeffs.extend(seed_to_words(seed_a));
+
+    // Store public/secret keys in a HashMap
+    let mut keys: HashMap<String, String> = HashMap::new();
+    keys.insert(String::from("secret"), compress(&sk.coeffs().to_vec()));
+    keys.insert(String::from("public"), compress(&pk_coeffs));
+    keys
+}
+
+/// Expand the public polynomial `a` from its seed
+/// # Arguments:
+/// * `seed_a` - 32-byte public seed
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// uniform polynomial in Z_q[X]/(x^n+1)
+pub fn expand_a(seed_a: &[u8; SEED_BYTES], params: &Parameters) -> Polynomial<i64> {
+    gen_uniform_poly_from_seed(params.n, params.q, seed_a, 0)
+}
+
+/// Rebuild the public key (b, a) from its serialized coefficients
+/// # Arguments:
+/// * `pk_coeffs` - either b followed by the seed of `a` (compact), or b followed by a
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// public key as an array of two polynomials, or an error unless there are exactly n + SEED_WORDS
+/// or 2n coefficients
+pub fn public_key_from_coeffs(pk_coeffs: &[i64], params: &Parameters) -> Result<[Polynomial<i64>; 2], String> {
+    let n = params.n;
+    let a = if pk_coeffs.len() == n + SEED_WORDS {
+        expand_a(&words_to_seed(&pk_coeffs[n..]), params)
+    } else if pk_coeffs.len() == 2 * n {
+        // keys generated before seed expansion carry all n coefficients of a
+        Polynomial::new(pk_coeffs[n..].to_vec())
+    } else {
+        return Err(String::from("wrong length for a public key"));
+    };
+    Ok([Polynomial::new(pk_coeffs[..n].to_vec()), a])
 }
 
 /// Generate a public and secret key pair and return as a HashMap
@@ -33,7 +161,8 @@ pub fn keygen(params: &Parameters, seed: Option<u64>) -> ([Polynomial<i64>; 2],
 ///	* `params` - ring-LWE parameters
 /// * `seed` - random seed
 /// # Returns:
-///	HashMap containing public and secret keys as base64 encoded strings
+///	HashMap containing public and secret keys as base64 encoded strings, the public key
+/// holding b and the seed of a
 /// # Example:
 /// ```
 /// let params = ring_lwe::utils::Parameters::default();
@@ -42,16 +171,11 @@ pub fn keygen(params: &Parameters, seed: Option<u64>) -> ([Polynomial<i64>; 2],
 /// let sk_string = keys.get("secret").unwrap();
 /// ```
 pub fn keygen_string(params: &Parameters, seed: Option<u64>) -> HashMap<String, String> {
-    // Generate keys using parameters
-    let (pk, sk) = keygen(params, seed);
-
-    let mut pk_coeffs: Vec<i64> = Vec::with_capacity(2 * params.n);
-    pk_coeffs.extend(pk[0].coeffs());
-    pk_coeffs.extend(pk[1].coeffs());
+    if params.k > 1 {
+        return module_keygen_string(params, seed);
+    }
 
-    // Store public/secret keys in a HashMap
-    let mut keys: HashMap<String, String> = HashMap::new();
-    keys.insert(String::from("secret"), compress(&sk.coeffs().to_vec()));
-    keys.insert(String::from("public"), compress(&pk_coeffs));
-    keys
+    // Generate keys using parameters
+    let ((b, seed_a), sk) = keygen_compact(params, seed);
+    key_strings(&b, &seed_a, &sk, params)
 }
\ No newline at end of file
@@ -2,6 +2,28 @@ pub mod keygen;
 pub mod encrypt;
 pub mod decrypt;
 pub mod utils;
+pub mod noise;
+pub mod security;
+pub mod search;
+pub mod module_lwe;
+pub mod kdf;
+pub mod secret_key;
+pub mod armor;
+pub mod seal;
+pub mod signature;
+pub mod signcrypt;
+pub mod hybrid;
+pub mod key_exchange;
+pub mod channel;
+pub mod prekey;
+pub mod ratchet;
+pub mod threshold;
+pub mod dkg;
+pub mod decryption_proof;
+pub mod possession;
+pub mod validate;
+pub mod blind;
+pub mod anonymous;
 
 #[cfg(test)]
 mod tests;
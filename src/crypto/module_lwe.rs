@@ -0,0 +1,273 @@
+use crate::crypto::utils::{Parameters, mod_coeffs, polymul_fast, polyadd, polyinv, polysub, gen_ternary_poly, gen_uniform_poly_from_seed, gen_seed, nearest_int, pad_coeffs, seed_to_words, words_to_seed, compress, decompress, try_decompress, SEED_BYTES, SEED_WORDS};
+use crate::crypto::encrypt::message_blocks;
+use crate::crypto::decrypt::bits_to_message;
+use crate::crypto::kdf::sub_seed;
+use polynomial_ring::Polynomial;
+use std::collections::HashMap;
+
+/// Module-LWE public key (b, A) with b a vector of k ring elements and A a k x k matrix
+pub type ModulePublicKey = (Vec<Polynomial<i64>>, Vec<Vec<Polynomial<i64>>>);
+
+/// Compact module-LWE public key (b, seed_A), with the matrix expanded from the seed
+pub type ModuleCompactPublicKey = (Vec<Polynomial<i64>>, [u8; SEED_BYTES]);
+
+/// Module-LWE ciphertext (c0, c1) with c0 a ring element and c1 a vector of k ring elements
+pub type ModuleCiphertext = (Polynomial<i64>, Vec<Polynomial<i64>>);
+
+/// Inner product of two vectors of ring elements
+/// # Arguments:
+/// * `x` - vector of polynomials
+/// * `y` - vector of polynomials of the same length
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// sum of x[i]*y[i] in Z_q[X]/(x^n+1)
+pub fn inner_product(x: &[Polynomial<i64>], y: &[Polynomial<i64>], params: &Parameters) -> Polynomial<i64> {
+    let (q, f, omega) = (params.q, &params.f, params.omega);
+    x.iter().zip(y).fold(Polynomial::new(vec![]), |acc, (xi, yi)| {
+        polyadd(&acc, &polymul_fast(xi, yi, q, f, omega), q, f)
+    })
+}
+
+/// Product of a k x k matrix of ring elements with a vector, optionally transposed
+fn matrix_vector(a: &[Vec<Polynomial<i64>>], v: &[Polynomial<i64>], transpose: bool, params: &Parameters) -> Vec<Polynomial<i64>> {
+    (0..params.k)
+        .map(|i| {
+            let row: Vec<Polynomial<i64>> = (0..params.k)
+                .map(|j| if transpose { a[j][i].clone() } else { a[i][j].clone() })
+                .collect();
+            inner_product(&row, v, params)
+        })
+        .collect()
+}
+
+/// Generate a module-LWE public and secret key pair
+/// # Arguments:
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// * `seed` - random seed
+/// # Returns:
+/// (public key (b, A), secret key vector s) with b = -A*s - e
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::new(256, 7681, 2).with_rank(3);
+/// let (pk, sk) = ring_lwe::module_lwe::module_keygen(&params, None);
+/// assert_eq!(pk.1.len(), 3);
+/// assert_eq!(sk.len(), 3);
+/// ```
+pub fn module_keygen(params: &Parameters, seed: Option<u64>) -> (ModulePublicKey, Vec<Polynomial<i64>>) {
+    let ((b, seed_a), sk) = module_keygen_compact(params, seed);
+    ((b, expand_matrix(&seed_a, params)), sk)
+}
+
+/// Generate a module-LWE key pair whose public matrix is expanded from a seed
+/// # Arguments:
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// * `seed` - random seed
+/// # Returns:
+/// (compact public key (b, seed_A), secret key vector s)
+pub fn module_keygen_compact(params: &Parameters, seed: Option<u64>) -> (ModuleCompactPublicKey, Vec<Polynomial<i64>>) {
+    let (n, q, k, f) = (params.n, params.q, params.k, &params.f);
+
+    // Sample the secret and error vectors from separate streams
+    let seed_a = gen_seed(sub_seed(seed, "module keygen matrix", 0));
+    let a = expand_matrix(&seed_a, params);
+    let sk: Vec<Polynomial<i64>> = (0..k).map(|i| gen_ternary_poly(n, sub_seed(seed, "module keygen secret", i))).collect();
+    let e: Vec<Polynomial<i64>> = (0..k).map(|i| gen_ternary_poly(n, sub_seed(seed, "module keygen error", i))).collect();
+
+    // b = -A*s - e
+    let b = matrix_vector(&a, &sk, false, params)
+        .iter()
+        .zip(&e)
+        .map(|(as_i, e_i)| polyadd(&polyinv(as_i, q), &polyinv(e_i, q), q, f))
+        .collect();
+
+    ((b, seed_a), sk)
+}
+
+/// Expand the public k x k matrix from its seed
+/// # Arguments:
+/// * `seed_a` - 32-byte public seed
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// # Returns:
+/// matrix whose entry (i, j) is expanded with nonce 256*i + j
+pub fn expand_matrix(seed_a: &[u8; SEED_BYTES], params: &Parameters) -> Vec<Vec<Polynomial<i64>>> {
+    (0..params.k)
+        .map(|i| (0..params.k).map(|j| gen_uniform_poly_from_seed(params.n, params.q, seed_a, (256 * i + j) as u16)).collect())
+        .collect()
+}
+
+/// Encrypt a polynomial using a module-LWE public key
+/// # Arguments:
+/// * `pk` - public key (b, A)
+/// * `m` - plaintext polynomial
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// * `seed` - random seed
+/// # Returns:
+/// ciphertext (c0, c1) with c0 = <b,u> + e1 + floor(q/t)*m and c1 = A^T*u + e2
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::new(256, 7681, 2).with_rank(2);
+/// let (pk, sk) = ring_lwe::module_lwe::module_keygen(&params, None);
+/// let m = polynomial_ring::Polynomial::new(vec![1, 0, 1]);
+/// let ct = ring_lwe::module_lwe::module_encrypt(&pk, &m, &params, None);
+/// assert_eq!(ring_lwe::module_lwe::module_decrypt(&sk, &ct, &params), m);
+/// ```
+pub fn module_encrypt(pk: &ModulePublicKey, m: &Polynomial<i64>, params: &Parameters, seed: Option<u64>) -> ModuleCiphertext {
+    let (n, q, t, k, f) = (params.n, params.q, params.t, params.k, &params.f);
+    let (b, a) = pk;
+    // Scale the plaintext polynomial. use floor(m*q/t) rather than floor (q/t)*m
+    let scaled_m = mod_coeffs(m * q / t, q);
+
+    // Generate random vectors
+    let u: Vec<Polynomial<i64>> = (0..k).map(|i| gen_ternary_poly(n, sub_seed(seed, "module encrypt u", i))).collect();
+    let e2: Vec<Polynomial<i64>> = (0..k).map(|i| gen_ternary_poly(n, sub_seed(seed, "module encrypt e2", i))).collect();
+    let e1 = gen_ternary_poly(n, sub_seed(seed, "module encrypt e1", 0));
+
+    // Compute ciphertext components
+    let c0 = polyadd(&polyadd(&inner_product(b, &u, params), &e1, q, f), &scaled_m, q, f);
+    let c1 = matrix_vector(a, &u, true, params)
+        .iter()
+        .zip(&e2)
+        .map(|(atu_i, e2_i)| polyadd(atu_i, e2_i, q, f))
+        .collect();
+
+    (c0, c1)
+}
+
+/// Decrypt a module-LWE ciphertext using the secret key vector
+/// # Arguments:
+/// * `sk` - secret key vector
+/// * `ct` - ciphertext (c0, c1)
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// # Returns:
+/// decrypted polynomial, the rounding of t/q * (c0 + <c1,s>)
+pub fn module_decrypt(sk: &[Polynomial<i64>], ct: &ModuleCiphertext, params: &Parameters) -> Polynomial<i64> {
+    let (q, t, f) = (params.q, params.t, &params.f);
+    let scaled_pt = polyadd(&inner_product(&ct.1, sk, params), &ct.0, q, f);
+    let decrypted_coeffs = scaled_pt.coeffs().iter()
+        .map(|c| nearest_int(c * t, q).rem_euclid(t))
+        .collect();
+    Polynomial::new(decrypted_coeffs)
+}
+
+/// Decrypt a module-LWE ciphertext and measure the noise it carries
+/// # Arguments:
+/// * `sk` - secret key vector
+/// * `ct` - ciphertext (c0, c1)
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// # Returns:
+/// (decrypted polynomial, infinity norm of the noise term), as for `decrypt_with_noise`
+pub fn module_decrypt_with_noise(sk: &[Polynomial<i64>], ct: &ModuleCiphertext, params: &Parameters) -> (Polynomial<i64>, i64) {
+    let (q, t, f) = (params.q, params.t, &params.f);
+    let m = module_decrypt(sk, ct, params);
+    let scaled_pt = polyadd(&inner_product(&ct.1, sk, params), &ct.0, q, f);
+    let noise = polysub(&scaled_pt, &mod_coeffs(&m * q / t, q), q, f);
+    let noise_norm = noise.coeffs().iter().map(|c| c.abs()).max().unwrap_or(0);
+    (m, noise_norm)
+}
+
+/// Generate a module-LWE key pair as base64 encoded strings
+/// # Arguments:
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// * `seed` - random seed
+/// # Returns:
+/// HashMap with the public key b || seed_A and the secret key vector, each polynomial
+/// padded to n coefficients
+pub fn module_keygen_string(params: &Parameters, seed: Option<u64>) -> HashMap<String, String> {
+    let ((b, seed_a), sk) = module_keygen_compact(params, seed);
+
+    let mut pk_coeffs: Vec<i64> = Vec::with_capacity(params.k * params.n + SEED_WORDS);
+    b.iter().for_each(|p| pk_coeffs.extend(pad_coeffs(p, params.n)));
+    pk_coeffs.extend(seed_to_words(&seed_a));
+    let sk_coeffs: Vec<i64> = sk.iter().flat_map(|p| pad_coeffs(p, params.n)).collect();
+
+    let mut keys: HashMap<String, String> = HashMap::new();
+    keys.insert(String::from("secret"), compress(&sk_coeffs));
+    keys.insert(String::from("public"), compress(&pk_coeffs));
+    keys
+}
+
+/// Split a flat coefficient vector into polynomials of n coefficients
+fn split_polys(coeffs: &[i64], n: usize) -> Vec<Polynomial<i64>> {
+    coeffs.chunks(n).map(|chunk| Polynomial::new(chunk.to_vec())).collect()
+}
+
+/// Decode a public key b || seed_A, or b || A with the full matrix in row major order
+fn public_key_from_string(pk_base64: &str, params: &Parameters) -> ModulePublicKey {
+    let (n, k) = (params.n, params.k);
+    let pk_coeffs = decompress(pk_base64);
+    let b = split_polys(&pk_coeffs[..k * n], n);
+    let a = if pk_coeffs.len() == k * n + SEED_WORDS {
+        expand_matrix(&words_to_seed(&pk_coeffs[k * n..]), params)
+    } else {
+        // the full matrix, row major
+        split_polys(&pk_coeffs[k * n..], n).chunks(k).map(|row| row.to_vec()).collect()
+    };
+    (b, a)
+}
+
+/// Encrypt a string using a module-LWE public key
+/// # Arguments:
+/// * `pk_base64` - public key b || seed_A as produced by `module_keygen_string`, or b || A
+/// * `message` - message to encrypt
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// * `seed` - random seed
+/// # Returns:
+/// base64 encoded blocks of c0 || c1, each polynomial padded to n coefficients
+pub fn module_encrypt_string(pk_base64: &str, message: &str, params: &Parameters, seed: Option<u64>) -> String {
+    let n = params.n;
+    let pk = public_key_from_string(pk_base64, params);
+
+    let mut ciphertext_list: Vec<i64> = Vec::new();
+    for message_block in message_blocks(message, n) {
+        let (c0, c1) = module_encrypt(&pk, &message_block, params, seed);
+        ciphertext_list.extend(pad_coeffs(&c0, n));
+        c1.iter().for_each(|p| ciphertext_list.extend(pad_coeffs(p, n)));
+    }
+    compress(&ciphertext_list)
+}
+
+/// Rerandomize a module-LWE ciphertext string by adding a fresh encryption of zero to each block
+/// # Arguments:
+/// * `pk_base64` - public key as produced by `module_keygen_string`
+/// * `ciphertext_base64` - ciphertext from `module_encrypt_string`
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// # Returns:
+/// a new ciphertext of the same message, or an error for a malformed ciphertext
+pub fn module_rerandomize_string(pk_base64: &str, ciphertext_base64: &str, params: &Parameters) -> Result<String, String> {
+    let (n, q, k, f) = (params.n, params.q, params.k, &params.f);
+    let pk = public_key_from_string(pk_base64, params);
+
+    let coeffs = try_decompress(ciphertext_base64)?;
+    if coeffs.is_empty() || coeffs.len() % ((k + 1) * n) != 0 {
+        return Err(String::from("wrong length for a ciphertext"));
+    }
+    let mut ciphertext_list: Vec<i64> = Vec::with_capacity(coeffs.len());
+    for block in coeffs.chunks((k + 1) * n) {
+        let polys = split_polys(block, n);
+        let (z0, z1) = module_encrypt(&pk, &Polynomial::new(vec![]), params, None);
+        ciphertext_list.extend(pad_coeffs(&polyadd(&polys[0], &z0, q, f), n));
+        polys[1..].iter().zip(&z1).for_each(|(c, z)| ciphertext_list.extend(pad_coeffs(&polyadd(c, z, q, f), n)));
+    }
+    Ok(compress(&ciphertext_list))
+}
+
+/// Decrypt a string encrypted with `module_encrypt_string`
+/// # Arguments:
+/// * `sk_base64` - secret key vector as produced by `module_keygen_string`
+/// * `ciphertext_base64` - base64 encoded ciphertext
+/// * `params` - ring-LWE parameters with module rank `params.k`
+/// # Returns:
+/// decrypted plaintext message
+pub fn module_decrypt_string(sk_base64: &str, ciphertext_base64: &str, params: &Parameters) -> String {
+    let (n, k) = (params.n, params.k);
+    let sk = split_polys(&decompress(sk_base64), n);
+    let ciphertext_array = decompress(ciphertext_base64);
+
+    let mut decrypted_bits: Vec<i64> = Vec::new();
+    for block in ciphertext_array.chunks((k + 1) * n) {
+        let polys = split_polys(block, n);
+        let ct = (polys[0].clone(), polys[1..].to_vec());
+        decrypted_bits.extend(pad_coeffs(&module_decrypt(&sk, &ct, params), n));
+    }
+    bits_to_message(&decrypted_bits)
+}
@@ -0,0 +1,137 @@
+use crate::crypto::utils::Parameters;
+
+/// Variance of a coefficient drawn uniformly from {-1,0,+1}
+const TERNARY_VARIANCE: f64 = 2.0 / 3.0;
+
+/// Natural logarithm of the complementary error function erfc(x) for x >= 0
+/// # Arguments:
+/// * `x` - non-negative real number
+/// # Returns:
+/// ln(erfc(x)), computed without underflow for large x (relative error below 1.2e-7)
+fn ln_erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x);
+    let poly = -1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+        + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+        + t * (-0.82215223 + t * 0.17087277))))))));
+    t.ln() - x * x + poly
+}
+
+impl Parameters {
+    /// Variance of a single coefficient of the decryption noise
+    /// # Returns:
+    /// variance of the noise term -<e,u> + e1 + <e2,sk>, where keygen and encrypt draw
+    /// every secret and error polynomial uniformly from {-1,0,+1}
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// assert!(params.noise_variance() > 0.0);
+    /// ```
+    pub fn noise_variance(&self) -> f64 {
+        self.joint_noise_variance(1)
+    }
+
+    /// Variance of a single coefficient of the decryption noise under a key summed from several
+    /// independent keys, as distributed key generation produces
+    /// # Arguments:
+    /// * `key_parties` - number of ternary secret and error polynomials summed into the key
+    /// # Returns:
+    /// `noise_variance()` with the key terms <e,u> and <e2,sk> scaled by `key_parties`
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// assert_eq!(params.joint_noise_variance(1), params.noise_variance());
+    /// assert!(params.joint_noise_variance(5) > 4.9 * params.noise_variance());
+    /// ```
+    pub fn joint_noise_variance(&self, key_parties: usize) -> f64 {
+        // each coefficient of <e,u> and <e2,sk> is a sum of n*k products of u or e2 with a sum
+        // of key_parties ternary values
+        let product_variance = TERNARY_VARIANCE * TERNARY_VARIANCE;
+        2.0 * (self.n * self.k * key_parties) as f64 * product_variance + TERNARY_VARIANCE
+    }
+
+    /// Largest noise coefficient that still decrypts correctly
+    /// # Returns:
+    /// bound B such that every noise coefficient with |v| <= B rounds back to the plaintext
+    pub fn noise_bound(&self) -> i64 {
+        // the extra -1 absorbs the floor in the plaintext scaling floor(m*q/t)
+        self.q / (2 * self.t) - 1
+    }
+
+    /// Base-2 logarithm of the decryption failure probability of one ciphertext block
+    /// # Returns:
+    /// log2 of the union bound over n coefficients of P(|v| > B), with v approximated by a
+    /// centered Gaussian of variance `noise_variance()`
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// assert!(params.failure_probability_log2() < -128.0);
+    /// ```
+    pub fn failure_probability_log2(&self) -> f64 {
+        let sigma = self.noise_variance().sqrt();
+        let z = (self.noise_bound() as f64 + 0.5) / (sigma * std::f64::consts::SQRT_2);
+        let ln_p = (self.n as f64).ln() + ln_erfc(z);
+        (ln_p / std::f64::consts::LN_2).min(0.0)
+    }
+
+    /// Variance of a coefficient of the decryption noise of a product of depth+1 fresh ciphertexts
+    /// # Arguments:
+    /// * `depth` - number of ciphertext multiplications
+    /// # Returns:
+    /// variance of the error left after tensoring depth+1 ciphertexts and decoding at scale
+    /// (q/t)^(depth+1), as `test_hom_prod` does; equal to `noise_variance()` for depth 0
+    ///
+    /// The product of the phases q/t*m_i + v_i expands into terms with j noise polynomials and
+    /// depth+1-j plaintexts, scaled down by t^(depth+1-j). A coefficient of a product of j noise
+    /// polynomials has variance at most j!*n^(j-1)*sigma^(2j): the j! counts every pairing of the
+    /// factors, since ciphertexts under one key share e and s and their noise is correlated.
+    /// Multiplying by plaintexts with binary coefficients scales it by at most n^(2l-1) for l
+    /// plaintext factors.
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// assert_eq!(params.product_noise_variance(0), params.noise_variance());
+    /// assert!(params.product_noise_variance(1) > params.noise_variance());
+    /// ```
+    pub fn product_noise_variance(&self, depth: u32) -> f64 {
+        let (n, t, factors) = (self.n as f64, self.t as f64, depth as i32 + 1);
+        let sigma2 = self.noise_variance();
+        let mut binomial = 1.0;
+        let mut variance = 0.0;
+        let pairings = |j: i32| (1..=j).map(f64::from).product::<f64>();
+        for noise_factors in (1..=factors).rev() {
+            let plaintext_factors = factors - noise_factors;
+            // ways to pick the noise factors, C(depth+1, noise_factors)
+            if plaintext_factors > 0 {
+                binomial = binomial * (factors - plaintext_factors + 1) as f64 / plaintext_factors as f64;
+            }
+            let noise_product = pairings(noise_factors) * n.powi(noise_factors - 1) * sigma2.powi(noise_factors);
+            let plaintext_growth = if plaintext_factors > 0 { n.powi(2 * plaintext_factors - 1) } else { 1.0 };
+            variance += binomial * noise_product * plaintext_growth / t.powi(2 * plaintext_factors);
+        }
+        variance
+    }
+
+    /// Base-2 logarithm of the decryption failure probability of a product of depth+1 fresh
+    /// ciphertexts, per block
+    /// # Arguments:
+    /// * `depth` - number of ciphertext multiplications
+    /// # Returns:
+    /// log2 of the union bound over n coefficients of P(|v| > q/(2 t^(depth+1))), with v a
+    /// centered Gaussian of variance `product_noise_variance(depth)`; equal to
+    /// `failure_probability_log2()` for depth 0
+    pub fn product_failure_probability_log2(&self, depth: u32) -> f64 {
+        let sigma = self.product_noise_variance(depth).sqrt();
+        let bound = self.q / (2 * self.t.pow(depth + 1)) - 1;
+        let z = (bound as f64 + 0.5) / (sigma * std::f64::consts::SQRT_2);
+        let ln_p = (self.n as f64).ln() + ln_erfc(z.max(0.0));
+        (ln_p / std::f64::consts::LN_2).min(0.0)
+    }
+
+    /// Decryption failure probability of one ciphertext block
+    /// # Returns:
+    /// probability that at least one coefficient decrypts incorrectly, which underflows to
+    /// 0.0 for the default parameters; use `failure_probability_log2` to compare parameter sets
+    pub fn failure_probability(&self) -> f64 {
+        self.failure_probability_log2().exp2()
+    }
+}
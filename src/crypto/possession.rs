@@ -0,0 +1,66 @@
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::seal::{wrap_data_key, unwrap_data_key, DATA_KEY_BYTES};
+use crate::crypto::utils::Parameters;
+use crate::crypto::validate::validate_public_key;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+/// Response to a challenge: a hash of the decrypted nonce, the public key and the challenge
+///
+/// The registrant never returns the plaintext itself, so a server that passes off someone
+/// else's ciphertext as a challenge learns nothing about it.
+fn response_for(nonce: &[u8], public_key: &str, challenge: &str) -> String {
+    let response: [u8; KEY_BYTES] = derive_bytes("ring-lwe key possession", &[nonce, public_key.as_bytes(), challenge.as_bytes()]);
+    response.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Encrypt a fresh challenge to a public key submitted for registration
+/// # Arguments:
+/// * `public_key` - public key from `keygen_string`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// (base64 challenge for the registrant, response to expect), or an error for a malformed key
+/// # Example:
+/// ```
+/// use ring_lwe::possession::{possession_challenge, possession_response, possession_verified};
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+/// let (challenge, expected) = possession_challenge(pk, &params).unwrap();
+/// let response = possession_response(sk, pk, &challenge, &params).unwrap();
+/// assert!(possession_verified(&expected, &response));
+/// ```
+pub fn possession_challenge(public_key: &str, params: &Parameters) -> Result<(String, String), String> {
+    validate_public_key(public_key, params)?;
+    let mut nonce = [0u8; DATA_KEY_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let challenge = wrap_data_key(public_key, &nonce, params);
+    let expected = response_for(&nonce, public_key, &challenge);
+    Ok((challenge, expected))
+}
+
+/// Answer a challenge, proving knowledge of the secret key
+/// # Arguments:
+/// * `secret_key` - secret key of the key being registered
+/// * `public_key` - the public key being registered
+/// * `challenge` - base64 challenge from `possession_challenge`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the response, or an error when the challenge does not decrypt with this secret key
+pub fn possession_response(secret_key: &str, public_key: &str, challenge: &str, params: &Parameters) -> Result<String, String> {
+    let nonce = unwrap_data_key(secret_key, challenge, params)
+        .ok_or_else(|| String::from("the challenge does not decrypt with this secret key"))?;
+    Ok(response_for(&nonce, public_key, challenge))
+}
+
+/// Compare a response with the expected one in constant time
+/// # Arguments:
+/// * `expected` - expected response from `possession_challenge`
+/// * `response` - response from the registrant
+/// # Returns:
+/// whether the registrant decrypted the challenge
+pub fn possession_verified(expected: &str, response: &str) -> bool {
+    let response = response.trim();
+    expected.len() == response.len()
+        && expected.bytes().zip(response.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
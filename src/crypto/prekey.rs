@@ -0,0 +1,246 @@
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::keygen::keygen_string;
+use crate::crypto::seal::{wrap_data_key, unwrap_data_key, DATA_KEY_BYTES, NONCE_BYTES};
+use crate::crypto::signature::{sign, verify, signing_key_from_string, verifying_key_from_string, signature_to_string, signature_from_string, SignatureParameters, SigningKey};
+use crate::crypto::utils::Parameters;
+use crate::crypto::validate::validate_public_key;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of the bytes signed for a prekey, keeping them apart from other signed messages
+const PREKEY_CONTEXT: &[u8] = b"ring-lwe one-time prekey\0";
+
+/// Prefix signed for a last-resort prekey instead, so a one-time prekey cannot be passed off
+/// as one that may be handed out many times
+const LAST_RESORT_CONTEXT: &[u8] = b"ring-lwe last-resort prekey\0";
+
+const PREKEY_ID_BYTES: usize = 16;
+
+/// A ring-LWE prekey signed by the recipient's long-term signing key: one-time, or the
+/// last-resort prekey senders fall back to when no one-time prekey is available
+#[derive(Serialize, Deserialize)]
+struct SignedPrekey {
+    id: String,
+    public_key: String,
+    last_resort: bool,
+    /// creation time in seconds since the Unix epoch, so a store can refuse an older
+    /// last-resort prekey replayed over a newer one
+    created: u64,
+    signature: String,
+}
+
+/// A message encrypted to a recipient's long-term key and one of their prekeys
+#[derive(Serialize, Deserialize)]
+struct PrekeyMessage {
+    /// id of the prekey used, so the recipient can find and then delete its secret
+    prekey_id: String,
+    /// key share encrypted to the long-term public key
+    identity_share: String,
+    /// key share encrypted to the one-time prekey
+    prekey_share: String,
+    nonce: [u8; NONCE_BYTES],
+    body: Vec<u8>,
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    general_purpose::STANDARD.encode(bincode::serialize(value).expect("Failed to serialize data"))
+}
+
+fn decode<T: DeserializeOwned>(base64_str: &str, what: &str) -> Result<T, String> {
+    let bytes = general_purpose::STANDARD.decode(base64_str.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    bincode::deserialize(&bytes).map_err(|_| format!("malformed {}", what))
+}
+
+fn signed_bytes(prekey: &SignedPrekey) -> Vec<u8> {
+    let mut bytes = if prekey.last_resort { LAST_RESORT_CONTEXT.to_vec() } else { PREKEY_CONTEXT.to_vec() };
+    bytes.extend(bincode::serialize(&(&prekey.id, &prekey.public_key, prekey.created)).expect("Failed to serialize data"));
+    bytes
+}
+
+/// Message key from both shares and the headers, so it stays secret while either the
+/// long-term key or the prekey does; once the prekey secret is deleted, a later leak of the
+/// long-term key no longer opens the message
+fn message_key(identity_share: &[u8], prekey_share: &[u8], message: &PrekeyMessage) -> [u8; KEY_BYTES] {
+    derive_bytes("ring-lwe prekey message", &[
+        identity_share,
+        prekey_share,
+        message.prekey_id.as_bytes(),
+        message.identity_share.as_bytes(),
+        message.prekey_share.as_bytes(),
+    ])
+}
+
+/// Generate a batch of signed one-time prekeys
+/// # Arguments:
+/// * `signing_key` - the recipient's long-term signing key from `sign_keygen_string`
+/// * `count` - number of prekeys
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// (signed prekey to publish, prekey secret key to keep until the prekey is used) pairs, or an
+/// error for a malformed signing key
+pub fn generate_prekeys(signing_key: &str, count: usize, params: &Parameters, sig_params: &SignatureParameters) -> Result<Vec<(String, String)>, String> {
+    let signing_key = signing_key_from_string(signing_key, sig_params)?;
+    Ok((0..count).map(|_| signed_prekey(&signing_key, false, params, sig_params)).collect())
+}
+
+/// Generate the signed last-resort prekey, used when an owner has no one-time prekeys left
+/// # Arguments:
+/// * `signing_key` - the recipient's long-term signing key from `sign_keygen_string`
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// (signed prekey to publish, its secret key), or an error for a malformed signing key
+///
+/// The last-resort prekey may encrypt many messages, so deleting its secret after one message
+/// is not possible and those messages only get forward secrecy once the recipient replaces it.
+pub fn generate_last_resort_prekey(signing_key: &str, params: &Parameters, sig_params: &SignatureParameters) -> Result<(String, String), String> {
+    let signing_key = signing_key_from_string(signing_key, sig_params)?;
+    Ok(signed_prekey(&signing_key, true, params, sig_params))
+}
+
+fn signed_prekey(signing_key: &SigningKey, last_resort: bool, params: &Parameters, sig_params: &SignatureParameters) -> (String, String) {
+    let keys = keygen_string(params, None);
+    let mut id = [0u8; PREKEY_ID_BYTES];
+    OsRng.fill_bytes(&mut id);
+    let id: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).expect("system clock before 1970").as_secs();
+    let mut prekey = SignedPrekey { id, public_key: keys.get("public").unwrap().clone(), last_resort, created, signature: String::new() };
+    prekey.signature = signature_to_string(&sign(signing_key, &signed_bytes(&prekey), sig_params), sig_params);
+    (encode(&prekey), keys.get("secret").unwrap().clone())
+}
+
+/// Check a one-time prekey signature
+/// # Arguments:
+/// * `verifying_key` - the recipient's long-term verifying key
+/// * `prekey` - signed prekey from `generate_prekeys`
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// the prekey id, or an error when the prekey is malformed, not signed by `verifying_key` or a
+/// last-resort prekey
+pub fn verify_prekey(verifying_key: &str, prekey: &str, sig_params: &SignatureParameters) -> Result<String, String> {
+    match verified_prekey(verifying_key, prekey, sig_params)? {
+        prekey if !prekey.last_resort => Ok(prekey.id),
+        _ => Err(String::from("this is a last-resort prekey, not a one-time prekey")),
+    }
+}
+
+/// Check a last-resort prekey signature
+/// # Arguments:
+/// * `verifying_key` - the recipient's long-term verifying key
+/// * `prekey` - signed prekey from `generate_last_resort_prekey`
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// (prekey id, signed creation time in seconds since the Unix epoch), or an error when the
+/// prekey is malformed, not signed by `verifying_key` or a one-time prekey
+pub fn verify_last_resort_prekey(verifying_key: &str, prekey: &str, sig_params: &SignatureParameters) -> Result<(String, u64), String> {
+    match verified_prekey(verifying_key, prekey, sig_params)? {
+        prekey if prekey.last_resort => Ok((prekey.id, prekey.created)),
+        _ => Err(String::from("this is a one-time prekey, not a last-resort prekey")),
+    }
+}
+
+fn verified_prekey(verifying_key: &str, prekey: &str, sig_params: &SignatureParameters) -> Result<SignedPrekey, String> {
+    let prekey: SignedPrekey = decode(prekey, "prekey")?;
+    let verifying_key = verifying_key_from_string(verifying_key, sig_params)?;
+    let signature = signature_from_string(&prekey.signature, sig_params)?;
+    if !verify(&verifying_key, &signed_bytes(&prekey), &signature, sig_params) {
+        return Err(String::from("the prekey is not signed by this key"));
+    }
+    Ok(prekey)
+}
+
+/// Encrypt a message to a recipient's long-term key and one of their prekeys
+/// # Arguments:
+/// * `identity_pk` - the recipient's long-term public key from `keygen_string`
+/// * `verifying_key` - the recipient's long-term verifying key, which signed the prekey
+/// * `prekey` - signed prekey from `generate_prekeys`, used for this message only, or the
+///   last-resort prekey from `generate_last_resort_prekey`
+/// * `message` - message to encrypt
+/// * `params` - ring-LWE parameters
+/// * `sig_params` - signature parameters
+/// # Returns:
+/// base64 encoded message, or an error when the prekey signature does not verify or the
+/// long-term public key is malformed
+/// # Example:
+/// ```
+/// use ring_lwe::prekey::{generate_prekeys, prekey_encrypt, prekey_id, prekey_decrypt};
+/// use ring_lwe::signature::{sign_keygen_string, SignatureParameters};
+/// let (params, sig_params) = (ring_lwe::utils::Parameters::default(), SignatureParameters::default());
+/// let identity = ring_lwe::keygen::keygen_string(&params, None);
+/// let signing = sign_keygen_string(&sig_params, None);
+/// let prekeys = generate_prekeys(signing.get("secret").unwrap(), 2, &params, &sig_params).unwrap();
+///
+/// let (prekey, prekey_secret) = &prekeys[0];
+/// let ciphertext = prekey_encrypt(identity.get("public").unwrap(), signing.get("public").unwrap(), prekey, "hello", &params, &sig_params).unwrap();
+/// assert_eq!(prekey_id(&ciphertext), ring_lwe::prekey::verify_prekey(signing.get("public").unwrap(), prekey, &sig_params));
+/// assert_eq!(prekey_decrypt(identity.get("secret").unwrap(), prekey_secret, &ciphertext, &params), Ok(String::from("hello")));
+/// ```
+pub fn prekey_encrypt(
+    identity_pk: &str,
+    verifying_key: &str,
+    prekey: &str,
+    message: &str,
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+) -> Result<String, String> {
+    let prekey = verified_prekey(verifying_key, prekey, sig_params)?;
+    validate_public_key(identity_pk, params)?;
+
+    let mut identity_share = [0u8; DATA_KEY_BYTES];
+    let mut prekey_share = [0u8; DATA_KEY_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut identity_share);
+    OsRng.fill_bytes(&mut prekey_share);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut envelope = PrekeyMessage {
+        prekey_id: prekey.id,
+        identity_share: wrap_data_key(identity_pk, &identity_share, params),
+        prekey_share: wrap_data_key(&prekey.public_key, &prekey_share, params),
+        nonce,
+        body: Vec::new(),
+    };
+    let key = message_key(&identity_share, &prekey_share, &envelope);
+    envelope.body = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: message.as_bytes(), aad: envelope.prekey_id.as_bytes() })
+        .expect("Failed to encrypt message");
+    Ok(encode(&envelope))
+}
+
+/// Read which prekey a message was encrypted to
+/// # Arguments:
+/// * `ciphertext` - base64 message from `prekey_encrypt`
+/// # Returns:
+/// the prekey id, or an error for a malformed message
+pub fn prekey_id(ciphertext: &str) -> Result<String, String> {
+    decode::<PrekeyMessage>(ciphertext, "prekey message").map(|message| message.prekey_id)
+}
+
+/// Decrypt a message from `prekey_encrypt`; the caller deletes a one-time prekey secret
+/// afterwards
+/// # Arguments:
+/// * `identity_sk` - the recipient's long-term secret key
+/// * `prekey_sk` - secret key of the prekey named by `prekey_id`
+/// * `ciphertext` - base64 message from `prekey_encrypt`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the message, or an error when it is malformed or does not decrypt with these keys
+pub fn prekey_decrypt(identity_sk: &str, prekey_sk: &str, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+    let message: PrekeyMessage = decode(ciphertext, "prekey message")?;
+    let plaintext = unwrap_data_key(identity_sk, &message.identity_share, params)
+        .zip(unwrap_data_key(prekey_sk, &message.prekey_share, params))
+        .and_then(|(identity_share, prekey_share)| {
+            let key = message_key(&identity_share, &prekey_share, &message);
+            Aes256Gcm::new(&key.into())
+                .decrypt(Nonce::from_slice(&message.nonce), Payload { msg: &message.body, aad: message.prekey_id.as_bytes() })
+                .ok()
+        })
+        .ok_or_else(|| String::from("the message does not decrypt with these keys"))?;
+    String::from_utf8(plaintext).map_err(|e| format!("message is not UTF-8: {}", e))
+}
@@ -0,0 +1,330 @@
+use crate::crypto::armor::fingerprint;
+use crate::crypto::kdf::{derive_bytes, KEY_BYTES};
+use crate::crypto::keygen::keygen_string;
+use crate::crypto::seal::{wrap_data_key, unwrap_data_key, DATA_KEY_BYTES, NONCE_BYTES};
+use crate::crypto::utils::Parameters;
+use crate::crypto::validate::validate_public_key;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Messages sent on one chain before the sender mixes in a fresh KEM step
+pub const KEM_INTERVAL: u32 = 10;
+
+/// Most message keys kept for messages that have not arrived yet
+const MAX_SKIPPED: usize = 256;
+
+/// Symmetric chain: each message key is derived from the chain key, which then moves on
+#[derive(Serialize, Deserialize, Clone)]
+struct Chain {
+    key: [u8; KEY_BYTES],
+    index: u32,
+}
+
+impl Chain {
+    fn next(&mut self) -> [u8; KEY_BYTES] {
+        let message_key = derive_bytes("ring-lwe ratchet message", &[&self.key]);
+        self.key = derive_bytes("ring-lwe ratchet chain", &[&self.key]);
+        self.index += 1;
+        message_key
+    }
+}
+
+/// A ring-LWE key pair this side advertised for the peer to encapsulate to
+#[derive(Serialize, Deserialize, Clone)]
+struct RatchetKey {
+    fingerprint: String,
+    public_key: String,
+    secret_key: String,
+}
+
+/// Fresh secret encrypted to the peer's latest ratchet key, starting a sending epoch
+#[derive(Serialize, Deserialize, Clone)]
+struct KemStep {
+    target: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    epoch: u32,
+    index: u32,
+    /// messages sent in the previous epoch, so the receiver can keep their keys
+    previous_length: u32,
+    /// the sender's latest ratchet public key, for the receiver's next KEM step
+    ratchet_key: String,
+    /// repeated in every message of the epoch, so any of them can start it
+    kem: KemStep,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RatchetMessage {
+    header: Header,
+    nonce: [u8; NONCE_BYTES],
+    body: Vec<u8>,
+}
+
+/// Ratchet state of one side of one mail thread
+///
+/// Each direction has its own root key, so both sides can start KEM steps without
+/// coordinating. The state holds secret keys; persist it encrypted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThreadSession {
+    thread_id: String,
+    send_root: [u8; KEY_BYTES],
+    receive_root: [u8; KEY_BYTES],
+    sending: Option<Chain>,
+    receiving: Option<Chain>,
+    send_epoch: u32,
+    receive_epoch: u32,
+    send_kem: Option<KemStep>,
+    previous_length: u32,
+    /// own ratchet keys the peer may still encapsulate to, oldest first
+    own_keys: Vec<RatchetKey>,
+    peer_key: Option<String>,
+    skipped: BTreeMap<(u32, u32), [u8; KEY_BYTES]>,
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    general_purpose::STANDARD.encode(bincode::serialize(value).expect("Failed to serialize data"))
+}
+
+fn decode<T: DeserializeOwned>(base64_str: &str, what: &str) -> Result<T, String> {
+    let bytes = general_purpose::STANDARD.decode(base64_str.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    bincode::deserialize(&bytes).map_err(|_| format!("malformed {}", what))
+}
+
+/// Initial root key of one direction of a thread
+fn initial_root(thread_id: &str, responder_key: &str, direction: &str) -> Result<[u8; KEY_BYTES], String> {
+    Ok(derive_bytes("ring-lwe ratchet root", &[thread_id.as_bytes(), fingerprint(responder_key)?.as_bytes(), direction.as_bytes()]))
+}
+
+/// Mix a KEM secret into a root key, giving the next root key and a new chain key
+fn mix(root: &[u8; KEY_BYTES], secret: &[u8], kem: &KemStep) -> ([u8; KEY_BYTES], [u8; KEY_BYTES]) {
+    let inputs: [&[u8]; 4] = [root, secret, kem.target.as_bytes(), kem.ciphertext.as_bytes()];
+    (derive_bytes("ring-lwe ratchet root step", &inputs), derive_bytes("ring-lwe ratchet chain start", &inputs))
+}
+
+fn aad(thread_id: &str, header: &Header) -> Vec<u8> {
+    bincode::serialize(&(thread_id, header)).expect("Failed to serialize data")
+}
+
+fn open_body(key: &[u8; KEY_BYTES], message: &RatchetMessage, aad: &[u8]) -> Result<String, String> {
+    let plaintext = Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(&message.nonce), Payload { msg: &message.body, aad })
+        .map_err(|_| String::from("the message does not decrypt in this thread"))?;
+    String::from_utf8(plaintext).map_err(|e| format!("message is not UTF-8: {}", e))
+}
+
+impl ThreadSession {
+    /// Start a thread as the first sender
+    /// # Arguments:
+    /// * `thread_id` - id of the mail thread, bound into every message
+    /// * `peer_public_key` - the recipient's long-term public key, which the first KEM step targets
+    /// # Returns:
+    /// session ready to encrypt the first message, or an error when the key is not base64
+    /// # Example:
+    /// ```
+    /// use ring_lwe::ratchet::ThreadSession;
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// let bob_keys = ring_lwe::keygen::keygen_string(&params, None);
+    /// let mut alice = ThreadSession::initiate("thread-1", bob_keys.get("public").unwrap()).unwrap();
+    /// let mut bob = ThreadSession::accept("thread-1", bob_keys.get("public").unwrap(), bob_keys.get("secret").unwrap()).unwrap();
+    ///
+    /// let first = alice.encrypt("hi bob", &params).unwrap();
+    /// assert_eq!(bob.decrypt(&first, &params), Ok(String::from("hi bob")));
+    /// let reply = bob.encrypt("hi alice", &params).unwrap();
+    /// assert_eq!(alice.decrypt(&reply, &params), Ok(String::from("hi alice")));
+    /// ```
+    pub fn initiate(thread_id: &str, peer_public_key: &str) -> Result<Self, String> {
+        Ok(ThreadSession {
+            thread_id: thread_id.to_string(),
+            send_root: initial_root(thread_id, peer_public_key, "initiator")?,
+            receive_root: initial_root(thread_id, peer_public_key, "responder")?,
+            sending: None,
+            receiving: None,
+            send_epoch: 0,
+            receive_epoch: 0,
+            send_kem: None,
+            previous_length: 0,
+            own_keys: Vec::new(),
+            peer_key: Some(peer_public_key.to_string()),
+            skipped: BTreeMap::new(),
+        })
+    }
+
+    /// Join a thread as its first recipient
+    /// # Arguments:
+    /// * `thread_id` - id of the mail thread
+    /// * `public_key` - own long-term public key, which the initiator's first message targets
+    /// * `secret_key` - the matching secret key
+    /// # Returns:
+    /// session that can encrypt once it has decrypted the first message, or an error when the
+    /// key is not base64
+    pub fn accept(thread_id: &str, public_key: &str, secret_key: &str) -> Result<Self, String> {
+        Ok(ThreadSession {
+            thread_id: thread_id.to_string(),
+            send_root: initial_root(thread_id, public_key, "responder")?,
+            receive_root: initial_root(thread_id, public_key, "initiator")?,
+            sending: None,
+            receiving: None,
+            send_epoch: 0,
+            receive_epoch: 0,
+            send_kem: None,
+            previous_length: 0,
+            own_keys: vec![RatchetKey {
+                fingerprint: fingerprint(public_key)?,
+                public_key: public_key.to_string(),
+                secret_key: secret_key.to_string(),
+            }],
+            peer_key: None,
+            skipped: BTreeMap::new(),
+        })
+    }
+
+    /// Encrypt the next message of the thread
+    ///
+    /// The first message and every `KEM_INTERVAL`-th one after it start a new epoch: a fresh
+    /// secret is encapsulated to the peer's latest ratchet key and mixed into the root key, and
+    /// a new ratchet key of our own is advertised. Other messages only advance the chain.
+    /// # Arguments:
+    /// * `message` - message to encrypt
+    /// * `params` - ring-LWE parameters
+    /// # Returns:
+    /// base64 encoded message, or an error when this side has not received a message yet
+    pub fn encrypt(&mut self, message: &str, params: &Parameters) -> Result<String, String> {
+        let peer_key = self.peer_key.clone().ok_or_else(|| String::from("no message has been received in this thread yet"))?;
+        if self.sending.as_ref().is_none_or(|chain| chain.index >= KEM_INTERVAL) {
+            let keys = keygen_string(params, None);
+            let public_key = keys.get("public").unwrap().clone();
+            self.own_keys.push(RatchetKey {
+                fingerprint: fingerprint(&public_key).expect("keygen_string produces base64"),
+                public_key,
+                secret_key: keys.get("secret").unwrap().clone(),
+            });
+
+            let mut secret = [0u8; DATA_KEY_BYTES];
+            OsRng.fill_bytes(&mut secret);
+            let kem = KemStep { target: fingerprint(&peer_key)?, ciphertext: wrap_data_key(&peer_key, &secret, params) };
+            let (root, chain) = mix(&self.send_root, &secret, &kem);
+            self.previous_length = self.sending.as_ref().map_or(0, |chain| chain.index);
+            self.send_root = root;
+            self.sending = Some(Chain { key: chain, index: 0 });
+            self.send_epoch += 1;
+            self.send_kem = Some(kem);
+        }
+
+        let chain = self.sending.as_mut().unwrap();
+        let header = Header {
+            epoch: self.send_epoch,
+            index: chain.index,
+            previous_length: self.previous_length,
+            ratchet_key: self.own_keys.last().unwrap().public_key.clone(),
+            kem: self.send_kem.clone().unwrap(),
+        };
+        let key = chain.next();
+        let mut nonce = [0u8; NONCE_BYTES];
+        OsRng.fill_bytes(&mut nonce);
+        let body = Aes256Gcm::new(&key.into())
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: message.as_bytes(), aad: &aad(&self.thread_id, &header) })
+            .expect("Failed to encrypt message");
+        Ok(encode(&RatchetMessage { header, nonce, body }))
+    }
+
+    /// Decrypt a message from the peer
+    ///
+    /// Messages may arrive out of order within an epoch and across one epoch boundary; a message
+    /// from an epoch whose predecessor has not been seen at all is rejected until it has. The
+    /// state only changes when the message decrypts.
+    /// # Arguments:
+    /// * `ciphertext` - base64 message from the peer's `encrypt`
+    /// * `params` - ring-LWE parameters
+    /// # Returns:
+    /// the message, or an error when it is malformed, replayed, from another thread, or arrives
+    /// too early
+    pub fn decrypt(&mut self, ciphertext: &str, params: &Parameters) -> Result<String, String> {
+        let message: RatchetMessage = decode(ciphertext, "thread message")?;
+        let header = &message.header;
+        let aad = aad(&self.thread_id, header);
+
+        if let Some(key) = self.skipped.get(&(header.epoch, header.index)) {
+            let plaintext = open_body(key, &message, &aad)?;
+            self.skipped.remove(&(header.epoch, header.index));
+            return Ok(plaintext);
+        }
+
+        let mut next = self.clone();
+        if header.epoch == next.receive_epoch + 1 {
+            if next.receiving.is_some() {
+                next.skip_to(header.previous_length)?;
+            }
+            let target = next.own_keys.iter().position(|key| key.fingerprint == header.kem.target)
+                .ok_or_else(|| String::from("the message is encrypted to an unknown ratchet key"))?;
+            let secret = unwrap_data_key(&next.own_keys[target].secret_key, &header.kem.ciphertext, params)
+                .ok_or_else(|| String::from("the message does not decrypt in this thread"))?;
+            let (root, chain) = mix(&next.receive_root, &secret, &header.kem);
+            next.receive_root = root;
+            next.receiving = Some(Chain { key: chain, index: 0 });
+            next.receive_epoch += 1;
+            // the peer has seen the target key, so it will never use an older one again
+            next.own_keys.drain(..target);
+            // the peer's next key is only ever used to encrypt, so it has to be well formed
+            validate_public_key(&header.ratchet_key, params)?;
+            next.peer_key = Some(header.ratchet_key.clone());
+        } else if header.epoch > next.receive_epoch {
+            return Err(String::from("an earlier message of this thread is missing"));
+        } else if header.epoch < next.receive_epoch || next.receiving.is_none() {
+            return Err(String::from("the message key was already used or has expired"));
+        }
+
+        next.skip_to(header.index)?;
+        let key = next.receiving.as_mut().unwrap().next();
+        let plaintext = open_body(&key, &message, &aad)?;
+        *self = next;
+        Ok(plaintext)
+    }
+
+    /// Advance the receiving chain to `index`, keeping the keys of messages not yet received
+    fn skip_to(&mut self, index: u32) -> Result<(), String> {
+        let chain = self.receiving.as_mut().unwrap();
+        if index < chain.index {
+            return Err(String::from("the message key was already used or has expired"));
+        }
+        if (index - chain.index) as usize > MAX_SKIPPED {
+            return Err(String::from("too many messages of this thread are missing"));
+        }
+        while chain.index < index {
+            let skipped_index = chain.index;
+            self.skipped.insert((self.receive_epoch, skipped_index), chain.next());
+        }
+        while self.skipped.len() > MAX_SKIPPED {
+            self.skipped.pop_first();
+        }
+        Ok(())
+    }
+
+    /// Serialize the session for storage, e.g. per thread in QMail
+    /// # Returns:
+    /// base64 encoded state, including secret keys
+    pub fn export_state(&self) -> String {
+        encode(self)
+    }
+
+    /// Restore a session saved with `export_state`
+    /// # Arguments:
+    /// * `state` - base64 encoded state
+    /// # Returns:
+    /// the session, or an error for malformed state
+    pub fn import_state(state: &str) -> Result<Self, String> {
+        decode(state, "thread session")
+    }
+
+    /// Id of the thread this session belongs to
+    pub fn thread_id(&self) -> &str {
+        &self.thread_id
+    }
+}
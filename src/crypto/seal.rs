@@ -0,0 +1,126 @@
+use crate::crypto::decrypt::decrypt_string;
+use crate::crypto::encrypt::encrypt_string;
+use crate::crypto::utils::{Parameters, try_decompress};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Length in bytes of the AES-256-GCM data key
+pub(crate) const DATA_KEY_BYTES: usize = 32;
+pub(crate) const NONCE_BYTES: usize = 12;
+
+/// A message encrypted once for several recipients
+#[derive(Serialize, Deserialize)]
+struct SealedMessage {
+    /// the data key encrypted to each recipient public key, in no particular order
+    recipients: Vec<String>,
+    nonce: [u8; NONCE_BYTES],
+    /// AES-256-GCM encryption of the message under the data key
+    body: Vec<u8>,
+}
+
+/// Encrypt a message once for several recipients
+/// # Arguments:
+/// * `public_keys` - recipient public keys as produced by `keygen_string`
+/// * `message` - message to encrypt
+/// * `params` - parameters shared by all recipient keys
+/// # Returns:
+/// base64 encoded sealed message: the body encrypted once under a random data key, and the
+/// data key encrypted to each recipient. Entries carry no recipient identifiers, so To, Cc
+/// and Bcc recipients only learn that a message has several recipients.
+/// # Example:
+/// ```
+/// let params = ring_lwe::utils::Parameters::default();
+/// let alice = ring_lwe::keygen::keygen_string(&params, None);
+/// let bob = ring_lwe::keygen::keygen_string(&params, None);
+/// let public_keys = [alice.get("public").unwrap().clone(), bob.get("public").unwrap().clone()];
+/// let sealed = ring_lwe::seal::seal(&public_keys, "hello", &params);
+/// assert_eq!(ring_lwe::seal::open(bob.get("secret").unwrap(), &sealed, &params), Ok(String::from("hello")));
+/// ```
+pub fn seal(public_keys: &[String], message: &str, params: &Parameters) -> String {
+    let mut data_key = [0u8; DATA_KEY_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut data_key);
+    OsRng.fill_bytes(&mut nonce);
+
+    let recipients: Vec<String> = public_keys.iter()
+        .map(|pk| wrap_data_key(pk, &data_key, params))
+        .collect();
+
+    // binding the recipient list stops an outsider from swapping entries unnoticed
+    let aad = bincode::serialize(&recipients).expect("Failed to serialize data");
+    let body = Aes256Gcm::new(&data_key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: message.as_bytes(), aad: &aad })
+        .expect("Failed to encrypt message");
+
+    let sealed = SealedMessage { recipients, nonce, body };
+    general_purpose::STANDARD.encode(bincode::serialize(&sealed).expect("Failed to serialize data"))
+}
+
+/// Open a sealed message with one recipient's secret key
+/// # Arguments:
+/// * `secret_key` - secret key of one of the recipients, as produced by `keygen_string`
+/// * `sealed` - base64 encoded sealed message from `seal`
+/// * `params` - parameters the message was sealed under
+/// # Returns:
+/// the message, or an error when the sealed message is malformed or not addressed to this key
+pub fn open(secret_key: &str, sealed: &str, params: &Parameters) -> Result<String, String> {
+    let bytes = general_purpose::STANDARD.decode(sealed.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    let sealed: SealedMessage = bincode::deserialize(&bytes).map_err(|e| format!("invalid sealed message: {}", e))?;
+    let aad = bincode::serialize(&sealed.recipients).expect("Failed to serialize data");
+
+    // entries are anonymous, so try each until one yields a data key that authenticates the body
+    for entry in &sealed.recipients {
+        let Some(data_key) = unwrap_data_key(secret_key, entry, params) else { continue };
+        let plaintext = Aes256Gcm::new(&data_key.into())
+            .decrypt(Nonce::from_slice(&sealed.nonce), Payload { msg: &sealed.body, aad: &aad });
+        if let Ok(plaintext) = plaintext {
+            return String::from_utf8(plaintext).map_err(|e| format!("message is not UTF-8: {}", e));
+        }
+    }
+    Err(String::from("the message is not sealed to this key"))
+}
+
+/// Encrypt a data key to a public key
+/// # Arguments:
+/// * `public_key` - recipient public key as produced by `keygen_string`
+/// * `data_key` - AES-256-GCM key
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// base64 encoded ciphertext of the key
+pub(crate) fn wrap_data_key(public_key: &str, data_key: &[u8; DATA_KEY_BYTES], params: &Parameters) -> String {
+    // hex keeps the data key within the byte range encrypt_string and decrypt_string round trip
+    let data_key_hex: String = data_key.iter().map(|b| format!("{:02x}", b)).collect();
+    encrypt_string(&public_key.to_string(), &data_key_hex, params, None)
+}
+
+/// Decrypt a data key encrypted with `wrap_data_key`
+/// # Returns:
+/// the key, or None when either input is malformed or the ciphertext does not decrypt to a key;
+/// a wrong secret key can still yield a key by chance, which the AEAD tag of the body then rejects
+pub(crate) fn unwrap_data_key(secret_key: &str, wrapped: &str, params: &Parameters) -> Option<[u8; DATA_KEY_BYTES]> {
+    let (n, q, k) = (params.n, params.q, params.k);
+    // wrapped keys come from the network, so check their shape before decrypt_string slices them
+    let sk = try_decompress(secret_key).ok()?;
+    let ciphertext = try_decompress(wrapped).ok()?;
+    let block = (k + 1) * n;
+    if sk.len() > k * n || ciphertext.is_empty() || ciphertext.len() % block != 0 || ciphertext.iter().any(|c| c.abs() >= q) {
+        return None;
+    }
+    parse_data_key(&decrypt_string(&secret_key.to_string(), &wrapped.to_string(), params))
+}
+
+/// Parse a hex data key, rejecting the garbage a non-recipient key decrypts to
+fn parse_data_key(hex: &str) -> Option<[u8; DATA_KEY_BYTES]> {
+    if hex.len() != 2 * DATA_KEY_BYTES || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; DATA_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(key)
+}
@@ -0,0 +1,117 @@
+use crate::crypto::utils::Parameters;
+
+/// (ring degree, module rank) pairs tried by the search, using module ranks to fill the gaps
+/// between power-of-two ring degrees
+const RING_SHAPES: [(usize, usize); 8] = [(128, 1), (256, 1), (256, 2), (256, 3), (256, 4), (512, 1), (1024, 1), (2048, 1)];
+
+/// Largest working modulus the NTT can use without its i64 products overflowing
+pub const MAX_MODULUS: i64 = 1 << 31;
+
+/// Goals a parameter set has to meet
+#[derive(Debug, Clone)]
+pub struct SearchTarget {
+    /// minimum classical core-SVP security in bits
+    pub security_bits: f64,
+    /// maximum decryption failure probability of one ciphertext block
+    pub max_failure: f64,
+    /// number of ciphertext multiplications the parameters must support
+    pub depth: u32,
+    /// plaintext modulus
+    pub t: i64,
+}
+
+/// A parameter set meeting a search target, with its estimated costs
+#[derive(Debug)]
+pub struct Candidate {
+    /// the parameters, with the working modulus q = p^(depth+1)
+    pub params: Parameters,
+    /// NTT-friendly prime p = 1 mod 2n underlying the working modulus
+    pub prime: i64,
+    /// classical core-SVP security in bits
+    pub security_bits: f64,
+    /// base-2 logarithm of the decryption failure probability per block after `depth` products
+    pub failure_log2: f64,
+    /// size of one ciphertext block as serialized by `compress`
+    pub ciphertext_bytes: usize,
+    /// size of one ciphertext block with coefficients packed to ceil(log2 q) bits
+    pub packed_ciphertext_bytes: usize,
+}
+
+/// Deterministic primality test by trial division, fast enough below `MAX_MODULUS`
+pub(crate) fn is_prime(p: i64) -> bool {
+    if p < 2 {
+        return false;
+    }
+    let mut d = 2;
+    while d * d <= p {
+        if p % d == 0 {
+            return false;
+        }
+        d += 1;
+    }
+    true
+}
+
+/// Enumerate NTT-friendly primes for a ring degree
+/// # Arguments:
+/// * `n` - ring degree, a power of two
+/// * `limit` - exclusive upper bound on the primes
+/// # Returns:
+/// iterator over the primes p < limit with p = 1 mod 2n, in increasing order
+/// # Example:
+/// ```
+/// let primes: Vec<i64> = ring_lwe::search::ntt_primes(1024, 20000).collect();
+/// assert_eq!(primes, vec![12289, 18433]);
+/// ```
+pub fn ntt_primes(n: usize, limit: i64) -> impl Iterator<Item = i64> {
+    let step = 2 * n as i64;
+    (1..).map(move |k| k * step + 1).take_while(move |&p| p < limit).filter(|&p| is_prime(p))
+}
+
+/// Search for parameter sets meeting a security level, failure rate and multiplicative depth
+/// # Arguments:
+/// * `target` - goals the parameters have to meet
+/// # Returns:
+/// at most one candidate per ring degree and module rank, using the smallest prime that meets
+/// the failure target since it also gives the most security, ordered by ciphertext size
+///
+/// A depth-d circuit runs at the working modulus q = p^(d+1) for an NTT-friendly prime p, as
+/// `test_hom_prod` does for one multiplication. The failure rate is that of a product of d+1
+/// fresh ciphertexts at q, from `product_failure_probability_log2`, and security is estimated on q.
+/// # Example:
+/// ```
+/// use ring_lwe::search::{search, SearchTarget};
+/// let target = SearchTarget { security_bits: 128.0, max_failure: 1e-40, depth: 0, t: 2 };
+/// let candidates = search(&target);
+/// assert!(candidates.iter().all(|c| c.security_bits >= 128.0));
+/// ```
+pub fn search(target: &SearchTarget) -> Vec<Candidate> {
+    let max_failure_log2 = target.max_failure.log2();
+    let mut candidates = vec![];
+    for (n, k) in RING_SHAPES {
+        // the working modulus p^(depth+1) has to stay below the NTT overflow limit
+        let prime_limit = (MAX_MODULUS as f64).powf(1.0 / (target.depth + 1) as f64).ceil() as i64;
+        let working = |p: i64| Parameters::new(n, p.pow(target.depth + 1), target.t).with_rank(k);
+        let prime = ntt_primes(n, prime_limit)
+            .filter(|&p| p > 2 * target.t && p.pow(target.depth + 1) < MAX_MODULUS)
+            .find(|&p| working(p).product_failure_probability_log2(target.depth) <= max_failure_log2);
+        let Some(prime) = prime else { continue };
+        let params = working(prime);
+        let q = params.q;
+        let security_bits = params.security_bits();
+        if security_bits < target.security_bits {
+            continue;
+        }
+        let coeff_bits = 64 - (q - 1).leading_zeros() as usize;
+        candidates.push(Candidate {
+            failure_log2: params.product_failure_probability_log2(target.depth),
+            ciphertext_bytes: 8 + (k + 1) * n * 8,
+            packed_ciphertext_bytes: ((k + 1) * n * coeff_bits).div_ceil(8),
+            params,
+            prime,
+            security_bits,
+        });
+    }
+    candidates.sort_by_key(|c| c.packed_ciphertext_bytes);
+    candidates
+}
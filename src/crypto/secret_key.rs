@@ -0,0 +1,341 @@
+use crate::crypto::kdf::derive_bytes;
+use crate::crypto::utils::{compress, decompress};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use polynomial_ring::Polynomial;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+/// Magic bytes opening every password-protected secret key container
+const MAGIC: &[u8; 6] = b"RLWESK";
+/// Current container version
+const VERSION: u8 = 1;
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 12;
+/// magic, version, three u32 Argon2id costs, salt and nonce
+const HEADER_BYTES: usize = MAGIC.len() + 1 + 12 + SALT_BYTES + NONCE_BYTES;
+/// Largest Argon2id costs accepted from a container header: those `export_encrypted` writes,
+/// since imports run on unauthenticated service requests
+const MAX_M_COST: u32 = Params::DEFAULT_M_COST;
+const MAX_T_COST: u32 = Params::DEFAULT_T_COST;
+const MAX_P_COST: u32 = Params::DEFAULT_P_COST;
+
+/// Prefix of every printable recovery share
+const SHARE_PREFIX: &str = "RLWE-SHARE";
+const SET_ID_BYTES: usize = 4;
+const CHECKSUM_BYTES: usize = 4;
+/// Formats of the shared key bytes: five trits per byte, or little-endian i64 coefficients
+const TERNARY_FORMAT: u8 = 0;
+const WIDE_FORMAT: u8 = 1;
+
+/// A ring-LWE or module-LWE secret key, held as the coefficients `keygen_string` serializes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretKey {
+    coeffs: Vec<i64>,
+}
+
+impl SecretKey {
+    /// Wrap the coefficients of a secret key polynomial
+    pub fn from_polynomial(sk: &Polynomial<i64>) -> Self {
+        SecretKey { coeffs: sk.coeffs().to_vec() }
+    }
+
+    /// Decode a secret key produced by `keygen_string`
+    pub fn from_base64(sk_base64: &str) -> Self {
+        SecretKey { coeffs: decompress(sk_base64) }
+    }
+
+    /// Encode the secret key as `keygen_string` does
+    pub fn to_base64(&self) -> String {
+        compress(&self.coeffs)
+    }
+
+    /// Coefficients of the secret key, polynomial after polynomial for module-LWE keys
+    pub fn coeffs(&self) -> &[i64] {
+        &self.coeffs
+    }
+
+    /// Split the secret key into printable recovery shares, any `k` of which recover it
+    /// # Arguments:
+    /// * `k` - number of shares needed for recovery
+    /// * `n` - number of shares, at most 255
+    /// # Returns:
+    /// one line per share, `RLWE-SHARE-<set>-<k>of<n>-<index>-<hex payload>-<checksum>`, or an
+    /// error for an impossible threshold; fewer than `k` shares reveal nothing about the key
+    /// # Example:
+    /// ```
+    /// use ring_lwe::secret_key::SecretKey;
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// let (_, sk) = ring_lwe::keygen::keygen(&params, None);
+    /// let sk = SecretKey::from_polynomial(&sk);
+    /// let shares = sk.split_backup(2, 3).unwrap();
+    /// assert_eq!(SecretKey::recover_from_shares(&shares[1..]), Ok(sk));
+    /// ```
+    pub fn split_backup(&self, k: usize, n: usize) -> Result<Vec<String>, String> {
+        if k == 0 || k > n || n > 255 {
+            return Err(format!("cannot split into {} shares with threshold {}", n, k));
+        }
+        let secret = backup_bytes(&self.coeffs);
+        let mut set_id = [0u8; SET_ID_BYTES];
+        OsRng.fill_bytes(&mut set_id);
+        let set_id = to_hex(&set_id);
+
+        // one random polynomial of degree k - 1 over GF(256) per secret byte
+        let mut payloads = vec![Vec::with_capacity(secret.len()); n];
+        let mut coeffs = vec![0u8; k];
+        for &byte in &secret {
+            coeffs[0] = byte;
+            OsRng.fill_bytes(&mut coeffs[1..]);
+            for (x, payload) in (1..=n as u8).zip(payloads.iter_mut()) {
+                payload.push(coeffs.iter().rev().fold(0, |acc, &c| gf_mul(acc, x) ^ c));
+            }
+        }
+        Ok(payloads.iter().enumerate()
+            .map(|(i, payload)| {
+                let body = format!("{}-{}-{}of{}-{}-{}", SHARE_PREFIX, set_id, k, n, i + 1, to_hex(payload));
+                let checksum = share_checksum(&body);
+                format!("{}-{}", body, checksum)
+            })
+            .collect())
+    }
+
+    /// Recover a secret key from shares made by `split_backup`
+    /// # Arguments:
+    /// * `shares` - at least k shares of one set, in any order; whitespace inside a share is ignored
+    /// # Returns:
+    /// the secret key, or an error naming a mistyped share, shares from different sets, too
+    /// few shares, or extra shares that do not agree with the others
+    pub fn recover_from_shares(shares: &[String]) -> Result<Self, String> {
+        let shares = shares.iter().map(|share| parse_share(share)).collect::<Result<Vec<_>, _>>()?;
+        let first = shares.first().ok_or_else(|| String::from("no shares given"))?;
+        if shares.iter().any(|share| share.set_id != first.set_id || share.k != first.k || share.n != first.n) {
+            return Err(String::from("the shares come from different backups"));
+        }
+        let mut indices: Vec<u8> = shares.iter().map(|share| share.index).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.len() != shares.len() {
+            return Err(String::from("the same share was given twice"));
+        }
+        if shares.len() < first.k {
+            return Err(format!("{} shares given, {} needed", shares.len(), first.k));
+        }
+        if shares.iter().any(|share| share.payload.len() != first.payload.len()) {
+            return Err(String::from("the shares have different lengths"));
+        }
+
+        let (basis, extra) = shares.split_at(first.k);
+        let at = |x: u8| -> Vec<u8> {
+            (0..first.payload.len())
+                .map(|j| interpolate(basis.iter().map(|share| (share.index, share.payload[j])), x))
+                .collect()
+        };
+        if extra.iter().any(|share| at(share.index) != share.payload) {
+            return Err(String::from("the shares do not agree with each other"));
+        }
+        let coeffs = from_backup_bytes(&at(0))?;
+        Ok(SecretKey { coeffs })
+    }
+
+    /// Encrypt the secret key under a password
+    /// # Arguments:
+    /// * `password` - password the key encryption key is derived from
+    /// # Returns:
+    /// base64 encoded container: "RLWESK", version, Argon2id costs, salt, nonce and the
+    /// AES-256-GCM encryption of the key, with the header authenticated as associated data
+    /// # Example:
+    /// ```
+    /// use ring_lwe::secret_key::SecretKey;
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// let (_, sk) = ring_lwe::keygen::keygen(&params, None);
+    /// let sk = SecretKey::from_polynomial(&sk);
+    /// let wrapped = sk.export_encrypted("correct horse battery staple");
+    /// assert_eq!(SecretKey::import_encrypted(&wrapped, "correct horse battery staple"), Ok(sk));
+    /// ```
+    pub fn export_encrypted(&self, password: &str) -> String {
+        let mut salt = [0u8; SALT_BYTES];
+        let mut nonce = [0u8; NONCE_BYTES];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut container = Vec::with_capacity(HEADER_BYTES);
+        container.extend_from_slice(MAGIC);
+        container.push(VERSION);
+        for cost in [Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST] {
+            container.extend_from_slice(&cost.to_le_bytes());
+        }
+        container.extend_from_slice(&salt);
+        container.extend_from_slice(&nonce);
+
+        let key = derive_key(password, &salt, Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST)
+            .expect("default Argon2id parameters are valid");
+        let plaintext = bincode::serialize(&self.coeffs).expect("Failed to serialize data");
+        let ciphertext = Aes256Gcm::new(&key.into())
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &container })
+            .expect("Failed to encrypt secret key");
+        container.extend_from_slice(&ciphertext);
+        general_purpose::STANDARD.encode(&container)
+    }
+
+    /// Decrypt a secret key exported with `export_encrypted`
+    /// # Arguments:
+    /// * `container_base64` - base64 encoded container
+    /// * `password` - password the key was exported under
+    /// # Returns:
+    /// the secret key, or an error for a malformed container or a wrong password
+    pub fn import_encrypted(container_base64: &str, password: &str) -> Result<Self, String> {
+        let container = general_purpose::STANDARD.decode(container_base64.trim())
+            .map_err(|e| format!("invalid base64: {}", e))?;
+        if container.len() < HEADER_BYTES || &container[..MAGIC.len()] != MAGIC {
+            return Err(String::from("not a password-protected secret key"));
+        }
+        if container[MAGIC.len()] != VERSION {
+            return Err(format!("unsupported secret key container version {}", container[MAGIC.len()]));
+        }
+
+        let (header, ciphertext) = container.split_at(HEADER_BYTES);
+        let cost = |i: usize| {
+            let start = MAGIC.len() + 1 + 4 * i;
+            u32::from_le_bytes(header[start..start + 4].try_into().unwrap())
+        };
+        let salt = &header[MAGIC.len() + 13..MAGIC.len() + 13 + SALT_BYTES];
+        let nonce = &header[HEADER_BYTES - NONCE_BYTES..];
+
+        // a crafted header must not make us allocate or compute without bound
+        if cost(0) > MAX_M_COST || cost(1) > MAX_T_COST || cost(2) > MAX_P_COST {
+            return Err(String::from("Argon2id costs in the container are too large"));
+        }
+        let key = derive_key(password, salt, cost(0), cost(1), cost(2))?;
+        let plaintext = Aes256Gcm::new(&key.into())
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| String::from("wrong password or corrupted secret key"))?;
+        let coeffs = bincode::deserialize(&plaintext).map_err(|e| format!("invalid secret key: {}", e))?;
+        Ok(SecretKey { coeffs })
+    }
+}
+
+/// Derive the AES-256 key encryption key from a password with Argon2id
+fn derive_key(password: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<[u8; 32], String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| format!("invalid Argon2id parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// A parsed recovery share
+struct BackupShare {
+    set_id: String,
+    k: usize,
+    n: usize,
+    index: u8,
+    payload: Vec<u8>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+/// Checksum over everything before it, so a mistyped share is caught before recovery; case
+/// is ignored since shares may be copied by hand
+fn share_checksum(body: &str) -> String {
+    to_hex(&derive_bytes::<CHECKSUM_BYTES>("ring-lwe backup share", &[body.to_ascii_lowercase().as_bytes()]))
+}
+
+fn parse_share(share: &str) -> Result<BackupShare, String> {
+    let share: String = share.split_whitespace().collect::<String>().to_ascii_lowercase();
+    let short = |share: &str| share.chars().take(24).collect::<String>();
+    let (body, checksum) = share.rsplit_once('-').ok_or_else(|| format!("malformed share {}", short(&share)))?;
+    if share_checksum(body) != checksum {
+        return Err(format!("checksum mismatch in share {}; it was probably mistyped", short(&share)));
+    }
+    let fields: Vec<&str> = body.split('-').collect();
+    let malformed = || format!("malformed share {}", short(&share));
+    match fields.as_slice() {
+        ["rlwe", "share", set_id, kn, index, payload] => {
+            let (k, n) = kn.split_once("of").ok_or_else(malformed)?;
+            Ok(BackupShare {
+                set_id: set_id.to_string(),
+                k: k.parse().map_err(|_| malformed())?,
+                n: n.parse().map_err(|_| malformed())?,
+                index: index.parse().ok().filter(|&index| index != 0).ok_or_else(malformed)?,
+                payload: from_hex(payload).ok_or_else(malformed)?,
+            })
+        }
+        _ => Err(malformed()),
+    }
+}
+
+/// Serialize coefficients compactly: ternary keys take a fifth of a byte per coefficient
+fn backup_bytes(coeffs: &[i64]) -> Vec<u8> {
+    let ternary = coeffs.iter().all(|c| (-1..=1).contains(c));
+    let mut bytes = vec![if ternary { TERNARY_FORMAT } else { WIDE_FORMAT }];
+    bytes.extend_from_slice(&(coeffs.len() as u32).to_le_bytes());
+    if ternary {
+        bytes.extend(coeffs.chunks(5).map(|chunk| chunk.iter().rev().fold(0u8, |acc, &c| acc * 3 + (c + 1) as u8)));
+    } else {
+        bytes.extend(coeffs.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    bytes
+}
+
+fn from_backup_bytes(bytes: &[u8]) -> Result<Vec<i64>, String> {
+    let invalid = || String::from("the recovered key is malformed");
+    if bytes.len() < 5 {
+        return Err(invalid());
+    }
+    let len = u32::from_le_bytes(bytes[1..5].try_into().unwrap()) as usize;
+    let body = &bytes[5..];
+    match bytes[0] {
+        TERNARY_FORMAT if body.len() == len.div_ceil(5) => Ok(body.iter()
+            .flat_map(|&byte| (0..5).scan(byte, |rest, _| {
+                let trit = *rest % 3;
+                *rest /= 3;
+                Some(trit as i64 - 1)
+            }))
+            .take(len)
+            .collect()),
+        WIDE_FORMAT if body.len() == 8 * len => Ok(body.chunks(8)
+            .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()),
+        _ => Err(invalid()),
+    }
+}
+
+/// Multiplication in GF(256) modulo x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+        b >>= 1;
+    }
+    product
+}
+
+/// a^-1 = a^254 in GF(256)
+fn gf_inv(a: u8) -> u8 {
+    (0..7).fold((1, gf_mul(a, a)), |(acc, square), _| (gf_mul(acc, square), gf_mul(square, square))).0
+}
+
+/// Value at `x` of the polynomial through `points` (Lagrange interpolation over GF(256))
+fn interpolate(points: impl Iterator<Item = (u8, u8)> + Clone, x: u8) -> u8 {
+    points.clone().fold(0, |acc, (xi, yi)| {
+        let weight = points.clone()
+            .filter(|&(xj, _)| xj != xi)
+            .fold(1, |w, (xj, _)| gf_mul(w, gf_mul(x ^ xj, gf_inv(xi ^ xj))));
+        acc ^ gf_mul(yi, weight)
+    })
+}
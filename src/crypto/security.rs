@@ -0,0 +1,205 @@
+use crate::crypto::utils::Parameters;
+use std::f64::consts::{E, PI};
+
+/// Core-SVP exponent of classical sieving, 2^(0.292 b) for block size b
+const CLASSICAL_SIEVE_EXPONENT: f64 = 0.292;
+/// Core-SVP exponent of quantum sieving, 2^(0.265 b) for block size b
+const QUANTUM_SIEVE_EXPONENT: f64 = 0.265;
+/// Number of short vectors produced by one sieve call, 2^(0.2075 b)
+const SIEVE_OUTPUT_EXPONENT: f64 = 0.2075;
+/// Smallest block size for which the root-Hermite factor estimate is meaningful
+const MIN_BLOCK_SIZE: usize = 50;
+
+/// Recommended floor in classical core-SVP bits: the default target of `params search` and the
+/// level below which `security` prints a warning. Nothing is refused unless a floor is passed to
+/// `require_security` or `--min-security`.
+pub const DEFAULT_MIN_SECURITY_BITS: f64 = 128.0;
+
+/// Core-SVP hardness of a parameter set against the primal and dual lattice attacks
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityEstimate {
+    /// BKZ block size needed by the primal (unique-SVP) attack
+    pub primal_block_size: usize,
+    /// BKZ block size needed by the dual (distinguishing) attack
+    pub dual_block_size: usize,
+    /// classical cost of the primal attack in bits
+    pub primal_classical: f64,
+    /// classical cost of the dual attack in bits
+    pub dual_classical: f64,
+    /// quantum cost of the primal attack in bits
+    pub primal_quantum: f64,
+    /// quantum cost of the dual attack in bits
+    pub dual_quantum: f64,
+}
+
+impl SecurityEstimate {
+    /// Classical security in bits, the cheaper of the two attacks
+    pub fn classical_bits(&self) -> f64 {
+        self.primal_classical.min(self.dual_classical)
+    }
+
+    /// Quantum security in bits, the cheaper of the two attacks
+    pub fn quantum_bits(&self) -> f64 {
+        self.primal_quantum.min(self.dual_quantum)
+    }
+}
+
+/// Natural logarithm of the root-Hermite factor reached by BKZ with block size b
+/// # Arguments:
+/// * `b` - BKZ block size
+/// # Returns:
+/// ln(delta) with delta = ((pi*b)^(1/b) * b/(2*pi*e))^(1/(2(b-1)))
+fn ln_root_hermite(b: usize) -> f64 {
+    let b = b as f64;
+    ((PI * b).ln() / b + (b / (2.0 * PI * E)).ln()) / (2.0 * (b - 1.0))
+}
+
+/// Step between the sample counts tried by the attacks, keeping the search cheap for large n
+fn sample_step(n: usize) -> usize {
+    std::cmp::max(1, n / 256)
+}
+
+/// Smallest block size for which the primal attack recovers the secret
+/// # Arguments:
+/// * `n` - LWE dimension
+/// * `q` - modulus
+/// * `sigma` - standard deviation of the secret and error coefficients
+/// # Returns:
+/// block size b minimised over the number of samples m <= n
+fn primal_block_size(n: usize, q: f64, sigma: f64) -> usize {
+    let mut best = n + n + 1;
+    for m in (sample_step(n)..=n).step_by(sample_step(n)) {
+        let d = n + m + 1;
+        // success once sigma*sqrt(b) <= delta^(2b-d-1) * q^(m/d)
+        let found = (MIN_BLOCK_SIZE..std::cmp::min(best, d)).find(|&b| {
+            let lhs = sigma.ln() + 0.5 * (b as f64).ln();
+            let rhs = (2.0 * b as f64 - d as f64 - 1.0) * ln_root_hermite(b) + (m as f64 / d as f64) * q.ln();
+            lhs <= rhs
+        });
+        if let Some(b) = found {
+            best = b;
+        }
+    }
+    best
+}
+
+/// Cheapest dual attack, trading BKZ block size against the number of repetitions
+/// # Arguments:
+/// * `n` - LWE dimension
+/// * `q` - modulus
+/// * `sigma` - standard deviation of the error coefficients
+/// * `exponent` - core-SVP exponent of the cost model
+/// # Returns:
+/// (block size, cost in bits) minimised over block size and number of samples m <= n, at most
+/// the cost of reducing the full 2n-dimensional lattice
+fn dual_attack(n: usize, q: f64, sigma: f64, exponent: f64) -> (usize, f64) {
+    // lattices too small for the block size range are reduced outright
+    let mut best = (n + n, exponent * (n + n) as f64);
+    for m in (sample_step(n)..=n).step_by(sample_step(n)) {
+        let d = n + m;
+        for b in MIN_BLOCK_SIZE..d {
+            // BKZ finds a dual vector of length delta^(d-1) * q^(n/d)
+            let ln_length = (d as f64 - 1.0) * ln_root_hermite(b) + (n as f64 / d as f64) * q.ln();
+            let tau = ln_length.exp() * sigma / q;
+            // distinguishing advantage eps = 4*exp(-2*pi^2*tau^2), needing 1/eps^2 vectors
+            let log2_eps = 2.0 + (-2.0 * PI * PI * tau * tau) / std::f64::consts::LN_2;
+            let repetitions = (-2.0 * log2_eps - SIEVE_OUTPUT_EXPONENT * b as f64).max(0.0);
+            let cost = exponent * b as f64 + repetitions;
+            if cost < best.1 {
+                best = (b, cost);
+            }
+        }
+    }
+    best
+}
+
+impl Parameters {
+    /// Estimate the core-SVP hardness of the parameter set
+    /// # Returns:
+    /// costs of the primal and dual attacks on the public key under the classical
+    /// (2^0.292b) and quantum (2^0.265b) sieving models, with ternary secret and errors
+    /// and the module treated as plain LWE in dimension n*k
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// let estimate = params.security_estimate();
+    /// assert!(estimate.quantum_bits() < estimate.classical_bits());
+    /// ```
+    pub fn security_estimate(&self) -> SecurityEstimate {
+        let (n, q) = (self.n * self.k, self.q as f64);
+        // keygen and encrypt draw secret and errors uniformly from {-1,0,+1}
+        let sigma = (2.0f64 / 3.0).sqrt();
+        let primal_block_size = primal_block_size(n, q, sigma);
+        let (dual_block_size, dual_classical) = dual_attack(n, q, sigma, CLASSICAL_SIEVE_EXPONENT);
+        let (_, dual_quantum) = dual_attack(n, q, sigma, QUANTUM_SIEVE_EXPONENT);
+        SecurityEstimate {
+            primal_block_size,
+            dual_block_size,
+            primal_classical: CLASSICAL_SIEVE_EXPONENT * primal_block_size as f64,
+            dual_classical,
+            primal_quantum: QUANTUM_SIEVE_EXPONENT * primal_block_size as f64,
+            dual_quantum,
+        }
+    }
+
+    /// Classical core-SVP security of the parameter set in bits
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::default();
+    /// assert!(params.security_bits() >= 128.0);
+    /// ```
+    pub fn security_bits(&self) -> f64 {
+        self.security_estimate().classical_bits()
+    }
+
+    /// Build a parameter set, refusing it when it falls below a security floor
+    /// # Arguments:
+    /// * `n` - polynomial modulus degree
+    /// * `q` - ciphertext modulus
+    /// * `t` - plaintext modulus
+    /// * `min_security_bits` - smallest acceptable classical core-SVP security
+    /// # Returns:
+    /// the parameters, or an error naming the estimated security
+    /// # Example:
+    /// ```
+    /// use ring_lwe::utils::Parameters;
+    /// assert!(Parameters::with_min_security(1024, 12289, 2, 128.0).is_ok());
+    /// assert!(Parameters::with_min_security(64, 257, 2, 128.0).is_err());
+    /// ```
+    pub fn with_min_security(n: usize, q: i64, t: i64, min_security_bits: f64) -> Result<Self, String> {
+        Parameters::new(n, q, t).require_security(min_security_bits)
+    }
+
+    /// Refuse the parameter set when it falls below a security floor
+    /// # Arguments:
+    /// * `min_security_bits` - smallest acceptable classical core-SVP security
+    /// # Returns:
+    /// the parameters, or an error naming the estimated security
+    /// # Example:
+    /// ```
+    /// use ring_lwe::utils::Parameters;
+    /// assert!(Parameters::new(256, 7681, 2).require_security(128.0).is_err());
+    /// assert!(Parameters::new(256, 7681, 2).with_rank(4).require_security(128.0).is_ok());
+    /// ```
+    pub fn require_security(self, min_security_bits: f64) -> Result<Self, String> {
+        self.check_security(min_security_bits)?;
+        Ok(self)
+    }
+
+    /// Check the parameter set against a security floor without taking it, for functions that
+    /// are handed parameters by reference
+    /// # Arguments:
+    /// * `min_security_bits` - smallest acceptable classical core-SVP security
+    /// # Returns:
+    /// an error naming the estimated security when it falls below the floor
+    pub fn check_security(&self, min_security_bits: f64) -> Result<(), String> {
+        let bits = self.security_bits();
+        if bits < min_security_bits {
+            return Err(format!(
+                "parameters n={} q={} k={} give {:.1} bits of security, below the required {:.1}",
+                self.n, self.q, self.k, bits, min_security_bits
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use crate::crypto::kdf::{derive_bytes, derive_reader, sub_seed};
+use crate::crypto::keygen::expand_a;
+use crate::crypto::utils::{Parameters, polymul_fast, polyadd, polysub, gen_ternary_poly, gen_seed, pad_coeffs, seed_to_words, words_to_seed, compress, try_decompress, SEED_BYTES, SEED_WORDS};
+use polynomial_ring::Polynomial;
+use sha3::Shake256;
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use std::collections::HashMap;
+
+/// Length in bytes of the hash a signature challenge is expanded from
+pub const CHALLENGE_BYTES: usize = 32;
+
+/// Parameters of the signature scheme
+///
+/// Signatures need a much larger modulus than encryption: the masking polynomials have to hide
+/// c*s while staying short relative to q, so the ring is set up independently of the
+/// encryption `Parameters`.
+#[derive(Debug)]
+pub struct SignatureParameters {
+    pub ring: Parameters, // Ring degree n and modulus q
+    pub kappa: usize,     // Number of +-1 coefficients in a challenge polynomial
+    pub gamma: i64,       // Masking polynomials are uniform in [-gamma, gamma]
+}
+
+impl Default for SignatureParameters {
+    fn default() -> Self {
+        // 2^23 - 2^13 + 1 supports the NTT up to n = 4096; C(1024,16)*2^16 > 2^131 challenges;
+        // each attempt is accepted with probability about exp(-2n*kappa/gamma) = 0.78
+        SignatureParameters { ring: Parameters::new(1024, 8380417, 2), kappa: 16, gamma: 1 << 17 }
+    }
+}
+
+/// Public key for verifying signatures, t = a*s1 + s2 with `a` expanded from `seed_a`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyingKey {
+    pub t: Polynomial<i64>,
+    pub seed_a: [u8; SEED_BYTES],
+}
+
+/// Secret key for signing, the short polynomials s1 and s2 with their public key
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigningKey {
+    pub s1: Polynomial<i64>,
+    pub s2: Polynomial<i64>,
+    pub verifying_key: VerifyingKey,
+}
+
+/// Signature (c, z1, z2), with the challenge c stored as the hash it is expanded from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub challenge: [u8; CHALLENGE_BYTES],
+    pub z1: Polynomial<i64>,
+    pub z2: Polynomial<i64>,
+}
+
+impl VerifyingKey {
+    /// Serialized coefficients t || seed_a
+    fn to_coeffs(&self, n: usize) -> Vec<i64> {
+        let mut coeffs = pad_coeffs(&self.t, n);
+        coeffs.extend(seed_to_words(&self.seed_a));
+        coeffs
+    }
+
+    fn from_coeffs(coeffs: &[i64], n: usize) -> Result<Self, String> {
+        if coeffs.len() != n + SEED_WORDS {
+            return Err(String::from("wrong length for a verifying key"));
+        }
+        Ok(VerifyingKey { t: Polynomial::new(coeffs[..n].to_vec()), seed_a: words_to_seed(&coeffs[n..]) })
+    }
+}
+
+/// Largest absolute coefficient of a polynomial
+pub(crate) fn inf_norm(x: &Polynomial<i64>) -> i64 {
+    x.coeffs().iter().map(|c| c.abs()).max().unwrap_or(0)
+}
+
+/// Hash the verifying key, commitment w and message to the challenge
+fn challenge_hash(vk: &VerifyingKey, w: &Polynomial<i64>, message: &[u8], n: usize) -> [u8; CHALLENGE_BYTES] {
+    let vk_bytes = bincode::serialize(&vk.to_coeffs(n)).expect("Failed to serialize data");
+    let w_bytes = bincode::serialize(&pad_coeffs(w, n)).expect("Failed to serialize data");
+    derive_bytes("ring-lwe signature challenge", &[&vk_bytes, &w_bytes, message])
+}
+
+/// Expand a challenge hash to a polynomial with exactly kappa coefficients in {-1,+1}
+pub(crate) fn challenge_poly(challenge: &[u8; CHALLENGE_BYTES], n: usize, kappa: usize) -> Polynomial<i64> {
+    let mut hasher = Shake256::default();
+    hasher.update(challenge);
+    let mut reader = hasher.finalize_xof();
+    let mut coeffs = vec![0i64; n];
+    let mut placed = 0;
+    let mut buf = [0u8; 3];
+    while placed < kappa {
+        reader.read(&mut buf);
+        // n is a power of two, so the reduction is unbiased
+        let position = u16::from_le_bytes([buf[0], buf[1]]) as usize % n;
+        if coeffs[position] == 0 {
+            coeffs[position] = if buf[2] & 1 == 0 { 1 } else { -1 };
+            placed += 1;
+        }
+    }
+    Polynomial::new(coeffs)
+}
+
+/// Expand one attempt's masking polynomials y1, y2, uniform in [-gamma, gamma], from
+/// SHAKE-256 over the signing key, message and attempt counter
+fn mask_polys(sk: &SigningKey, message: &[u8], counter: u64, params: &SignatureParameters) -> (Polynomial<i64>, Polynomial<i64>) {
+    let n = params.ring.n;
+    let mut key_coeffs = pad_coeffs(&sk.s1, n);
+    key_coeffs.extend(pad_coeffs(&sk.s2, n));
+    let key_bytes = bincode::serialize(&key_coeffs).expect("Failed to serialize data");
+    let mut reader = derive_reader("ring-lwe signature mask", &[&key_bytes, message, &counter.to_le_bytes()]);
+    let range = 2 * params.gamma as u32 + 1;
+    // smallest all-ones mask covering the range, so about half of the draws are accepted at worst
+    let bits = u32::MAX >> range.leading_zeros();
+    let mut sample = || loop {
+        let mut buf = [0u8; 4];
+        reader.read(&mut buf);
+        let draw = u32::from_le_bytes(buf) & bits;
+        if draw < range {
+            return draw as i64 - params.gamma;
+        }
+    };
+    let y1 = Polynomial::new((0..n).map(|_| sample()).collect());
+    let y2 = Polynomial::new((0..n).map(|_| sample()).collect());
+    (y1, y2)
+}
+
+/// Generate a signing key pair
+/// # Arguments:
+/// * `params` - signature parameters
+/// * `seed` - random seed
+/// # Returns:
+/// (verifying key, signing key)
+/// # Example:
+/// ```
+/// use ring_lwe::signature::{sign_keygen, sign, verify, SignatureParameters};
+/// let params = SignatureParameters::default();
+/// let (vk, sk) = sign_keygen(&params, None);
+/// let signature = sign(&sk, b"hello", &params);
+/// assert!(verify(&vk, b"hello", &signature, &params));
+/// assert!(!verify(&vk, b"hellO", &signature, &params));
+/// ```
+pub fn sign_keygen(params: &SignatureParameters, seed: Option<u64>) -> (VerifyingKey, SigningKey) {
+    let ring = &params.ring;
+    let (n, q, f, omega) = (ring.n, ring.q, &ring.f, ring.omega);
+    // the seed of a and both secrets each come from their own stream
+    let seed_a = gen_seed(sub_seed(seed, "sign keygen matrix", 0));
+    let a = expand_a(&seed_a, ring);
+    let s1 = gen_ternary_poly(n, sub_seed(seed, "sign keygen secret", 0));
+    let s2 = gen_ternary_poly(n, sub_seed(seed, "sign keygen secret", 1));
+    let t = polyadd(&polymul_fast(&a, &s1, q, f, omega), &s2, q, f);
+    let verifying_key = VerifyingKey { t, seed_a };
+    (verifying_key.clone(), SigningKey { s1, s2, verifying_key })
+}
+
+/// Sign a message with Fiat-Shamir with aborts
+/// # Arguments:
+/// * `sk` - signing key
+/// * `message` - message to sign
+/// * `params` - signature parameters
+/// # Returns:
+/// signature (c, z1, z2) with z = y + c*s, resampling y until z no longer depends on s
+///
+/// The masks y are derived from the signing key, the message and an attempt counter rather
+/// than drawn from a caller seed, so signing is deterministic and two different messages never
+/// share a mask; a repeated mask would reveal c*s and with it the key.
+pub fn sign(sk: &SigningKey, message: &[u8], params: &SignatureParameters) -> Signature {
+    let ring = &params.ring;
+    let (n, q, f, omega) = (ring.n, ring.q, &ring.f, ring.omega);
+    let a = expand_a(&sk.verifying_key.seed_a, ring);
+    // |c*s| <= kappa coefficient-wise, so z is uniform on this range whatever s is
+    let bound = params.gamma - params.kappa as i64;
+    for counter in 0u64.. {
+        let (y1, y2) = mask_polys(sk, message, counter, params);
+        let w = polyadd(&polymul_fast(&a, &y1, q, f, omega), &y2, q, f);
+        let challenge = challenge_hash(&sk.verifying_key, &w, message, n);
+        let c = challenge_poly(&challenge, n, params.kappa);
+        let z1 = polyadd(&y1, &polymul_fast(&c, &sk.s1, q, f, omega), q, f);
+        let z2 = polyadd(&y2, &polymul_fast(&c, &sk.s2, q, f, omega), q, f);
+        if inf_norm(&z1) <= bound && inf_norm(&z2) <= bound {
+            return Signature { challenge, z1, z2 };
+        }
+    }
+    unreachable!("signing attempts are accepted with constant probability")
+}
+
+/// Verify a signature
+/// # Arguments:
+/// * `vk` - verifying key of the signer
+/// * `message` - signed message
+/// * `signature` - signature from `sign`
+/// * `params` - signature parameters
+/// # Returns:
+/// whether z1 and z2 are short and a*z1 + z2 - t*c hashes back to the challenge
+pub fn verify(vk: &VerifyingKey, message: &[u8], signature: &Signature, params: &SignatureParameters) -> bool {
+    let ring = &params.ring;
+    let (n, q, f, omega) = (ring.n, ring.q, &ring.f, ring.omega);
+    let bound = params.gamma - params.kappa as i64;
+    let Signature { challenge, z1, z2 } = signature;
+    if z1.coeffs().len() > n || z2.coeffs().len() > n || inf_norm(z1) > bound || inf_norm(z2) > bound {
+        return false;
+    }
+    let a = expand_a(&vk.seed_a, ring);
+    let c = challenge_poly(challenge, n, params.kappa);
+    let az_plus_z2 = polyadd(&polymul_fast(&a, z1, q, f, omega), z2, q, f);
+    let w = polysub(&az_plus_z2, &polymul_fast(&vk.t, &c, q, f, omega), q, f);
+    challenge_hash(vk, &w, message, n) == *challenge
+}
+
+/// Generate a signing key pair as base64 encoded strings
+/// # Arguments:
+/// * `params` - signature parameters
+/// * `seed` - random seed
+/// # Returns:
+/// HashMap with the verifying key t || seed_a as "public" and s1 || s2 || t || seed_a as "secret"
+pub fn sign_keygen_string(params: &SignatureParameters, seed: Option<u64>) -> HashMap<String, String> {
+    let n = params.ring.n;
+    let (vk, sk) = sign_keygen(params, seed);
+    let mut sk_coeffs = pad_coeffs(&sk.s1, n);
+    sk_coeffs.extend(pad_coeffs(&sk.s2, n));
+    sk_coeffs.extend(vk.to_coeffs(n));
+
+    let mut keys: HashMap<String, String> = HashMap::new();
+    keys.insert(String::from("secret"), compress(&sk_coeffs));
+    keys.insert(String::from("public"), verifying_key_to_string(&vk, params));
+    keys
+}
+
+/// Encode a verifying key as `sign_keygen_string` does
+pub fn verifying_key_to_string(vk: &VerifyingKey, params: &SignatureParameters) -> String {
+    compress(&vk.to_coeffs(params.ring.n))
+}
+
+/// Decode a verifying key produced by `sign_keygen_string`
+pub fn verifying_key_from_string(vk_base64: &str, params: &SignatureParameters) -> Result<VerifyingKey, String> {
+    VerifyingKey::from_coeffs(&try_decompress(vk_base64)?, params.ring.n)
+}
+
+/// Decode a signing key produced by `sign_keygen_string`
+pub fn signing_key_from_string(sk_base64: &str, params: &SignatureParameters) -> Result<SigningKey, String> {
+    let n = params.ring.n;
+    let coeffs = try_decompress(sk_base64)?;
+    if coeffs.len() != 3 * n + SEED_WORDS {
+        return Err(String::from("wrong length for a signing key"));
+    }
+    Ok(SigningKey {
+        s1: Polynomial::new(coeffs[..n].to_vec()),
+        s2: Polynomial::new(coeffs[n..2 * n].to_vec()),
+        verifying_key: VerifyingKey::from_coeffs(&coeffs[2 * n..], n)?,
+    })
+}
+
+/// Encode a signature as base64: the challenge hash as four words, then z1 and z2
+pub fn signature_to_string(signature: &Signature, params: &SignatureParameters) -> String {
+    let n = params.ring.n;
+    let mut coeffs = seed_to_words(&signature.challenge);
+    coeffs.extend(pad_coeffs(&signature.z1, n));
+    coeffs.extend(pad_coeffs(&signature.z2, n));
+    compress(&coeffs)
+}
+
+/// Decode a signature produced by `signature_to_string`
+pub fn signature_from_string(signature_base64: &str, params: &SignatureParameters) -> Result<Signature, String> {
+    let n = params.ring.n;
+    let coeffs = try_decompress(signature_base64)?;
+    if coeffs.len() != SEED_WORDS + 2 * n {
+        return Err(String::from("wrong length for a signature"));
+    }
+    Ok(Signature {
+        challenge: words_to_seed(&coeffs[..SEED_WORDS]),
+        z1: Polynomial::new(coeffs[SEED_WORDS..SEED_WORDS + n].to_vec()),
+        z2: Polynomial::new(coeffs[SEED_WORDS + n..].to_vec()),
+    })
+}
+
+/// Sign a string with a base64 encoded signing key
+/// # Arguments:
+/// * `sk_base64` - signing key from `sign_keygen_string`
+/// * `message` - message to sign
+/// * `params` - signature parameters
+/// # Returns:
+/// base64 encoded signature, or an error for a malformed key
+/// # Example:
+/// ```
+/// use ring_lwe::signature::{sign_keygen_string, sign_string, verify_string, SignatureParameters};
+/// let params = SignatureParameters::default();
+/// let keys = sign_keygen_string(&params, None);
+/// let signature = sign_string(keys.get("secret").unwrap(), "hello", &params).unwrap();
+/// assert!(verify_string(keys.get("public").unwrap(), "hello", &signature, &params));
+/// ```
+pub fn sign_string(sk_base64: &str, message: &str, params: &SignatureParameters) -> Result<String, String> {
+    let sk = signing_key_from_string(sk_base64, params)?;
+    Ok(signature_to_string(&sign(&sk, message.as_bytes(), params), params))
+}
+
+/// Verify a base64 encoded signature on a string
+/// # Arguments:
+/// * `vk_base64` - verifying key from `sign_keygen_string`
+/// * `message` - signed message
+/// * `signature_base64` - signature from `sign_string`
+/// * `params` - signature parameters
+/// # Returns:
+/// whether the signature is valid; malformed keys and signatures are invalid
+pub fn verify_string(vk_base64: &str, message: &str, signature_base64: &str, params: &SignatureParameters) -> bool {
+    match (verifying_key_from_string(vk_base64, params), signature_from_string(signature_base64, params)) {
+        (Ok(vk), Ok(signature)) => verify(&vk, message.as_bytes(), &signature, params),
+        _ => false,
+    }
+}
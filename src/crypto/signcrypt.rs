@@ -0,0 +1,185 @@
+use crate::crypto::armor::fingerprint;
+use crate::crypto::seal::{wrap_data_key, unwrap_data_key, DATA_KEY_BYTES, NONCE_BYTES};
+use crate::crypto::signature::{sign, verify, signing_key_from_string, verifying_key_from_string, verifying_key_to_string, signature_to_string, signature_from_string, SignatureParameters};
+use crate::crypto::utils::Parameters;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the bytes signed for an envelope, keeping them apart from other signed messages
+const SIGNCRYPT_CONTEXT: &[u8] = b"ring-lwe signcrypt envelope\0";
+
+/// A mail envelope signed by its sender and encrypted to one recipient
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Envelope {
+    /// verifying key of the sender, as produced by `sign_keygen_string`
+    pub(crate) sender_key: String,
+    /// fingerprint of the recipient public key the envelope is addressed to
+    pub(crate) recipient_fingerprint: String,
+    /// mail headers, readable without decrypting but covered by the signature and the AEAD tag
+    pub(crate) headers: Vec<(String, String)>,
+    /// data key encrypted to the recipient
+    pub(crate) wrapped_key: String,
+    pub(crate) nonce: [u8; NONCE_BYTES],
+    /// AES-256-GCM encryption of the message under the data key
+    pub(crate) body: Vec<u8>,
+    /// sender signature over all fields above
+    pub(crate) signature: String,
+}
+
+/// The bytes the sender signs: every envelope field but the signature
+#[derive(Serialize)]
+struct SignedFields<'a> {
+    sender_key: &'a str,
+    recipient_fingerprint: &'a str,
+    headers: &'a [(String, String)],
+    wrapped_key: &'a str,
+    nonce: &'a [u8; NONCE_BYTES],
+    body: &'a [u8],
+}
+
+impl Envelope {
+    pub(crate) fn signed_bytes(&self) -> Vec<u8> {
+        let fields = SignedFields {
+            sender_key: &self.sender_key,
+            recipient_fingerprint: &self.recipient_fingerprint,
+            headers: &self.headers,
+            wrapped_key: &self.wrapped_key,
+            nonce: &self.nonce,
+            body: &self.body,
+        };
+        let mut bytes = SIGNCRYPT_CONTEXT.to_vec();
+        bytes.extend(bincode::serialize(&fields).expect("Failed to serialize data"));
+        bytes
+    }
+}
+
+/// Associated data of the body: the sender and recipient fingerprints and the headers, so a
+/// body cannot be moved under another signer, recipient or set of headers
+fn body_aad(sender_fingerprint: &str, recipient_fingerprint: &str, headers: &[(String, String)]) -> Vec<u8> {
+    bincode::serialize(&(sender_fingerprint, recipient_fingerprint, headers)).expect("Failed to serialize data")
+}
+
+/// A verified and decrypted envelope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsigncrypted {
+    /// verifying key of the sender; callers match it against the identity claimed in `From`
+    pub sender_key: String,
+    /// fingerprint of `sender_key`
+    pub sender_fingerprint: String,
+    pub headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+/// Sign and encrypt a message for one recipient
+/// # Arguments:
+/// * `sender_sk` - sender signing key from `sign_keygen_string`
+/// * `recipient_pk` - recipient public key from `keygen_string`
+/// * `message` - message to protect
+/// * `headers` - mail headers such as From, To and Subject, bound to the message
+/// * `params` - encryption parameters of the recipient key
+/// * `sig_params` - signature parameters of the sender key
+/// # Returns:
+/// base64 encoded envelope, or an error for a malformed sender key
+///
+/// The body is encrypted with the sender fingerprint as associated data and the envelope is
+/// then signed, so replacing the signature with another sender's breaks decryption, and the
+/// signed recipient fingerprint stops the recipient from forwarding the envelope as if it had
+/// been sent to someone else.
+/// # Example:
+/// ```
+/// use ring_lwe::signcrypt::{signcrypt, unsigncrypt};
+/// use ring_lwe::signature::{sign_keygen_string, SignatureParameters};
+/// let (params, sig_params) = (ring_lwe::utils::Parameters::default(), SignatureParameters::default());
+/// let alice = sign_keygen_string(&sig_params, None);
+/// let bob = ring_lwe::keygen::keygen_string(&params, None);
+/// let headers = vec![(String::from("Subject"), String::from("lunch"))];
+/// let envelope = signcrypt(alice.get("secret").unwrap(), bob.get("public").unwrap(), "noon?", &headers, &params, &sig_params).unwrap();
+/// let opened = unsigncrypt(bob.get("secret").unwrap(), bob.get("public").unwrap(), &envelope, &params, &sig_params).unwrap();
+/// assert_eq!((opened.message.as_str(), &opened.headers), ("noon?", &headers));
+/// assert_eq!(&opened.sender_key, alice.get("public").unwrap());
+/// ```
+pub fn signcrypt(
+    sender_sk: &str,
+    recipient_pk: &str,
+    message: &str,
+    headers: &[(String, String)],
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+) -> Result<String, String> {
+    let signing_key = signing_key_from_string(sender_sk, sig_params)?;
+    let sender_key = verifying_key_to_string(&signing_key.verifying_key, sig_params);
+    let recipient_fingerprint = fingerprint(recipient_pk)?;
+
+    let mut data_key = [0u8; DATA_KEY_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut data_key);
+    OsRng.fill_bytes(&mut nonce);
+    let aad = body_aad(&fingerprint(&sender_key)?, &recipient_fingerprint, headers);
+    let body = Aes256Gcm::new(&data_key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: message.as_bytes(), aad: &aad })
+        .expect("Failed to encrypt message");
+
+    let mut envelope = Envelope {
+        sender_key,
+        recipient_fingerprint,
+        headers: headers.to_vec(),
+        wrapped_key: wrap_data_key(recipient_pk, &data_key, params),
+        nonce,
+        body,
+        signature: String::new(),
+    };
+    let signature = sign(&signing_key, &envelope.signed_bytes(), sig_params);
+    envelope.signature = signature_to_string(&signature, sig_params);
+    Ok(general_purpose::STANDARD.encode(bincode::serialize(&envelope).expect("Failed to serialize data")))
+}
+
+/// Verify and decrypt an envelope from `signcrypt`
+/// # Arguments:
+/// * `recipient_sk` - recipient secret key from `keygen_string`
+/// * `recipient_pk` - the matching public key, to check the envelope is addressed to it
+/// * `envelope` - base64 encoded envelope
+/// * `params` - encryption parameters of the recipient key
+/// * `sig_params` - signature parameters of the sender key
+/// # Returns:
+/// the sender key, headers and message, or an error when the envelope is malformed, addressed
+/// to another key, not signed by the key it names, or does not decrypt
+pub fn unsigncrypt(
+    recipient_sk: &str,
+    recipient_pk: &str,
+    envelope: &str,
+    params: &Parameters,
+    sig_params: &SignatureParameters,
+) -> Result<Unsigncrypted, String> {
+    let bytes = general_purpose::STANDARD.decode(envelope.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    let envelope: Envelope = bincode::deserialize(&bytes).map_err(|e| format!("invalid envelope: {}", e))?;
+
+    if envelope.recipient_fingerprint != fingerprint(recipient_pk)? {
+        return Err(String::from("the envelope is addressed to another key"));
+    }
+    let sender_key = verifying_key_from_string(&envelope.sender_key, sig_params)?;
+    let signature = signature_from_string(&envelope.signature, sig_params)?;
+    if !verify(&sender_key, &envelope.signed_bytes(), &signature, sig_params) {
+        return Err(String::from("invalid sender signature"));
+    }
+
+    let sender_fingerprint = fingerprint(&envelope.sender_key)?;
+    let aad = body_aad(&sender_fingerprint, &envelope.recipient_fingerprint, &envelope.headers);
+    let message = unwrap_data_key(recipient_sk, &envelope.wrapped_key, params)
+        .and_then(|data_key| {
+            Aes256Gcm::new(&data_key.into())
+                .decrypt(Nonce::from_slice(&envelope.nonce), Payload { msg: &envelope.body, aad: &aad })
+                .ok()
+        })
+        .ok_or_else(|| String::from("the envelope does not decrypt with this key"))?;
+
+    Ok(Unsigncrypted {
+        sender_key: envelope.sender_key,
+        sender_fingerprint,
+        headers: envelope.headers,
+        message: String::from_utf8(message).map_err(|e| format!("message is not UTF-8: {}", e))?,
+    })
+}
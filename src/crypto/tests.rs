@@ -1,9 +1,30 @@
 #[cfg(test)]  // This makes the following module compile only during tests
 mod tests {
-    use crate::keygen::{keygen, keygen_string};
-    use crate::encrypt::{encrypt, encrypt_string};
-    use crate::decrypt::{decrypt, decrypt_string};
-    use crate::utils::{Parameters, polyadd, polymul, polymul_fast, mod_coeffs, nearest_int, gen_uniform_poly};
+    use crate::keygen::{keygen, keygen_string, keygen_compact, keygen_from_seed, keygen_from_seed_string, expand_a, public_key_from_coeffs};
+    use crate::kdf::derive_path_key;
+    use crate::encrypt::{encrypt, encrypt_string, rerandomize, rerandomize_string};
+    use crate::decrypt::{decrypt, decrypt_string, decrypt_with_noise};
+    use crate::module_lwe::{module_keygen, module_keygen_string, module_encrypt, module_decrypt, module_encrypt_string, module_decrypt_string};
+    use crate::search::{search, SearchTarget};
+    use crate::secret_key::SecretKey;
+    use crate::armor::{armor, dearmor, fingerprint, ArmorKind};
+    use crate::seal::{seal, open, wrap_data_key, unwrap_data_key};
+    use crate::signature::{sign_keygen, sign, verify, sign_keygen_string, sign_string, verify_string, signing_key_from_string, signature_to_string, SignatureParameters};
+    use crate::signcrypt::{signcrypt, unsigncrypt, Envelope};
+    use crate::key_exchange::{initiate, respond, finish, initiate_authenticated, respond_authenticated, finish_authenticated, InitiatorMessage, ResponderMessage};
+    use crate::channel::{client_hello, server_accept, Direction};
+    use crate::prekey::{generate_prekeys, generate_last_resort_prekey, verify_prekey, verify_last_resort_prekey, prekey_encrypt, prekey_id, prekey_decrypt};
+    use crate::ratchet::{ThreadSession, KEM_INTERVAL};
+    use crate::threshold::{share_secret_key, partial_decrypt, combine, share_secret_key_string, partial_decrypt_string, combine_string, smudging_bound, smudging_bits, KeyShare, MIN_SMUDGING_BITS};
+    use crate::dkg::{DkgParty, DkgMessage, run_in_process};
+    use crate::decryption_proof::{prove_claim, prove_decryption, prove_decryption_unchecked, verify_decryption, verify_decryption_unchecked};
+    use crate::possession::{possession_challenge, possession_response, possession_verified};
+    use crate::validate::{validate_keypair, validate_public_key};
+    use crate::blind::{blind, unblind};
+    use crate::anonymous::{anonymous_encrypt, anonymous_decrypt, trial_decrypt};
+    use crate::hybrid::{hybrid_keygen_string, encapsulate, decapsulate, hybrid_encrypt_string, hybrid_decrypt_string};
+    use crate::utils::{Parameters, compress, decompress, pad_coeffs, seed_to_words, polyadd, polyinv, polymul, polymul_fast, mod_coeffs, nearest_int, gen_uniform_poly, gen_binary_poly, gen_ternary_poly, gen_seed};
+    use base64::{engine::general_purpose, Engine as _};
     use ntt::omega;
     use polynomial_ring::Polynomial;
 
@@ -54,10 +75,10 @@ mod tests {
     pub fn test_hom_prod() {
 
         let seed = None; //set the random seed
-        let mut params = Parameters::default();
-        let (q, t, f) = (params.q, params.t, &params.f);
-        params.q = q*q;
-        params.omega = omega(params.q, 2*params.n);
+        let default = Parameters::default();
+        let (q, t) = (default.q, default.t);
+        let params = Parameters::new(default.n, q*q, t);
+        let f = &params.f;
 
         //create polynomials from ints
         let m0_poly = Polynomial::new(vec![1, 0, 1]);
@@ -88,6 +109,1064 @@ mod tests {
         assert_eq!(plaintext_prod, decrypted_prod, "test failed: {} != {}", plaintext_prod, decrypted_prod);
     }
 
+    // Test that measured decryption noise stays within the bound and matches the analytic estimate
+    #[test]
+    pub fn test_decrypt_with_noise() {
+        let seed = None; //set the random seed
+        let params = Parameters::default();
+        let (pk, sk) = keygen(&params, seed);
+        let m = Polynomial::new(vec![1, 1, 0, 1]);
+        let ct = encrypt(&pk, &m, &params, seed);
+
+        let (decrypted_m, noise) = decrypt_with_noise(&sk, &ct, &params);
+        assert_eq!(decrypted_m, decrypt(&sk, &ct, &params));
+        assert_eq!(decrypted_m, m, "test failed: {} != {}", decrypted_m, m);
+        // the infinity norm over n coefficients stays well within 8 standard deviations
+        let sigma = params.noise_variance().sqrt();
+        assert!(noise > 0 && (noise as f64) < 8.0 * sigma, "unexpected noise {}", noise);
+    }
+
+    // Test the failure probability estimate is negligible for the defaults and grows as q shrinks
+    #[test]
+    pub fn test_failure_probability() {
+        let params = Parameters::default();
+        assert!(params.failure_probability_log2() < -128.0);
+        assert_eq!(params.failure_probability(), 0.0);
+
+        let small_q = Parameters::new(128, 257, 2);
+        assert!(small_q.failure_probability_log2() > params.failure_probability_log2());
+        assert!(small_q.failure_probability_log2() < 0.0);
+
+        // t = 16 leaves a noise bound of 7, below one standard deviation
+        let large_t = Parameters::new(128, 257, 16);
+        assert!(large_t.failure_probability() > 0.5);
+        assert!(large_t.failure_probability() <= 1.0);
+    }
+
+    // Test the security estimate grows with the ring degree and guards against weak parameters
+    #[test]
+    pub fn test_security_estimate() {
+        let params = Parameters::default();
+        let estimate = params.security_estimate();
+        assert!(estimate.classical_bits() >= 128.0, "default parameters give {} bits", estimate.classical_bits());
+        assert!(estimate.quantum_bits() < estimate.classical_bits());
+
+        let smaller = Parameters::new(512, 12289, 2);
+        assert!(smaller.security_bits() < params.security_bits());
+
+        assert!(Parameters::with_min_security(1024, 12289, 2, 128.0).is_ok());
+        assert!(Parameters::with_min_security(512, 12289, 2, 128.0).is_err());
+
+        // lattices below the smallest block size still get a finite estimate
+        let tiny = Parameters::new(16, 257, 2).security_estimate();
+        assert!(tiny.dual_classical.is_finite() && tiny.classical_bits() < 20.0);
+    }
+
+    // Test the parameter search only returns NTT-friendly sets meeting every target
+    #[test]
+    pub fn test_params_search() {
+        let target = SearchTarget { security_bits: 128.0, max_failure: 1e-40, depth: 0, t: 2 };
+        let candidates = search(&target);
+        assert!(!candidates.is_empty());
+        for c in &candidates {
+            let (n, q) = (c.params.n as i64, c.params.q);
+            assert_eq!(q % (2 * n), 1, "q = {} is not 1 mod 2n", q);
+            assert!(c.security_bits >= target.security_bits);
+            assert!(c.failure_log2 <= target.max_failure.log2());
+        }
+
+        // one multiplication squares the prime, as in test_hom_prod
+        let depth_one = search(&SearchTarget { depth: 1, ..target });
+        for c in &depth_one {
+            assert_eq!(c.params.q, c.prime * c.prime);
+            assert!(c.failure_log2 <= target.max_failure.log2());
+        }
+
+        // the failure estimate covers the noise of a product of two full-length messages
+        let c = depth_one.iter().find(|c| c.params.k == 1).expect("no ring-LWE candidate for depth 1");
+        let (params, f, delta) = (&c.params, &c.params.f, c.prime / c.params.t);
+        assert!(params.product_noise_variance(1) > params.noise_variance());
+        let (pk, sk) = keygen(params, None);
+        for _ in 0..3 {
+            let m0 = gen_binary_poly(params.n, None);
+            let m1 = gen_binary_poly(params.n, None);
+            let (u, v) = (encrypt(&pk, &m0, params, None), encrypt(&pk, &m1, params, None));
+            let c1 = polyadd(&polymul(&u[0], &v[1], params.q, f), &polymul(&u[1], &v[0], params.q, f), params.q, f);
+            let c2 = polymul(&u[1], &v[1], params.q, f);
+            let phase = polyadd(&polyadd(&polymul(&u[0], &v[0], params.q, f), &polymul(&c1, &sk, params.q, f), params.q, f),
+                &polymul(&polymul(&c2, &sk, params.q, f), &sk, params.q, f), params.q, f);
+            let decrypted = mod_coeffs(Polynomial::new(phase.coeffs().iter().map(|&coeff| nearest_int(coeff, delta * delta)).collect::<Vec<_>>()), params.t);
+            assert_eq!(decrypted, polymul(&m0, &m1, params.t, f));
+        }
+    }
+
+    // Test module-LWE keygen/encrypt/decrypt for several ranks, including the string API
+    #[test]
+    pub fn test_module_lwe() {
+        let seed = None; //set the random seed
+        let m = Polynomial::new(vec![1, 0, 1, 1]);
+        for k in 1..=3 {
+            let params = Parameters::new(256, 7681, 2).with_rank(k);
+            let (pk, sk) = module_keygen(&params, seed);
+            let ct = module_encrypt(&pk, &m, &params, seed);
+            let decrypted_m = module_decrypt(&sk, &ct, &params);
+            assert_eq!(decrypted_m, m, "test failed for k = {}: {} != {}", k, decrypted_m, m);
+        }
+
+        let message = String::from("hello");
+        let params = Parameters::new(256, 7681, 2).with_rank(3);
+        let keypair = keygen_string(&params, seed);
+        let ciphertext_string = encrypt_string(keypair.get("public").unwrap(), &message, &params, seed);
+        let decrypted_message = decrypt_string(keypair.get("secret").unwrap(), &ciphertext_string, &params);
+        assert_eq!(message, decrypted_message, "test failed: {} != {}", message, decrypted_message);
+
+        // each extra rank adds n dimensions to the lattice
+        assert!(params.security_bits() > Parameters::new(256, 7681, 2).with_rank(2).security_bits());
+
+        // neighbouring seeds do not share sampler streams
+        assert_ne!(module_keygen(&params, Some(5)).1[1], module_keygen(&params, Some(6)).1[0]);
+        // the ring-only functions refuse module parameters instead of ignoring the rank
+        assert!(std::panic::catch_unwind(|| keygen(&params, seed)).is_err());
+    }
+
+    // Test seed-expanded public keys are half the size and legacy expanded keys still encrypt
+    #[test]
+    pub fn test_compact_public_key() {
+        let seed = None; //set the random seed
+        let params = Parameters::default();
+        let message = String::from("hello");
+
+        let ((b, seed_a), sk) = keygen_compact(&params, seed);
+        let a = expand_a(&seed_a, &params);
+        assert!(a.coeffs().iter().all(|&c| c.abs() <= params.q / 2));
+
+        let compact = compress(&[pad_coeffs(&b, params.n), seed_to_words(&seed_a)].concat());
+        let legacy = compress(&[pad_coeffs(&b, params.n), pad_coeffs(&a, params.n)].concat());
+        assert!(100 * compact.len() < 51 * legacy.len(), "{} != {} / 2", compact.len(), legacy.len());
+
+        let sk_string = compress(&sk.coeffs().to_vec());
+        for pk_string in [compact, legacy] {
+            let ciphertext_string = encrypt_string(&pk_string, &message, &params, seed);
+            let decrypted_message = decrypt_string(&sk_string, &ciphertext_string, &params);
+            assert_eq!(message, decrypted_message, "test failed: {} != {}", message, decrypted_message);
+        }
+
+        // a seeded key draws its secret and error from separate streams, so e = -(b + a*sk) != sk
+        let ((b, seed_a), sk) = keygen_compact(&params, Some(7));
+        let a = expand_a(&seed_a, &params);
+        let e = polyinv(&polyadd(&b, &polymul_fast(&a, &sk, params.q, &params.f, params.omega), params.q, &params.f), params.q);
+        assert_ne!(e, sk);
+
+        // anything but n + SEED_WORDS or 2n coefficients is not a public key
+        assert!(public_key_from_coeffs(&pad_coeffs(&b, params.n), &params).is_err());
+        assert!(public_key_from_coeffs(&pad_coeffs(&b, params.n)[..10], &params).is_err());
+    }
+
+    // Test vectors pinning hierarchical key derivation from a master seed
+    #[test]
+    pub fn test_keygen_from_seed() {
+        let params = Parameters::new(256, 7681, 2);
+        let master_seed = [0u8; 32];
+
+        let node_key = derive_path_key(&master_seed, "user/epoch/3");
+        assert_eq!(node_key[..8], [0x66, 0x3c, 0x04, 0xec, 0x46, 0x98, 0x7f, 0xa9]);
+
+        let ((b, seed_a), sk) = keygen_from_seed(&master_seed, "user/epoch/3", &params);
+        assert_eq!(sk.coeffs()[..12], [-1, 1, 0, 1, -1, -1, 0, 1, 0, 1, -1, 0]);
+        assert_eq!(b.coeffs()[..6], [1469, -3823, -558, -3236, 2692, -1558]);
+        assert_eq!(seed_a[..8], [0x8a, 0xfb, 0xe5, 0x8d, 0x16, 0x9b, 0x06, 0xe5]);
+
+        // sibling paths and other master seeds give unrelated keys
+        let (_, sibling_sk) = keygen_from_seed(&master_seed, "user/epoch/4", &params);
+        let (_, other_sk) = keygen_from_seed(&[1u8; 32], "user/epoch/3", &params);
+        assert_ne!(sk, sibling_sk);
+        assert_ne!(sk, other_sk);
+
+        // derived keys work with the string API
+        let message = String::from("hello");
+        let keypair = keygen_from_seed_string(&master_seed, "user/epoch/3", &params);
+        let ciphertext_string = encrypt_string(keypair.get("public").unwrap(), &message, &params, None);
+        let decrypted_message = decrypt_string(keypair.get("secret").unwrap(), &ciphertext_string, &params);
+        assert_eq!(message, decrypted_message, "test failed: {} != {}", message, decrypted_message);
+    }
+
+    // Test password-protected export and import of secret keys
+    #[test]
+    pub fn test_secret_key_export() {
+        let params = Parameters::default();
+        let keypair = keygen_string(&params, None);
+        let sk = SecretKey::from_base64(keypair.get("secret").unwrap());
+        let wrapped = sk.export_encrypted("hunter2");
+
+        let imported = SecretKey::import_encrypted(&wrapped, "hunter2").unwrap();
+        assert_eq!(imported.to_base64(), *keypair.get("secret").unwrap());
+        assert!(SecretKey::import_encrypted(&wrapped, "hunter3").is_err());
+
+        // flipping any ciphertext or header byte is detected
+        let mut tampered = general_purpose::STANDARD.decode(&wrapped).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(SecretKey::import_encrypted(&general_purpose::STANDARD.encode(&tampered), "hunter2").is_err());
+        tampered[last] ^= 1;
+        tampered[7] ^= 1;
+        assert!(SecretKey::import_encrypted(&general_purpose::STANDARD.encode(&tampered), "hunter2").is_err());
+
+        // a header asking for more Argon2id work than export writes is refused before hashing
+        tampered[7] ^= 1;
+        tampered[11..15].copy_from_slice(&64u32.to_le_bytes());
+        let refused = SecretKey::import_encrypted(&general_purpose::STANDARD.encode(&tampered), "hunter2");
+        assert_eq!(refused, Err(String::from("Argon2id costs in the container are too large")));
+
+        // module-LWE keys keep all k polynomials
+        let module_params = Parameters::new(256, 7681, 2).with_rank(2);
+        let (_, module_sk) = module_keygen(&module_params, None);
+        let coeffs: Vec<i64> = module_sk.iter().flat_map(|s| pad_coeffs(s, module_params.n)).collect();
+        let wrapped = SecretKey::from_base64(&compress(&coeffs)).export_encrypted("hunter2");
+        assert_eq!(SecretKey::import_encrypted(&wrapped, "hunter2").unwrap().coeffs(), coeffs);
+
+        // /keygen only falls back to a raw secret key for an empty body
+        use crate::api::handlers::keygen_handler;
+        use axum::body::Bytes;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let keygen_body = |body: &'static str| runtime.block_on(keygen_handler(Bytes::from(body)));
+        assert!(keygen_body("").unwrap().0.secret_key.is_some());
+        let wrapped = keygen_body(r#"{"password": "hunter2"}"#).unwrap().0;
+        assert!(wrapped.secret_key.is_none() && wrapped.encrypted_secret_key.is_some());
+        for malformed in [r#"{"pasword": "hunter2"}"#, r#"{"password": "hunter2""#, "password=hunter2"] {
+            assert_eq!(keygen_body(malformed).err().map(|e| e.0), Some(axum::http::StatusCode::BAD_REQUEST));
+        }
+    }
+
+    // Test armoring and dearmoring keys and ciphertexts
+    #[test]
+    pub fn test_armor() {
+        let params = Parameters::new(512, 12289, 2);
+        let keypair = keygen_string(&params, None);
+        let ciphertext = encrypt_string(keypair.get("public").unwrap(), &String::from("hello"), &params, None);
+
+        for (kind, payload) in [
+            (ArmorKind::PublicKey, keypair.get("public").unwrap()),
+            (ArmorKind::SecretKey, keypair.get("secret").unwrap()),
+            (ArmorKind::Ciphertext, &ciphertext),
+        ] {
+            let armored = armor(kind, payload, &params).unwrap();
+            assert!(armored.lines().all(|line| line.len() <= 64));
+            let decoded = dearmor(&armored).unwrap();
+            assert_eq!((decoded.kind, &decoded.payload), (kind, payload));
+            assert!(decoded.matches(&params));
+
+            // pasted text keeps working with CRLF line endings and surrounding prose
+            let pasted = format!("key follows:\r\n{}\r\nthanks", armored.replace('\n', "\r\n"));
+            assert_eq!(dearmor(&pasted).unwrap(), decoded);
+        }
+
+        let armored = armor(ArmorKind::PublicKey, keypair.get("public").unwrap(), &params).unwrap();
+        let body_line = armored.lines().nth(4).unwrap();
+        let flipped: String = body_line.chars().rev().collect();
+        assert!(dearmor(&armored.replacen(body_line, &flipped, 1)).is_err());
+        assert!(dearmor(&armored.replace("END RING-LWE PUBLIC KEY", "END RING-LWE SECRET KEY")).is_err());
+        assert!(dearmor(&armored.replace("Fingerprint: ", "Fingerprint: 00")).is_err());
+        assert!(dearmor(&armored.replace("q=12289", "q=12288")).is_err());
+
+        // payloads that are not base64 are refused rather than panicking
+        assert!(armor(ArmorKind::PublicKey, "not base64!", &params).is_err());
+        assert!(fingerprint("not base64!").is_err());
+    }
+
+    // Test sealing one message to several recipients
+    #[test]
+    pub fn test_seal_multiple_recipients() {
+        let params = Parameters::new(512, 12289, 2);
+        let recipients: Vec<_> = (0..3).map(|_| keygen_string(&params, None)).collect();
+        let outsider = keygen_string(&params, None);
+        let public_keys: Vec<String> = recipients.iter().map(|keys| keys.get("public").unwrap().clone()).collect();
+        let message = "meeting moved to 3pm, see agenda";
+
+        let sealed = seal(&public_keys, message, &params);
+        for keys in &recipients {
+            assert_eq!(open(keys.get("secret").unwrap(), &sealed, &params), Ok(String::from(message)));
+        }
+        assert!(open(outsider.get("secret").unwrap(), &sealed, &params).is_err());
+
+        // the body is stored once, so adding recipients only adds key encapsulations
+        let single = seal(&public_keys[..1], message, &params);
+        let long_message = message.repeat(50);
+        let single_long = seal(&public_keys[..1], &long_message, &params);
+        let sealed_long = seal(&public_keys, &long_message, &params);
+        let growth = (sealed_long.len() - sealed.len()).abs_diff(single_long.len() - single.len());
+        assert!(growth <= 4, "the body grew {} base64 characters more for three recipients", growth);
+
+        // module-LWE keys work too
+        let module_params = Parameters::new(256, 7681, 2).with_rank(2);
+        let keys = keygen_string(&module_params, None);
+        let sealed = seal(&[keys.get("public").unwrap().clone()], message, &module_params);
+        assert_eq!(open(keys.get("secret").unwrap(), &sealed, &module_params), Ok(String::from(message)));
+
+        // malformed recipient entries are skipped instead of panicking
+        let sk = recipients[0].get("secret").unwrap();
+        let short_block = compress(&vec![1i64; params.n]);
+        let huge_coeffs = compress(&vec![i64::MAX; 2 * params.n]);
+        for entry in ["not base64!", "AAAA", short_block.as_str(), huge_coeffs.as_str()] {
+            assert_eq!(unwrap_data_key(sk, entry, &params), None);
+            let forged = (vec![entry.to_string()], [0u8; 12], vec![0u8; 16]);
+            let forged = general_purpose::STANDARD.encode(bincode::serialize(&forged).unwrap());
+            assert!(open(sk, &forged, &params).is_err());
+        }
+    }
+
+    // Test signing and verifying messages
+    #[test]
+    pub fn test_signature() {
+        let params = SignatureParameters::default();
+        let (vk, sk) = sign_keygen(&params, None);
+        let (other_vk, _) = sign_keygen(&params, None);
+        let message = b"From: alice@example.com\r\nSubject: lunch";
+
+        let signature = sign(&sk, message, &params);
+        assert!(verify(&vk, message, &signature, &params));
+        assert!(!verify(&vk, b"From: mallory@example.com\r\nSubject: lunch", &signature, &params));
+        assert!(!verify(&other_vk, message, &signature, &params));
+
+        // the mask comes from the key and message, so re-signing repeats the signature and a
+        // different message gets a different mask
+        assert_eq!(sign(&sk, message, &params), signature);
+        let other = sign(&sk, b"From: alice@example.com\r\nSubject: dinner", &params);
+        assert_ne!(other.z1, signature.z1);
+        assert_ne!(other.z2, signature.z2);
+
+        // a seeded key publishes neither the stream its secrets come from nor shared secrets
+        let (seeded_vk, seeded) = sign_keygen(&params, Some(9));
+        assert_ne!(seeded_vk.seed_a, gen_seed(Some(9)));
+        assert_ne!(seeded.s1, gen_ternary_poly(params.ring.n, Some(9)));
+        assert_ne!(seeded.s1, seeded.s2);
+
+        // z has to stay short and consistent with the challenge
+        let mut forged = signature.clone();
+        let mut z1 = forged.z1.coeffs().to_vec();
+        z1[0] += 1;
+        forged.z1 = Polynomial::new(z1);
+        assert!(!verify(&vk, message, &forged, &params));
+        let mut forged = signature.clone();
+        forged.z2 = Polynomial::new(vec![params.gamma; params.ring.n]);
+        assert!(!verify(&vk, message, &forged, &params));
+
+        // string API, where malformed input is rejected rather than panicking
+        let keys = sign_keygen_string(&params, None);
+        let signature_string = sign_string(keys.get("secret").unwrap(), "hello", &params).unwrap();
+        assert!(verify_string(keys.get("public").unwrap(), "hello", &signature_string, &params));
+        assert!(!verify_string(keys.get("public").unwrap(), "hello", "not base64!", &params));
+        assert!(!verify_string(keys.get("public").unwrap(), "hello", &compress(&vec![0; 8]), &params));
+        assert!(sign_string("AAAA", "hello", &params).is_err());
+    }
+
+    // Test signcryption and the attacks it has to stop
+    #[test]
+    pub fn test_signcrypt() {
+        let params = Parameters::new(512, 12289, 2);
+        let sig_params = SignatureParameters::default();
+        let alice = sign_keygen_string(&sig_params, None);
+        let mallory = sign_keygen_string(&sig_params, None);
+        let bob = keygen_string(&params, None);
+        let carol = keygen_string(&params, None);
+        let headers: Vec<(String, String)> = [("From", "alice@example.com"), ("To", "bob@example.com"), ("Subject", "offer")]
+            .iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        let (bob_sk, bob_pk) = (bob.get("secret").unwrap(), bob.get("public").unwrap());
+
+        let envelope = signcrypt(alice.get("secret").unwrap(), bob_pk, "I accept", &headers, &params, &sig_params).unwrap();
+        let opened = unsigncrypt(bob_sk, bob_pk, &envelope, &params, &sig_params).unwrap();
+        assert_eq!(opened.message, "I accept");
+        assert_eq!(opened.headers, headers);
+        assert_eq!(&opened.sender_key, alice.get("public").unwrap());
+
+        let decode = |envelope: &str| -> Envelope { bincode::deserialize(&general_purpose::STANDARD.decode(envelope).unwrap()).unwrap() };
+        let encode = |envelope: &Envelope| general_purpose::STANDARD.encode(bincode::serialize(envelope).unwrap());
+
+        // headers cannot be changed
+        let mut tampered = decode(&envelope);
+        tampered.headers[2].1 = String::from("retraction");
+        assert!(unsigncrypt(bob_sk, bob_pk, &encode(&tampered), &params, &sig_params).is_err());
+
+        // signature stripping: mallory re-signs alice's envelope as her own
+        let mallory_sk = signing_key_from_string(mallory.get("secret").unwrap(), &sig_params).unwrap();
+        let mut stripped = decode(&envelope);
+        stripped.sender_key = mallory.get("public").unwrap().clone();
+        stripped.signature = signature_to_string(&sign(&mallory_sk, &stripped.signed_bytes(), &sig_params), &sig_params);
+        assert!(unsigncrypt(bob_sk, bob_pk, &encode(&stripped), &params, &sig_params).is_err());
+
+        // surreptitious forwarding: bob passes alice's envelope on to carol
+        let carol_pk = carol.get("public").unwrap();
+        let mut forwarded = decode(&envelope);
+        let data_key = unwrap_data_key(bob_sk, &forwarded.wrapped_key, &params).unwrap();
+        forwarded.wrapped_key = wrap_data_key(carol_pk, &data_key, &params);
+        assert!(unsigncrypt(carol.get("secret").unwrap(), carol_pk, &encode(&forwarded), &params, &sig_params).is_err());
+        forwarded.recipient_fingerprint = fingerprint(carol_pk).unwrap();
+        assert!(unsigncrypt(carol.get("secret").unwrap(), carol_pk, &encode(&forwarded), &params, &sig_params).is_err());
+    }
+
+    // Test the hybrid X25519 + ring-LWE KEM
+    #[test]
+    pub fn test_hybrid_kem() {
+        let params = Parameters::new(512, 12289, 2);
+        let keys = hybrid_keygen_string(&params);
+        let other = hybrid_keygen_string(&params);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+
+        let (ct, key) = encapsulate(pk, &params).unwrap();
+        assert_eq!(decapsulate(sk, &ct, &params), Ok(key));
+        assert_ne!(decapsulate(other.get("secret").unwrap(), &ct, &params), Ok(key));
+
+        // the key depends on both halves of the ciphertext, so neither component decides it alone
+        let (x25519_ct, ring_lwe_ct): ([u8; 32], String) = bincode::deserialize(&general_purpose::STANDARD.decode(&ct).unwrap()).unwrap();
+        let (other_ct, _) = encapsulate(pk, &params).unwrap();
+        let (other_x25519_ct, other_ring_lwe_ct): ([u8; 32], String) = bincode::deserialize(&general_purpose::STANDARD.decode(&other_ct).unwrap()).unwrap();
+        for mixed in [(x25519_ct, other_ring_lwe_ct), (other_x25519_ct, ring_lwe_ct.clone())] {
+            let mixed = general_purpose::STANDARD.encode(bincode::serialize(&mixed).unwrap());
+            assert_ne!(decapsulate(sk, &mixed, &params), Ok(key));
+        }
+
+        // a ring-LWE half nudged by one still decrypts to the same message, but fails the
+        // re-encryption check and gets the same pseudorandom key every time
+        let mut nudged = decompress(&ring_lwe_ct);
+        nudged[0] += 1;
+        let nudged = general_purpose::STANDARD.encode(bincode::serialize(&(x25519_ct, compress(&nudged))).unwrap());
+        let rejected = decapsulate(sk, &nudged, &params).unwrap();
+        assert_ne!(rejected, key);
+        assert_eq!(decapsulate(sk, &nudged, &params), Ok(rejected));
+
+        // messages, and rejection of keys from another scheme
+        let ciphertext = hybrid_encrypt_string(pk, "presence: online", &params).unwrap();
+        assert_eq!(hybrid_decrypt_string(sk, &ciphertext, &params), Ok(String::from("presence: online")));
+        assert!(hybrid_decrypt_string(other.get("secret").unwrap(), &ciphertext, &params).is_err());
+        let ring_lwe_keys = keygen_string(&params, None);
+        assert!(hybrid_encrypt_string(ring_lwe_keys.get("public").unwrap(), "hello", &params).is_err());
+    }
+
+    // Test that both sides of a key exchange agree, deterministically and over the wire format
+    #[test]
+    pub fn test_key_exchange() {
+        let params = Parameters::default();
+        let (state, first) = initiate(&params, Some(1));
+        let (reply, responder_key) = respond(&first, &params, Some(2));
+        assert_eq!(finish(state, &reply, &params), responder_key);
+
+        // fixed seeds give the same session key every time
+        let (state, first) = initiate(&params, Some(1));
+        let (reply, again) = respond(&first, &params, Some(2));
+        assert_eq!((finish(state, &reply, &params), again), (responder_key, responder_key));
+
+        let first = InitiatorMessage::from_base64(&first.to_base64(&params), &params).unwrap();
+        let reply = ResponderMessage::from_base64(&reply.to_base64(&params), &params).unwrap();
+        assert_eq!(respond(&first, &params, Some(2)), (reply, responder_key));
+        assert!(ResponderMessage::from_base64(&first.to_base64(&params), &params).is_err());
+
+        // the authenticated variant only completes between the expected long-term keys
+        let sig_params = SignatureParameters::default();
+        let (alice_vk, alice) = sign_keygen(&sig_params, Some(3));
+        let (bob_vk, bob) = sign_keygen(&sig_params, Some(4));
+        let (mallory_vk, mallory) = sign_keygen(&sig_params, Some(5));
+        let (state, first) = initiate_authenticated(&alice, &bob_vk, &params, &sig_params, Some(6));
+        let (reply, bob_key) = respond_authenticated(&bob, &alice_vk, &first, &params, &sig_params, Some(7)).unwrap();
+        assert!(respond_authenticated(&bob, &mallory_vk, &first, &params, &sig_params, Some(7)).is_err());
+        assert_eq!(finish_authenticated(state, &reply, &params, &sig_params), Ok(bob_key));
+        assert_ne!(bob_key, responder_key);
+
+        // a reply signed by anyone but the expected responder is rejected; every session draws
+        // fresh ephemeral values, and alice's signatures differ with the transcript
+        let (state, again) = initiate_authenticated(&alice, &bob_vk, &params, &sig_params, None);
+        let (_, to_mallory) = initiate_authenticated(&alice, &mallory_vk, &params, &sig_params, None);
+        assert_ne!(again.signature, first.signature);
+        assert_ne!(to_mallory.signature, again.signature);
+        let (forged, _) = respond_authenticated(&mallory, &alice_vk, &to_mallory, &params, &sig_params, None).unwrap();
+        assert!(finish_authenticated(state, &forged, &params, &sig_params).is_err());
+    }
+
+    // Test the secure channel handshake and that sealed bodies only open in the right context
+    #[test]
+    pub fn test_secure_channel() {
+        let (params, sig_params) = (Parameters::default(), SignatureParameters::default());
+        let (server_vk, server_sk) = sign_keygen(&sig_params, None);
+        let (handshake, hello) = client_hello(&server_vk, &params);
+        let (reply, server_session) = server_accept(&server_sk, &hello, &params, &sig_params).unwrap();
+        let session = handshake.finish(&reply, &params, &sig_params).unwrap();
+        assert_eq!(session, server_session);
+
+        let request = session.seal(Direction::Request, "/encrypt", 0, b"{}");
+        assert_eq!(server_session.open(Direction::Request, "/encrypt", 0, &request), Ok(b"{}".to_vec()));
+        assert!(server_session.open(Direction::Request, "/decrypt", 0, &request).is_err());
+        assert!(server_session.open(Direction::Request, "/encrypt", 1, &request).is_err());
+        assert!(session.open(Direction::Response, "/encrypt", 0, &request).is_err());
+        // a response only answers the request with its sequence number
+        let response = server_session.seal(Direction::Response, "/encrypt", 1, b"{}");
+        assert!(session.open(Direction::Response, "/encrypt", 0, &response).is_err());
+
+        // a server without the pinned signing key cannot complete the handshake
+        let (_, impostor) = sign_keygen(&sig_params, None);
+        let (handshake, hello) = client_hello(&server_vk, &params);
+        let (reply, _) = server_accept(&impostor, &hello, &params, &sig_params).unwrap();
+        assert!(handshake.finish(&reply, &params, &sig_params).is_err());
+        assert!(server_accept(&server_sk, "not a hello", &params, &sig_params).is_err());
+
+        // over HTTP, the middleware refuses replays, unsealed bodies and unknown sessions
+        use crate::api::channel::ChannelState;
+        use crate::api::routes::router;
+        use crate::channel::{SESSION_HEADER, SEQUENCE_HEADER};
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let app = router(ChannelState::new(server_sk));
+        let call = |request: Request<Body>| runtime.block_on(async {
+            let response = app.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            (status, String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap())
+        });
+        let (handshake, hello) = client_hello(&server_vk, &params);
+        let handshake_request = serde_json::json!({ "hello": hello }).to_string();
+        let (status, reply) = call(Request::post("/handshake").header("content-type", "application/json").body(Body::from(handshake_request.clone())).unwrap());
+        assert_eq!(status, StatusCode::OK);
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        let session = handshake.finish(reply["reply"].as_str().unwrap(), &params, &sig_params).unwrap();
+
+        let health = |id: &str, sequence: Option<&str>, body: String| {
+            let mut request = Request::get("/health").header(SESSION_HEADER, id);
+            if let Some(sequence) = sequence {
+                request = request.header(SEQUENCE_HEADER, sequence);
+            }
+            call(request.body(Body::from(body)).unwrap())
+        };
+        let sealed = session.seal(Direction::Request, "/health", 0, b"");
+        let (status, body) = health(&session.id, Some("0"), sealed.clone());
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(session.open(Direction::Response, "/health", 0, &body), Ok(b"OK".to_vec()));
+        assert_eq!(health(&session.id, Some("0"), sealed.clone()).0, StatusCode::CONFLICT);
+        assert_eq!(health(&session.id, Some("1"), sealed.clone()).0, StatusCode::BAD_REQUEST);
+        assert_eq!(health(&session.id, None, sealed.clone()).0, StatusCode::BAD_REQUEST);
+        assert_eq!(health(&session.id, Some("1"), String::new()).0, StatusCode::BAD_REQUEST);
+        assert_eq!(health("unknown", Some("1"), sealed).0, StatusCode::UNAUTHORIZED);
+        let (status, body) = health(&session.id, Some("5"), session.seal(Direction::Request, "/health", 5, b""));
+        assert_eq!(status, StatusCode::OK);
+        assert!(session.open(Direction::Response, "/health", 0, &body).is_err());
+        assert_eq!(health(&session.id, Some("4"), session.seal(Direction::Request, "/health", 4, b"")).0, StatusCode::CONFLICT);
+        // requests without the header are served in the clear
+        assert_eq!(call(Request::get("/health").body(Body::empty()).unwrap()), (StatusCode::OK, String::from("OK")));
+
+        // without a configured signing key the channel refuses handshakes and sealed requests
+        let disabled = router(ChannelState::disabled());
+        let call = |request: Request<Body>| runtime.block_on(async { disabled.clone().oneshot(request).await.unwrap().status() });
+        assert_eq!(call(Request::post("/handshake").header("content-type", "application/json").body(Body::from(handshake_request)).unwrap()), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(call(Request::get("/health").header(SESSION_HEADER, session.id.as_str()).header(SEQUENCE_HEADER, "6").body(Body::empty()).unwrap()), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(call(Request::get("/health").body(Body::empty()).unwrap()), StatusCode::OK);
+    }
+
+    // Test one-time prekeys: signature checks, and that a message needs both the long-term and prekey secrets
+    #[test]
+    pub fn test_prekeys() {
+        let (params, sig_params) = (Parameters::default(), SignatureParameters::default());
+        let identity = keygen_string(&params, None);
+        let signing = sign_keygen_string(&sig_params, None);
+        let other = sign_keygen_string(&sig_params, None);
+        let (identity_pk, identity_sk, verifying_key) = (identity.get("public").unwrap(), identity.get("secret").unwrap(), signing.get("public").unwrap());
+        let prekeys = generate_prekeys(signing.get("secret").unwrap(), 2, &params, &sig_params).unwrap();
+        let (prekey, prekey_sk) = &prekeys[0];
+        assert_ne!(verify_prekey(verifying_key, prekey, &sig_params), verify_prekey(verifying_key, &prekeys[1].0, &sig_params));
+        assert!(verify_prekey(other.get("public").unwrap(), prekey, &sig_params).is_err());
+        assert!(prekey_encrypt(identity_pk, other.get("public").unwrap(), prekey, "hello", &params, &sig_params).is_err());
+
+        let ciphertext = prekey_encrypt(identity_pk, verifying_key, prekey, "hello", &params, &sig_params).unwrap();
+        assert_eq!(prekey_id(&ciphertext), verify_prekey(verifying_key, prekey, &sig_params));
+        assert_eq!(prekey_decrypt(identity_sk, prekey_sk, &ciphertext, &params), Ok(String::from("hello")));
+
+        // once the prekey secret is gone, the long-term key alone does not open the message
+        assert!(prekey_decrypt(identity_sk, &prekeys[1].1, &ciphertext, &params).is_err());
+        assert!(prekey_decrypt(keygen_string(&params, None).get("secret").unwrap(), prekey_sk, &ciphertext, &params).is_err());
+
+        // the last-resort prekey is signed as such, so neither kind passes for the other
+        let (last_resort, last_resort_sk) = generate_last_resort_prekey(signing.get("secret").unwrap(), &params, &sig_params).unwrap();
+        assert!(verify_last_resort_prekey(verifying_key, &last_resort, &sig_params).is_ok_and(|(_, created)| created > 0));
+        assert!(verify_prekey(verifying_key, &last_resort, &sig_params).is_err());
+        assert!(verify_last_resort_prekey(verifying_key, prekey, &sig_params).is_err());
+        let ciphertext = prekey_encrypt(identity_pk, verifying_key, &last_resort, "hello", &params, &sig_params).unwrap();
+        assert_eq!(prekey_decrypt(identity_sk, &last_resort_sk, &ciphertext, &params), Ok(String::from("hello")));
+
+        // the service stores only signed public halves and rate limits one-time prekeys per
+        // owner, falling back to the last-resort prekey
+        use crate::api::prekeys::{decrypt_handler, fetch_handler, upload_handler, PrekeyStore};
+        use crate::models::{PrekeyDecryptRequest, PrekeyFetchRequest, PrekeyUploadRequest};
+        use axum::extract::{Json, State};
+        use axum::http::StatusCode;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let store = PrekeyStore::new();
+        let upload = |prekeys: Vec<String>, last_resort: Option<String>| runtime.block_on(upload_handler(
+            State(store.clone()),
+            Json(PrekeyUploadRequest { verifying_key: verifying_key.clone(), prekeys, last_resort }),
+        ));
+        let fetch = || runtime.block_on(fetch_handler(State(store.clone()), Json(PrekeyFetchRequest { verifying_key: verifying_key.clone() })));
+        assert_eq!(fetch().err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+        assert!(upload(vec![last_resort.clone()], None).is_err());
+        assert!(upload(Vec::new(), Some(prekey.clone())).is_err());
+        // an empty upload is refused and creates no entry
+        assert_eq!(upload(Vec::new(), None).err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(fetch().err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+        let many = generate_prekeys(signing.get("secret").unwrap(), 12, &params, &sig_params).unwrap();
+        assert_eq!(upload(many.iter().map(|(prekey, _)| prekey.clone()).collect(), None).unwrap().0.available, 12);
+        for (prekey, _) in &many[..10] {
+            let fetched = fetch().unwrap().0;
+            assert_eq!((&fetched.prekey, fetched.last_resort), (prekey, false));
+        }
+        assert_eq!(fetch().err().map(|e| e.0), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(upload(Vec::new(), Some(last_resort.clone())).unwrap().0.available, 2);
+        let fetched = fetch().unwrap().0;
+        assert_eq!((fetched.prekey, fetched.last_resort), (last_resort.clone(), true));
+
+        // prekeys accepted once, even if already handed out, and older last-resort prekeys are
+        // refused as replays
+        assert_eq!(upload(vec![many[0].0.clone()], None).err().map(|e| e.0), Some(StatusCode::CONFLICT));
+        assert_eq!(upload(vec![many[11].0.clone()], None).err().map(|e| e.0), Some(StatusCode::CONFLICT));
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let (newer, _) = generate_last_resort_prekey(signing.get("secret").unwrap(), &params, &sig_params).unwrap();
+        assert!(upload(Vec::new(), Some(newer.clone())).is_ok());
+        assert_eq!(upload(Vec::new(), Some(last_resort.clone())).err().map(|e| e.0), Some(StatusCode::CONFLICT));
+        assert!(upload(Vec::new(), Some(newer)).is_ok());
+
+        // prekey secrets are only taken over the secure channel
+        let request = PrekeyDecryptRequest { secret_key: identity_sk.clone(), prekey_secret_key: last_resort_sk, ciphertext, password: None };
+        assert_eq!(runtime.block_on(decrypt_handler(None, Json(request))).err().map(|e| e.0), Some(StatusCode::FORBIDDEN));
+    }
+
+    // Test a thread ratchet across KEM steps, with out-of-order delivery, replays and a persisted session
+    #[test]
+    pub fn test_thread_ratchet() {
+        let params = Parameters::new(256, 12289, 2);
+        let bob_keys = keygen_string(&params, None);
+        let (bob_pk, bob_sk) = (bob_keys.get("public").unwrap(), bob_keys.get("secret").unwrap());
+        let mut alice = ThreadSession::initiate("thread-1", bob_pk).unwrap();
+        let mut bob = ThreadSession::accept("thread-1", bob_pk, bob_sk).unwrap();
+        assert!(bob.encrypt("too early", &params).is_err());
+
+        // enough messages for two KEM steps, delivered out of order around the epoch boundary
+        let messages: Vec<String> = (0..KEM_INTERVAL + 3).map(|i| alice.encrypt(&format!("mail {}", i), &params).unwrap()).collect();
+        let mut order: Vec<usize> = (0..messages.len()).collect();
+        order.swap(2, 5);
+        order.swap(KEM_INTERVAL as usize - 1, KEM_INTERVAL as usize + 1);
+        for i in order {
+            assert_eq!(bob.decrypt(&messages[i], &params), Ok(format!("mail {}", i)));
+        }
+        assert!(bob.decrypt(&messages[3], &params).is_err());
+
+        // a persisted session carries on, and replies run their own KEM steps
+        let mut bob = ThreadSession::import_state(&bob.export_state()).unwrap();
+        for i in 0..KEM_INTERVAL + 1 {
+            let reply = bob.encrypt(&format!("reply {}", i), &params).unwrap();
+            assert_eq!(alice.decrypt(&reply, &params), Ok(format!("reply {}", i)));
+        }
+        let later = alice.encrypt("after the replies", &params).unwrap();
+        assert_eq!(bob.decrypt(&later, &params), Ok(String::from("after the replies")));
+
+        // a message two epochs ahead waits for the epoch before it, and other threads do not mix
+        let pending: Vec<String> = (0..2 * KEM_INTERVAL).map(|i| alice.encrypt(&i.to_string(), &params).unwrap()).collect();
+        let last = pending.last().unwrap();
+        assert!(bob.decrypt(last, &params).is_err());
+        let mut other = ThreadSession::accept("thread-2", bob_pk, bob_sk).unwrap();
+        assert!(other.decrypt(&pending[0], &params).is_err());
+        for i in [KEM_INTERVAL as usize, pending.len() - 1, 0] {
+            assert_eq!(bob.decrypt(&pending[i], &params), Ok(i.to_string()));
+        }
+    }
+
+    // Test threshold decryption for several (k, n): any k shares decrypt, fewer do not
+    #[test]
+    pub fn test_threshold_decryption() {
+        // a modulus just below 2^31 leaves room for smudging noise of 2^20 noise deviations, and
+        // n = 2048 keeps that modulus above 128 bits of security
+        let params = Parameters::new(2048, 2147389441, 2);
+        let (pk, sk) = keygen(&params, Some(7));
+        let m = Polynomial::new(vec![1, 0, 1, 1, 0, 1]);
+        let ct = encrypt(&pk, &m, &params, Some(8));
+
+        for (k, n) in [(1, 1), (2, 3), (3, 5), (4, 4), (5, 7)] {
+            let shares = share_secret_key(&sk, k, n, &params, Some(9)).unwrap();
+            // the first k and the last k shareholders
+            for group in [&shares[..k], &shares[n - k..]] {
+                let participants: Vec<i64> = group.iter().map(|share| share.index).collect();
+                let partials: Vec<_> = group.iter()
+                    .map(|share| partial_decrypt(share, &ct, &participants, &params, Some(10 + share.index as u64)).unwrap())
+                    .collect();
+                assert_eq!(combine(&ct, &partials, &params), Ok(m.clone()), "k = {}, n = {}", k, n);
+            }
+            if k > 1 {
+                let too_few: Vec<i64> = (1..k as i64).collect();
+                assert!(partial_decrypt(&shares[0], &ct, &too_few, &params, None).is_err());
+                // k - 1 shares interpolated as if they were enough give an unrelated result
+                let mut short = shares[..k - 1].to_vec();
+                short.iter_mut().for_each(|share| share.threshold = k - 1);
+                let partials: Vec<_> = short.iter()
+                    .map(|share| partial_decrypt(share, &ct, &too_few, &params, None).unwrap())
+                    .collect();
+                assert_ne!(combine(&ct, &partials, &params), Ok(m.clone()));
+            }
+        }
+        assert!(share_secret_key(&sk, 4, 3, &params, None).is_err());
+        assert!(smudging_bound(&params, 3, 1) > 0);
+
+        // too little smudging noise, too little security, a composite modulus and participants
+        // outside 1..q are refused
+        let weak = Parameters::new(1024, 2147473409, 2);
+        assert!(smudging_bits(&weak, 5, 1) >= MIN_SMUDGING_BITS);
+        let (weak_pk, weak_sk) = keygen(&weak, None);
+        assert!(share_secret_key(&weak_sk, 2, 3, &weak, None).is_err());
+        let weak_share = KeyShare { index: 1, threshold: 2, key_parties: 1, share: weak_sk };
+        assert!(partial_decrypt(&weak_share, &encrypt(&weak_pk, &m, &weak, None), &[1, 2], &weak, None).is_err());
+        let default = Parameters::default();
+        assert!(smudging_bits(&default, 2, 1) < MIN_SMUDGING_BITS);
+        let (default_pk, default_sk) = keygen(&default, None);
+        let default_shares = share_secret_key(&default_sk, 2, 3, &default, None).unwrap();
+        let default_ct = encrypt(&default_pk, &m, &default, None);
+        assert!(partial_decrypt(&default_shares[0], &default_ct, &[1, 2], &default, None).is_err());
+        let composite = Parameters { q: 12289 * 3, ..Parameters::default() };
+        assert!(share_secret_key(&default_sk, 2, 3, &composite, None).is_err());
+        let shares = share_secret_key(&sk, 2, 3, &params, None).unwrap();
+        assert!(partial_decrypt(&shares[0], &ct, &[1, 0], &params, None).is_err());
+        assert!(partial_decrypt(&shares[0], &ct, &[1, params.q + 2], &params, None).is_err());
+
+        // strings, and partials computed for different participant sets do not combine
+        let keys = keygen_string(&params, None);
+        let ciphertext = encrypt_string(keys.get("public").unwrap(), &String::from("escrow mailbox"), &params, None);
+        let shares = share_secret_key_string(keys.get("secret").unwrap(), 2, 3, &params).unwrap();
+        let partials = [
+            partial_decrypt_string(&shares[0], &ciphertext, &[1, 3], &params).unwrap(),
+            partial_decrypt_string(&shares[2], &ciphertext, &[1, 3], &params).unwrap(),
+        ];
+        assert_eq!(combine_string(&ciphertext, &partials, &params), Ok(String::from("escrow mailbox")));
+        let mismatched = [partials[0].clone(), partial_decrypt_string(&shares[1], &ciphertext, &[1, 2], &params).unwrap()];
+        assert!(combine_string(&ciphertext, &mismatched, &params).is_err());
+
+        // untrusted participant counts and share indices are rejected without panicking
+        for count in [-1, 0, i64::MAX] {
+            let mut data = vec![1, count, 1, 3];
+            data.extend(vec![0; params.n]);
+            assert!(combine_string(&ciphertext, &[compress(&data)], &params).is_err(), "count = {}", count);
+        }
+        for index in [0, -1] {
+            let mut data = vec![index, 2, 1];
+            data.extend(vec![0; params.n]);
+            assert!(KeyShare::from_base64(&compress(&data), &params).is_err(), "index = {}", index);
+        }
+    }
+
+    // Test distributed key generation: parties agree on a key that k of them can decrypt under
+    #[test]
+    pub fn test_dkg() {
+        let params = Parameters::new(2048, 2147389441, 2);
+        for (k, n) in [(1, 2), (2, 3), (3, 3)] {
+            let parties: Vec<DkgParty> = (1..=n).map(|i| DkgParty::new(i, n as usize, k, &params, Some(100 * i as u64)).unwrap()).collect();
+            let outputs = run_in_process(parties, &params).unwrap();
+            assert!(outputs.iter().all(|output| output.public_key == outputs[0].public_key));
+
+            let ciphertext = encrypt_string(&outputs[0].public_key, &String::from("joint"), &params, None);
+            let group = &outputs[n as usize - k..];
+            let participants: Vec<i64> = group.iter().map(|output| output.share.index).collect();
+            let partials: Vec<String> = group.iter()
+                .map(|output| partial_decrypt_string(&output.share.to_base64(&params), &ciphertext, &participants, &params).unwrap())
+                .collect();
+            assert_eq!(combine_string(&ciphertext, &partials, &params), Ok(String::from("joint")), "k = {}, n = {}", k, n);
+        }
+
+        // the joint key's noise grows with the number of parties, so large groups are refused
+        assert_eq!(run_in_process((1..=2).map(|i| DkgParty::new(i, 2, 2, &params, None).unwrap()).collect(), &params).unwrap()[0].share.key_parties, 2);
+        assert!(DkgParty::new(1, 64, 5, &params, None).is_err());
+        assert!(DkgParty::new(1, 3, 2, &Parameters::default(), None).is_err());
+        assert!(DkgParty::new(1, 35, 2, &params, None).is_ok());
+        assert!(DkgParty::new(1, 36, 2, &params, None).is_err());
+        // enough smudging room, but too little security
+        let weak = Parameters::new(1024, 2147473409, 2);
+        assert!(smudging_bits(&weak, 2, 3) >= MIN_SMUDGING_BITS);
+        assert!(DkgParty::new(1, 3, 2, &weak, None).is_err());
+
+        // a party that changes its seed contribution or its b after committing is caught
+        let mut parties: Vec<DkgParty> = (1..=2).map(|i| DkgParty::new(i, 2, 2, &params, None).unwrap()).collect();
+        let first = parties[0].start();
+        let second = parties[1].start();
+        let seed_from_second = parties[1].handle(first[0].clone(), &params).unwrap();
+        let seed_from_first = parties[0].handle(second[0].clone(), &params).unwrap();
+        let mut steered = seed_from_second[0].clone();
+        if let DkgMessage::Seed { seed, .. } = &mut steered {
+            seed[0] ^= 1;
+        }
+        let mut steered_party = DkgParty::new(1, 2, 2, &params, None).unwrap();
+        steered_party.start();
+        steered_party.handle(second[0].clone(), &params).unwrap();
+        assert!(steered_party.handle(steered, &params).is_err());
+
+        let commitment_from_second = parties[1].handle(seed_from_first[0].clone(), &params).unwrap();
+        let commitment_from_first = parties[0].handle(seed_from_second[0].clone(), &params).unwrap();
+        let mut round4 = parties[1].handle(commitment_from_first[0].clone(), &params).unwrap();
+        parties[0].handle(commitment_from_second[0].clone(), &params).unwrap();
+        if let DkgMessage::Reveal { b, .. } = &mut round4[0] {
+            b[0] = (b[0] + 1) % params.q;
+        }
+        let deal = round4.iter().find(|message| message.recipient() == Some(1)).unwrap().clone();
+        parties[0].handle(deal, &params).unwrap();
+        assert!(parties[0].handle(round4[0].clone(), &params).is_err());
+    }
+
+    // Test secret key backup: any k shares recover the key, mistyped or mixed shares are refused
+    #[test]
+    pub fn test_secret_key_backup() {
+        let params = Parameters::default();
+        let keys = keygen_string(&params, None);
+        let sk = SecretKey::from_base64(keys.get("secret").unwrap());
+        let shares = sk.split_backup(3, 5).unwrap();
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let chosen: Vec<String> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(SecretKey::recover_from_shares(&chosen), Ok(sk.clone()));
+        }
+        assert_eq!(SecretKey::recover_from_shares(&shares), Ok(sk.clone()));
+        assert!(SecretKey::recover_from_shares(&shares[..2]).is_err());
+        assert!(sk.split_backup(4, 3).is_err());
+
+        // shares copied by hand with line breaks and lowercase still work
+        let copied: Vec<String> = shares[..3].iter().map(|share| {
+            let (head, tail) = share.split_at(60);
+            format!("{}\n  {}", head.to_lowercase(), tail)
+        }).collect();
+        assert_eq!(SecretKey::recover_from_shares(&copied), Ok(sk.clone()));
+
+        // a mistyped share fails its checksum, and shares of another backup do not mix
+        let mut typo = shares[0].clone().into_bytes();
+        typo[40] = if typo[40] == b'0' { b'1' } else { b'0' };
+        assert!(SecretKey::recover_from_shares(&[String::from_utf8(typo).unwrap(), shares[1].clone(), shares[2].clone()]).is_err());
+        let other = sk.split_backup(3, 5).unwrap();
+        assert!(SecretKey::recover_from_shares(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+
+        // a non-ternary key round trips through the wide format
+        let wide = SecretKey::from_polynomial(&Polynomial::new(vec![5, -7, 12288, 0, 1]));
+        assert_eq!(SecretKey::recover_from_shares(&wide.split_backup(1, 1).unwrap()), Ok(wide));
+    }
+
+    // Test decryption proofs: the proof checks out only for the true plaintext, ciphertext and key
+    #[test]
+    pub fn test_decryption_proof() {
+        // the largest modulus the arithmetic supports only fits the masked noise at n = 1024,
+        // which falls below the security floor, so the proof itself is tested without it
+        let params = Parameters::new(1024, 2147473409, 2);
+        let keys = keygen_string(&params, None);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+        // 200 bytes span two blocks
+        let message = "legal hold ".repeat(18) + "ok";
+        let ciphertext = encrypt_string(pk, &message, &params, None);
+        let proof = prove_decryption_unchecked(sk, pk, &ciphertext, &params).unwrap();
+        assert!(verify_decryption_unchecked(pk, &ciphertext, &message, &proof, &params));
+        assert!(!verify_decryption_unchecked(pk, &ciphertext, &message.replace("ok", "no"), &proof, &params));
+
+        // the proof does not carry over to another ciphertext or another key
+        let other_ciphertext = encrypt_string(pk, &message, &params, None);
+        assert!(!verify_decryption_unchecked(pk, &other_ciphertext, &message, &proof, &params));
+        let other_keys = keygen_string(&params, None);
+        assert!(!verify_decryption_unchecked(other_keys.get("public").unwrap(), &ciphertext, &message, &proof, &params));
+        assert!(prove_decryption_unchecked(other_keys.get("secret").unwrap(), pk, &ciphertext, &params).is_err());
+
+        // the key holder cannot claim the bitwise complement of a full block: "=\x7f" is
+        // 0x3D 0x7F and its complement 0xC2 0x80 is "\u{80}"; with t = 2, 2*floor(q/2) = -1
+        // mod q keeps the scaled difference short under challenges with an even coefficient sum
+        let full_block = "=\u{7f}".repeat(64);
+        let ciphertext = encrypt_string(pk, &full_block, &params, None);
+        let complement = "\u{80}".repeat(64);
+        let forged = prove_claim(sk, pk, &ciphertext, &complement, &params).unwrap();
+        assert!(!verify_decryption_unchecked(pk, &ciphertext, &complement, &forged, &params));
+
+        // q = 12289 leaves no room to mask the noise
+        let default = Parameters::default();
+        let keys = keygen_string(&default, None);
+        let ciphertext = encrypt_string(keys.get("public").unwrap(), &message, &default, None);
+        assert!(prove_decryption_unchecked(keys.get("secret").unwrap(), keys.get("public").unwrap(), &ciphertext, &default).is_err());
+
+        // the public functions refuse parameters below 128 bits, and at n = 2048 q = 2^31 is too
+        // small for the noise
+        assert!(prove_decryption(sk, pk, &other_ciphertext, &params).is_err());
+        assert!(!verify_decryption(pk, &other_ciphertext, &message, &prove_decryption_unchecked(sk, pk, &other_ciphertext, &params).unwrap(), &params));
+        let secure = Parameters::new(2048, 2147389441, 2);
+        let keys = keygen_string(&secure, None);
+        let ciphertext = encrypt_string(keys.get("public").unwrap(), &message, &secure, None);
+        assert!(prove_decryption(keys.get("secret").unwrap(), keys.get("public").unwrap(), &ciphertext, &secure).is_err());
+    }
+
+    // Test proof of possession: only the holder of the secret key answers a registration challenge
+    #[test]
+    pub fn test_possession() {
+        let params = Parameters::default();
+        let keys = keygen_string(&params, None);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+        let (challenge, expected) = possession_challenge(pk, &params).unwrap();
+        let response = possession_response(sk, pk, &challenge, &params).unwrap();
+        assert!(possession_verified(&expected, &response));
+        assert!(!possession_verified(&expected, &response[..response.len() - 1]));
+
+        // a copied public key does not help without its secret key
+        let other = keygen_string(&params, None);
+        let forged = possession_response(other.get("secret").unwrap(), pk, &challenge, &params);
+        assert!(forged.map_or(true, |forged| !possession_verified(&expected, &forged)));
+
+        // the answer is tied to the challenge and the key it was issued for
+        let (second, second_expected) = possession_challenge(pk, &params).unwrap();
+        assert!(!possession_verified(&second_expected, &response));
+        assert!(!possession_verified(&second_expected, &possession_response(sk, other.get("public").unwrap(), &second, &params).unwrap()));
+        assert!(possession_challenge("AAAA", &params).is_err());
+
+        // the service registers the key once the owner answers, and rate limits challenges per key
+        use crate::api::registry::{challenge_handler, complete_handler, KeyRegistry};
+        use crate::models::{RegisterChallengeRequest, RegisterCompleteRequest};
+        use axum::extract::{Json, State};
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let registry = KeyRegistry::new();
+        let request = || runtime.block_on(challenge_handler(State(registry.clone()), Json(RegisterChallengeRequest { public_key: pk.clone() })));
+        let issued = request().unwrap().0;
+        let response = possession_response(sk, pk, &issued.challenge, &params).unwrap();
+        let fingerprint = runtime.block_on(complete_handler(State(registry.clone()), Json(RegisterCompleteRequest { id: issued.id, response })))
+            .unwrap().0.fingerprint;
+        assert_eq!(registry.get(&fingerprint).as_ref(), Some(pk));
+        for _ in 1..5 {
+            assert!(request().is_ok());
+        }
+        assert_eq!(request().err().map(|e| e.0), Some(axum::http::StatusCode::TOO_MANY_REQUESTS));
+
+        // a full table evicts the oldest challenge rather than refusing new ones
+        let registry = KeyRegistry::with_max_pending(2);
+        let challenge = |public_key: &String| runtime.block_on(challenge_handler(State(registry.clone()), Json(RegisterChallengeRequest { public_key: public_key.clone() }))).unwrap().0;
+        let keys: Vec<_> = (0..3).map(|_| keygen_string(&params, None)).collect();
+        let issued: Vec<_> = keys.iter().map(|keys| challenge(keys.get("public").unwrap())).collect();
+        let complete = |i: usize| {
+            let response = possession_response(keys[i].get("secret").unwrap(), keys[i].get("public").unwrap(), &issued[i].challenge, &params).unwrap();
+            runtime.block_on(complete_handler(State(registry.clone()), Json(RegisterCompleteRequest { id: issued[i].id.clone(), response }))).map(|r| r.0.fingerprint)
+        };
+        assert_eq!(complete(0).err().map(|e| e.0), Some(axum::http::StatusCode::NOT_FOUND));
+        assert!(complete(1).is_ok());
+        assert!(complete(2).is_ok());
+    }
+
+    // Test key validation: matching pairs pass, mismatched or malformed keys are caught
+    #[test]
+    pub fn test_validate_keys() {
+        let params = Parameters::default();
+        let keys = keygen_string(&params, None);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+        assert_eq!(validate_public_key(pk, &params), Ok(()));
+        assert_eq!(validate_keypair(pk, sk, &params), Ok(()));
+        let other = keygen_string(&params, None);
+        assert!(validate_keypair(pk, other.get("secret").unwrap(), &params).is_err());
+        assert!(validate_keypair(other.get("public").unwrap(), sk, &params).is_err());
+
+        // coefficients outside (-q/2, q/2], wrong lengths and an all-zero secret key
+        let mut coeffs = decompress(pk);
+        coeffs[3] = params.q;
+        assert!(validate_public_key(&compress(&coeffs), &params).is_err());
+        coeffs.pop();
+        assert!(validate_public_key(&compress(&coeffs), &params).is_err());
+        assert!(validate_public_key("not base64", &params).is_err());
+        assert!(validate_keypair(pk, &compress(&vec![0; params.n]), &params).is_err());
+
+        // module-LWE keys are checked against the whole matrix
+        let module_params = Parameters::new(256, 7681, 2).with_rank(3);
+        let module_keys = module_keygen_string(&module_params, None);
+        assert_eq!(validate_keypair(module_keys.get("public").unwrap(), module_keys.get("secret").unwrap(), &module_params), Ok(()));
+        assert!(validate_public_key(module_keys.get("public").unwrap(), &params).is_err());
+    }
+
+    // Test blind decryption end to end: the /decrypt handler only sees the masked plaintext
+    #[test]
+    pub fn test_blind_decryption() {
+        use crate::api::handlers::decrypt_handler;
+        use crate::models::{DecryptRequest, Scheme};
+        use axum::Json;
+
+        let params = Parameters::default();
+        let keys = keygen_string(&params, None);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+        // two blocks, ending in a block that is mostly zero padding
+        let message = String::from("Subject: quarterly numbers\n").repeat(5);
+        let ciphertext = encrypt_string(pk, &message, &params, None);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let service_decrypt = |ciphertext: String| {
+            let request = DecryptRequest { secret_key: sk.clone(), ciphertext, password: None, scheme: Scheme::RingLwe };
+            runtime.block_on(decrypt_handler(Json(request))).unwrap().0.message
+        };
+        let (blinded, mask) = blind(pk, &ciphertext, &params).unwrap();
+        let masked = service_decrypt(blinded.clone());
+        assert!(!masked.contains("quarterly"));
+        assert_eq!(unblind(&masked, &mask), Ok(message.clone()));
+
+        // every blinding looks different, and a mask does not unblind another blinding
+        let (other_blinded, other_mask) = blind(pk, &ciphertext, &params).unwrap();
+        assert_ne!(blinded, other_blinded);
+        assert_ne!(unblind(&service_decrypt(other_blinded), &mask), Ok(message.clone()));
+        assert_ne!(mask, other_mask);
+        assert!(blind(pk, "AAAA", &params).is_err());
+    }
+
+    // Test rerandomization: fresh ciphertexts of the same message, for ring and module keys
+    #[test]
+    pub fn test_rerandomize() {
+        let params = Parameters::default();
+        let (pk, sk) = keygen(&params, None);
+        let m = Polynomial::new(vec![1, 1, 0, 1]);
+        let ct = encrypt(&pk, &m, &params, None);
+        let fresh = rerandomize(&pk, &ct, &params, None);
+        assert!(fresh[0] != ct[0] && fresh[1] != ct[1]);
+        assert_eq!(decrypt(&sk, &fresh, &params), m);
+
+        // forwarding through many servers only adds noise
+        let keys = keygen_string(&params, None);
+        let (pk, sk) = (keys.get("public").unwrap(), keys.get("secret").unwrap());
+        let message = String::from("forwarded mail");
+        let mut ciphertext = encrypt_string(pk, &message, &params, None);
+        for _ in 0..30 {
+            let next = rerandomize_string(pk, &ciphertext, &params).unwrap();
+            assert_ne!(next, ciphertext);
+            ciphertext = next;
+        }
+        assert_eq!(decrypt_string(sk, &ciphertext, &params), message);
+        assert!(rerandomize_string(pk, "AAAA", &params).is_err());
+        assert!(rerandomize_string("AAAA", &ciphertext, &params).is_err());
+
+        let module_params = Parameters::new(256, 7681, 2).with_rank(2);
+        let module_keys = module_keygen_string(&module_params, None);
+        let module_pk = module_keys.get("public").unwrap();
+        let ciphertext = module_encrypt_string(module_pk, &message, &module_params, None);
+        let fresh = rerandomize_string(module_pk, &ciphertext, &module_params).unwrap();
+        assert_ne!(fresh, ciphertext);
+        assert_eq!(module_decrypt_string(module_keys.get("secret").unwrap(), &fresh, &module_params), message);
+    }
+
+    // Test that anonymous messages open only for their recipient and look alike for any key
+    #[test]
+    pub fn test_anonymous_recipient() {
+        let params = Parameters::default();
+        let keys: Vec<_> = (0..4).map(|_| keygen_string(&params, None)).collect();
+        let secret_keys: Vec<String> = keys.iter().map(|k| k.get("secret").unwrap().clone()).collect();
+        let message = String::from("for your eyes only");
+
+        for (index, key) in keys.iter().enumerate() {
+            let ciphertext = anonymous_encrypt(key.get("public").unwrap(), &message, &params).unwrap();
+            assert_eq!(trial_decrypt(&secret_keys, &ciphertext, &params), Ok(Some((index, message.clone()))));
+            assert_eq!(anonymous_decrypt(&secret_keys[index], &ciphertext, &params), Ok(message.clone()));
+            let others: Vec<String> = secret_keys.iter().enumerate().filter(|(i, _)| *i != index).map(|(_, sk)| sk.clone()).collect();
+            assert_eq!(trial_decrypt(&others, &ciphertext, &params), Ok(None));
+            assert!(anonymous_decrypt(&others[0], &ciphertext, &params).is_err());
+        }
+
+        // messages to different keys have the same length and the public key appears nowhere
+        let pk = keys[0].get("public").unwrap();
+        let first = anonymous_encrypt(pk, &message, &params).unwrap();
+        let second = anonymous_encrypt(keys[1].get("public").unwrap(), &message, &params).unwrap();
+        assert_eq!(first.len(), second.len());
+        assert_ne!(first, anonymous_encrypt(pk, &message, &params).unwrap());
+        assert!(!first.contains(pk.as_str()));
+
+        assert!(anonymous_encrypt("AAAA", &message, &params).is_err());
+        assert!(trial_decrypt(&secret_keys, "AAAA", &params).is_err());
+        assert!(anonymous_encrypt(pk, &message, &Parameters::new(128, 12289, 2)).is_err());
+    }
+
     // Test fast polynomial multiplcation using NTT for small example polynomials
     #[test]
     pub fn test_polymul_fast() {
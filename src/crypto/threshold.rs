@@ -0,0 +1,353 @@
+use crate::crypto::decrypt::bits_to_message;
+use crate::crypto::kdf::sub_seed;
+use crate::crypto::search::is_prime;
+use crate::crypto::security::DEFAULT_MIN_SECURITY_BITS;
+use crate::crypto::utils::{Parameters, polymul_fast, polyadd, gen_uniform_poly, mod_coeffs, nearest_int, pad_coeffs, compress, try_decompress};
+use polynomial_ring::Polynomial;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Standard deviations of encryption noise left as headroom when sizing the smudging noise;
+/// a coefficient exceeds it with probability below 2^-140
+const TAIL_SIGMAS: f64 = 14.0;
+
+/// Smallest log2 of the ratio between the smudging bound and the encryption noise standard
+/// deviation that partial decryptions accept. A partial decryption without smudging lets
+/// whoever made the ciphertext solve for the key; with it, each coefficient of the hidden noise
+/// is revealed with probability about 2^-20. That is short of the 2^-40 or more usually asked
+/// of noise flooding, and is all a modulus below 2^31 leaves room for.
+pub const MIN_SMUDGING_BITS: f64 = 20.0;
+
+/// One shareholder's Shamir share of a secret key
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyShare {
+    /// evaluation point, from 1 to n
+    pub index: i64,
+    /// number of shares needed to decrypt
+    pub threshold: usize,
+    /// number of independent keys summed into the shared key: 1 for `share_secret_key`, the
+    /// number of parties for distributed key generation; the decryption noise grows with it
+    pub key_parties: usize,
+    pub share: Polynomial<i64>,
+}
+
+/// A shareholder's contribution to decrypting one ciphertext block
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialDecryption {
+    pub index: i64,
+    /// indices of all shareholders taking part, which fix the Lagrange coefficients
+    pub participants: Vec<i64>,
+    pub share: Polynomial<i64>,
+}
+
+impl KeyShare {
+    /// Encode as base64 coefficients index || threshold || key_parties || share
+    pub fn to_base64(&self, params: &Parameters) -> String {
+        let mut data = vec![self.index, self.threshold as i64, self.key_parties as i64];
+        data.extend(pad_coeffs(&self.share, params.n));
+        compress(&data)
+    }
+
+    /// Decode a share written by `to_base64`
+    pub fn from_base64(share: &str, params: &Parameters) -> Result<Self, String> {
+        let data = try_decompress(share)?;
+        if data.len() != params.n + 3 || data[0] < 1 || data[0] >= params.q || data[1] < 1 || data[2] < 1 {
+            return Err(String::from("malformed key share"));
+        }
+        Ok(KeyShare {
+            index: data[0],
+            threshold: data[1] as usize,
+            key_parties: data[2] as usize,
+            share: Polynomial::new(data[3..].to_vec()),
+        })
+    }
+}
+
+/// a^e mod q by square and multiply
+fn mod_pow(mut a: i64, mut e: i64, q: i64) -> i64 {
+    let mut result = 1;
+    a = a.rem_euclid(q);
+    while e > 0 {
+        if e & 1 == 1 {
+            result = result * a % q;
+        }
+        a = a * a % q;
+        e >>= 1;
+    }
+    result
+}
+
+/// Lagrange coefficient of `index` for interpolating at 0 over `participants`, mod prime q
+fn lagrange_coefficient(index: i64, participants: &[i64], q: i64) -> i64 {
+    participants.iter().filter(|&&j| j != index).fold(1, |acc, &j| {
+        acc * j % q * mod_pow(j - index, q - 2, q) % q
+    })
+}
+
+/// Refuse a modulus that is not prime, where the Lagrange coefficients' Fermat inverses are wrong,
+/// and parameters below `DEFAULT_MIN_SECURITY_BITS`: the large q smudging needs weakens a ring
+/// of degree 1024 to about 75 bits, so shared keys need n = 2048
+fn check_params(params: &Parameters) -> Result<(), String> {
+    if !is_prime(params.q) {
+        return Err(format!("threshold decryption needs a prime modulus, and {} is not prime", params.q));
+    }
+    params.check_security(DEFAULT_MIN_SECURITY_BITS)
+}
+
+/// Check a participant list: at least `threshold` distinct indices in 1..q, including `index`
+fn check_participants(index: i64, threshold: usize, participants: &[i64], q: i64) -> Result<(), String> {
+    if participants.iter().any(|&j| j < 1 || j >= q) {
+        return Err(String::from("participant indices must lie between 1 and q - 1"));
+    }
+    let mut sorted = participants.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if sorted.len() != participants.len() {
+        return Err(String::from("participants must be distinct"));
+    }
+    if participants.len() < threshold {
+        return Err(format!("{} shares are needed, {} take part", threshold, participants.len()));
+    }
+    if !participants.contains(&index) {
+        return Err(String::from("the share is not among the participants"));
+    }
+    Ok(())
+}
+
+/// Largest smudging noise coefficient each participant may add
+/// # Arguments:
+/// * `params` - ring-LWE parameters
+/// * `participants` - number of shareholders taking part
+/// * `key_parties` - number of independent keys summed into the shared key, see `KeyShare`
+/// # Returns:
+/// bound such that the encryption noise plus all smudging noise stays within `noise_bound()`
+pub fn smudging_bound(params: &Parameters, participants: usize, key_parties: usize) -> i64 {
+    let headroom = params.noise_bound() - (TAIL_SIGMAS * params.joint_noise_variance(key_parties).sqrt()).ceil() as i64;
+    (headroom / participants.max(1) as i64).max(0)
+}
+
+/// How well each participant's smudging noise hides the encryption noise
+/// # Arguments:
+/// * `params` - ring-LWE parameters
+/// * `participants` - number of shareholders taking part
+/// * `key_parties` - number of independent keys summed into the shared key, see `KeyShare`
+/// # Returns:
+/// log2 of `smudging_bound` over the encryption noise standard deviation; partial decryption
+/// refuses parameters below `MIN_SMUDGING_BITS`
+/// # Example:
+/// ```
+/// use ring_lwe::threshold::{smudging_bits, MIN_SMUDGING_BITS};
+/// // the default q = 12289 leaves no room to hide the noise
+/// assert!(smudging_bits(&ring_lwe::utils::Parameters::default(), 2, 1) < MIN_SMUDGING_BITS);
+/// let params = ring_lwe::utils::Parameters::new(2048, 2147389441, 2);
+/// assert!(smudging_bits(&params, 5, 1) >= MIN_SMUDGING_BITS);
+/// // a key from many parties has more noise to hide
+/// assert!(smudging_bits(&params, 5, 64) < MIN_SMUDGING_BITS);
+/// ```
+pub fn smudging_bits(params: &Parameters, participants: usize, key_parties: usize) -> f64 {
+    let sigma = params.joint_noise_variance(key_parties).sqrt();
+    (smudging_bound(params, participants, key_parties).max(1) as f64 / sigma).log2()
+}
+
+/// Split a secret key into n Shamir shares, any k of which can decrypt
+/// # Arguments:
+/// * `sk` - secret key polynomial from `keygen`
+/// * `k` - threshold
+/// * `n` - number of shares
+/// * `params` - ring-LWE parameters; q must be prime and larger than n, and the set must reach
+///   `DEFAULT_MIN_SECURITY_BITS`
+/// * `seed` - random seed
+/// # Returns:
+/// shares with indices 1 to n, or an error for an impossible threshold, a composite q or
+/// parameters below 128 bits of security
+/// # Example:
+/// ```
+/// use ring_lwe::threshold::{share_secret_key, partial_decrypt, combine};
+/// // about 192 bits of classical security, with room for smudging noise
+/// let params = ring_lwe::utils::Parameters::with_min_security(2048, 2147389441, 2, 128.0).unwrap();
+/// let (pk, sk) = ring_lwe::keygen::keygen(&params, None);
+/// let m = polynomial_ring::Polynomial::new(vec![1, 0, 1]);
+/// let ct = ring_lwe::encrypt::encrypt(&pk, &m, &params, None);
+///
+/// let shares = share_secret_key(&sk, 2, 3, &params, None).unwrap();
+/// let participants = [1, 3];
+/// let partials: Vec<_> = [&shares[0], &shares[2]].iter()
+///     .map(|share| partial_decrypt(share, &ct, &participants, &params, None).unwrap())
+///     .collect();
+/// assert_eq!(combine(&ct, &partials, &params), Ok(m));
+/// ```
+pub fn share_secret_key(sk: &Polynomial<i64>, k: usize, n: usize, params: &Parameters, seed: Option<u64>) -> Result<Vec<KeyShare>, String> {
+    if k == 0 || k > n {
+        return Err(format!("cannot share with threshold {} among {}", k, n));
+    }
+    if n as i64 >= params.q {
+        return Err(String::from("the modulus q must exceed the number of shares"));
+    }
+    check_params(params)?;
+    let q = params.q;
+    // random polynomial of degree k-1 per coefficient, with the key as constant term
+    let coefficients: Vec<Vec<i64>> = std::iter::once(pad_coeffs(&mod_coeffs(sk.clone(), q), params.n))
+        .chain((1..k).map(|d| pad_coeffs(&gen_uniform_poly(params.n, q, sub_seed(seed, "threshold share polynomial", d)), params.n)))
+        .collect();
+
+    Ok((1..=n as i64).map(|index| {
+        let share: Vec<i64> = (0..params.n).map(|j| {
+            coefficients.iter().rev().fold(0, |acc, c| (acc * index + c[j]).rem_euclid(q))
+        }).collect();
+        KeyShare { index, threshold: k, key_parties: 1, share: Polynomial::new(share) }
+    }).collect())
+}
+
+/// Partially decrypt a ciphertext block with one share
+/// # Arguments:
+/// * `share` - this shareholder's key share
+/// * `ct` - ciphertext block from `encrypt`
+/// * `participants` - indices of all shareholders taking part, including this one
+/// * `params` - ring-LWE parameters
+/// * `seed` - random seed for the smudging noise
+/// # Returns:
+/// lambda * ct[1] * share plus smudging noise, or an error for an invalid participant list, a
+/// composite q, parameters below `DEFAULT_MIN_SECURITY_BITS`, or parameters whose smudging
+/// noise is below `MIN_SMUDGING_BITS`
+///
+/// The Lagrange coefficient is applied before the noise is added, so the combined noise is the
+/// plain sum of each participant's noise and stays within `smudging_bound`.
+pub fn partial_decrypt(share: &KeyShare, ct: &[Polynomial<i64>; 2], participants: &[i64], params: &Parameters, seed: Option<u64>) -> Result<PartialDecryption, String> {
+    check_params(params)?;
+    check_participants(share.index, share.threshold, participants, params.q)?;
+    let bits = smudging_bits(params, participants.len(), share.key_parties);
+    if bits < MIN_SMUDGING_BITS {
+        return Err(format!(
+            "the smudging noise only covers 2^{:.1} times the encryption noise, below 2^{}; use a larger q or fewer participants",
+            bits, MIN_SMUDGING_BITS
+        ));
+    }
+    let (n, q, f, omega) = (params.n, params.q, &params.f, params.omega);
+    let lambda = lagrange_coefficient(share.index, participants, q);
+    let product = polymul_fast(&ct[1], &share.share, q, f, omega);
+
+    let bound = smudging_bound(params, participants.len(), share.key_parties);
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let smudged: Vec<i64> = pad_coeffs(&product, n).iter()
+        .map(|&c| (c * lambda + rng.gen_range(-bound..=bound)).rem_euclid(q))
+        .collect();
+    Ok(PartialDecryption { index: share.index, participants: participants.to_vec(), share: Polynomial::new(smudged) })
+}
+
+/// Combine partial decryptions of one ciphertext block
+/// # Arguments:
+/// * `ct` - ciphertext block from `encrypt`
+/// * `partials` - one partial decryption from each participant
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the plaintext polynomial, or an error when the partials disagree on the participants or
+/// some participant is missing
+pub fn combine(ct: &[Polynomial<i64>; 2], partials: &[PartialDecryption], params: &Parameters) -> Result<Polynomial<i64>, String> {
+    let Some(first) = partials.first() else {
+        return Err(String::from("no partial decryptions to combine"));
+    };
+    let mut indices: Vec<i64> = partials.iter().map(|p| p.index).collect();
+    let mut participants = first.participants.clone();
+    indices.sort_unstable();
+    participants.sort_unstable();
+    if indices != participants || partials.iter().any(|p| p.participants != first.participants) {
+        return Err(String::from("the partial decryptions do not match their participant list"));
+    }
+
+    let (q, t, f) = (params.q, params.t, &params.f);
+    let scaled_pt = partials.iter().fold(ct[0].clone(), |acc, p| polyadd(&acc, &p.share, q, f));
+    let coeffs: Vec<i64> = scaled_pt.coeffs().iter().map(|c| nearest_int(c * t, q).rem_euclid(t)).collect();
+    Ok(Polynomial::new(coeffs))
+}
+
+/// Split a base64 secret key into n base64 shares, any k of which can decrypt
+/// # Arguments:
+/// * `sk_string` - secret key from `keygen_string`
+/// * `k` - threshold
+/// * `n` - number of shares
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// base64 shares, each carrying its index and the threshold
+pub fn share_secret_key_string(sk_string: &str, k: usize, n: usize, params: &Parameters) -> Result<Vec<String>, String> {
+    let sk = Polynomial::new(try_decompress(sk_string)?);
+    Ok(share_secret_key(&sk, k, n, params, None)?.iter().map(|share| share.to_base64(params)).collect())
+}
+
+/// Split a base64 ciphertext from `encrypt_string` into its blocks
+fn ciphertext_blocks(ciphertext: &str, params: &Parameters) -> Result<Vec<[Polynomial<i64>; 2]>, String> {
+    let data = try_decompress(ciphertext)?;
+    if data.is_empty() || data.len() % (2 * params.n) != 0 {
+        return Err(String::from("malformed ciphertext"));
+    }
+    Ok(data.chunks(2 * params.n)
+        .map(|block| [Polynomial::new(block[..params.n].to_vec()), Polynomial::new(block[params.n..].to_vec())])
+        .collect())
+}
+
+/// Partially decrypt a message from `encrypt_string` with one base64 share
+/// # Arguments:
+/// * `share` - base64 share from `share_secret_key_string`
+/// * `ciphertext` - base64 ciphertext from `encrypt_string`
+/// * `participants` - indices of all shareholders taking part
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// base64 partial decryption of every block
+/// # Example:
+/// ```
+/// use ring_lwe::threshold::{share_secret_key_string, partial_decrypt_string, combine_string};
+/// let params = ring_lwe::utils::Parameters::new(2048, 2147389441, 2);
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let ciphertext = ring_lwe::encrypt::encrypt_string(keys.get("public").unwrap(), &String::from("escrow"), &params, None);
+/// let shares = share_secret_key_string(keys.get("secret").unwrap(), 2, 3, &params).unwrap();
+/// let partials: Vec<String> = shares[1..].iter()
+///     .map(|share| partial_decrypt_string(share, &ciphertext, &[2, 3], &params).unwrap())
+///     .collect();
+/// assert_eq!(combine_string(&ciphertext, &partials, &params), Ok(String::from("escrow")));
+/// ```
+pub fn partial_decrypt_string(share: &str, ciphertext: &str, participants: &[i64], params: &Parameters) -> Result<String, String> {
+    let share = KeyShare::from_base64(share, params)?;
+    let mut data = vec![share.index, participants.len() as i64];
+    data.extend(participants);
+    for ct in ciphertext_blocks(ciphertext, params)? {
+        data.extend(pad_coeffs(&partial_decrypt(&share, &ct, participants, params, None)?.share, params.n));
+    }
+    Ok(compress(&data))
+}
+
+/// Combine base64 partial decryptions into the message
+/// # Arguments:
+/// * `ciphertext` - base64 ciphertext from `encrypt_string`
+/// * `partials` - one partial decryption from each participant
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// the message, or an error when the partials are malformed or do not fit together
+pub fn combine_string(ciphertext: &str, partials: &[String], params: &Parameters) -> Result<String, String> {
+    let blocks = ciphertext_blocks(ciphertext, params)?;
+    let partials: Vec<(i64, Vec<i64>, Vec<i64>)> = partials.iter().map(|partial| {
+        let data = try_decompress(partial)?;
+        // the participant count is untrusted input, so it is range checked before any arithmetic
+        let count = data.get(1)
+            .and_then(|&count| usize::try_from(count).ok())
+            .filter(|&count| count >= 1)
+            .ok_or_else(|| String::from("malformed partial decryption"))?;
+        let expected = blocks.len().checked_mul(params.n).and_then(|len| len.checked_add(count)).and_then(|len| len.checked_add(2));
+        if expected != Some(data.len()) {
+            return Err(String::from("the partial decryption does not fit this ciphertext"));
+        }
+        Ok((data[0], data[2..2 + count].to_vec(), data[2 + count..].to_vec()))
+    }).collect::<Result<_, String>>()?;
+
+    let mut bits = Vec::new();
+    for (i, ct) in blocks.iter().enumerate() {
+        let block_partials: Vec<PartialDecryption> = partials.iter().map(|(index, participants, shares)| PartialDecryption {
+            index: *index,
+            participants: participants.clone(),
+            share: Polynomial::new(shares[i * params.n..(i + 1) * params.n].to_vec()),
+        }).collect();
+        bits.extend(pad_coeffs(&combine(ct, &block_partials, params)?, params.n));
+    }
+    Ok(bits_to_message(&bits))
+}
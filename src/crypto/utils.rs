@@ -1,11 +1,18 @@
 use polynomial_ring::Polynomial;
 use rand_distr::{Uniform, Normal, Distribution};
 use ntt::polymul_ntt;
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand::rngs::StdRng;
+use sha3::Shake128;
+use sha3::digest::{ExtendableOutput, Update, XofReader};
 use base64::{engine::general_purpose, Engine as _};
 use bincode;
 
+/// Length in bytes of the seed the public polynomial `a` is expanded from
+pub const SEED_BYTES: usize = 32;
+/// Number of i64 words a seed occupies in a serialized public key
+pub const SEED_WORDS: usize = SEED_BYTES / 8;
+
 /// Ring-LWE parameters
 #[derive(Debug)]
 pub struct Parameters {
@@ -14,23 +21,55 @@ pub struct Parameters {
     pub t: i64,       // Plaintext modulus
     pub omega: i64,   // n-th root of unity mod q
     pub f: Polynomial<i64>, // Polynomial modulus (x^n + 1 representation)
+    pub k: usize,       // Module rank, 1 for ring-LWE
     #[allow(dead_code)]
     pub sigma: f64,    // Standard deviation for normal distribution
 }
 
-/// Default parameters for ring-LWE
-impl Default for Parameters {
-    fn default() -> Self {
-        let n = 1024;
-        let q = 12289;
-        let t = 2;
+impl Parameters {
+    /// Build a parameter set for the given ring degree and moduli
+    /// # Arguments:
+    /// * `n` - polynomial modulus degree, a power of two
+    /// * `q` - ciphertext modulus, with q = 1 mod 2n so the NTT applies
+    /// * `t` - plaintext modulus
+    /// # Returns:
+    /// parameters with the matching root of unity and polynomial modulus x^n + 1
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::new(512, 12289, 2);
+    /// assert_eq!(params.f.coeffs().len(), 513);
+    /// ```
+    pub fn new(n: usize, q: i64, t: i64) -> Self {
         let omega = ntt::omega(q, 2*n);
         let mut poly_vec = vec![0i64;n+1];
         poly_vec[0] = 1;
         poly_vec[n] = 1;
         let f = Polynomial::new(poly_vec);
         let sigma = 8.0;
-        Parameters {n, q, t, omega, f, sigma}
+        Parameters {n, q, t, omega, f, k: 1, sigma}
+    }
+
+    /// Switch to module-LWE with a k x k matrix of ring elements
+    /// # Arguments:
+    /// * `k` - module rank, where 1 is plain ring-LWE
+    /// # Returns:
+    /// the same parameters with lattice dimension n*k
+    /// # Example:
+    /// ```
+    /// let params = ring_lwe::utils::Parameters::new(256, 7681, 2).with_rank(3);
+    /// assert_eq!(params.k, 3);
+    /// ```
+    pub fn with_rank(mut self, k: usize) -> Self {
+        assert!(k >= 1, "module rank must be at least 1");
+        self.k = k;
+        self
+    }
+}
+
+/// Default parameters for ring-LWE
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters::new(1024, 12289, 2)
     }
 }
 
@@ -262,6 +301,79 @@ pub fn gen_uniform_poly(size: usize, q: i64, seed: Option<u64>) -> Polynomial<i6
 	mod_coeffs(Polynomial::new(coeffs),q)
 }
 
+/// Generate a uniform polynomial by expanding a public seed
+/// # Arguments:
+/// * `size` - number of coefficients
+/// * `q` - coefficient modulus
+/// * `seed` - 32-byte public seed
+/// * `nonce` - domain separator, so one seed can expand several polynomials
+/// # Returns:
+/// uniform polynomial with coefficients in {0,1,...,q-1}, read from SHAKE-128(seed || nonce)
+/// by rejection sampling
+/// # Example:
+/// ```
+/// let seed = [7u8; 32];
+/// let a = ring_lwe::utils::gen_uniform_poly_from_seed(1024, 12289, &seed, 0);
+/// assert_eq!(a, ring_lwe::utils::gen_uniform_poly_from_seed(1024, 12289, &seed, 0));
+/// ```
+pub fn gen_uniform_poly_from_seed(size: usize, q: i64, seed: &[u8; SEED_BYTES], nonce: u16) -> Polynomial<i64> {
+    let mut hasher = Shake128::default();
+    hasher.update(seed);
+    hasher.update(&nonce.to_le_bytes());
+    let mut reader = hasher.finalize_xof();
+
+    // Draw just enough bytes to cover q and reject candidates outside [0, q)
+    let bits = 64 - (q - 1).leading_zeros();
+    let mask = (1u64 << bits) - 1;
+    let mut buf = [0u8; 8];
+    let mut coeffs = Vec::with_capacity(size);
+    while coeffs.len() < size {
+        reader.read(&mut buf[..bits.div_ceil(8) as usize]);
+        let candidate = (u64::from_le_bytes(buf) & mask) as i64;
+        if candidate < q {
+            coeffs.push(candidate);
+        }
+    }
+    mod_coeffs(Polynomial::new(coeffs), q)
+}
+
+/// Generate a random seed for expanding public polynomials
+/// # Arguments:
+/// * `seed` - random seed
+/// # Returns:
+/// 32 random bytes
+pub fn gen_seed(seed: Option<u64>) -> [u8; SEED_BYTES] {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut bytes = [0u8; SEED_BYTES];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Pack a seed into i64 words so it can be serialized alongside coefficients
+/// # Arguments
+/// * `seed` - 32-byte seed
+/// # Returns
+/// * `words` - four little-endian 64-bit words
+pub fn seed_to_words(seed: &[u8; SEED_BYTES]) -> Vec<i64> {
+    seed.chunks(8).map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap())).collect()
+}
+
+/// Unpack a seed from the i64 words written by `seed_to_words`
+/// # Arguments
+/// * `words` - four little-endian 64-bit words
+/// # Returns
+/// * `seed` - 32-byte seed
+pub fn words_to_seed(words: &[i64]) -> [u8; SEED_BYTES] {
+    let mut seed = [0u8; SEED_BYTES];
+    for (chunk, word) in seed.chunks_mut(8).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    seed
+}
+
 /// Generate a normal polynomial
 /// # Arguments:
 ///	* `size` - number of coefficients
@@ -297,6 +409,18 @@ pub fn nearest_int(a: i64, b: i64) -> i64 {
 	}
 }
 
+/// coefficients of a polynomial padded with zeros to a fixed length
+/// # Arguments
+/// * `x` - polynomial with at most `n` coefficients
+/// * `n` - number of coefficients to return
+/// # Returns
+/// * `coeffs` - the n coefficients of x, including trailing zeros
+pub fn pad_coeffs(x: &Polynomial<i64>, n: usize) -> Vec<i64> {
+    let mut coeffs = x.coeffs().to_vec();
+    coeffs.resize(n, 0);
+    coeffs
+}
+
 /// seralize and encode a vector of i64 to a base64 encoded string
 /// # Arguments
 /// * `data` - vector of i64
@@ -315,4 +439,13 @@ pub fn compress(data: &Vec<i64>) -> String {
 pub fn decompress(base64_str: &str) -> Vec<i64> {
     let decoded_bytes = general_purpose::STANDARD.decode(base64_str).expect("Failed to decode base64 string");
     bincode::deserialize(&decoded_bytes).expect("Failed to deserialize data")
-}
\ No newline at end of file
+}
+/// decode and deserialize a base64 encoded string from an untrusted source
+/// # Arguments
+/// * `base64_str` - base64 encoded string
+/// # Returns
+/// * `decoded_data` - vector of i64, or an error for malformed input instead of a panic
+pub fn try_decompress(base64_str: &str) -> Result<Vec<i64>, String> {
+    let decoded_bytes = general_purpose::STANDARD.decode(base64_str).map_err(|e| format!("invalid base64: {}", e))?;
+    bincode::deserialize(&decoded_bytes).map_err(|e| format!("invalid encoding: {}", e))
+}
@@ -0,0 +1,119 @@
+use crate::crypto::keygen::public_key_from_coeffs;
+use crate::crypto::module_lwe::{expand_matrix, inner_product};
+use crate::crypto::utils::{Parameters, polyadd, polyinv, try_decompress, words_to_seed, SEED_WORDS};
+use polynomial_ring::Polynomial;
+
+/// Standard deviations the number of nonzero coefficients of s or e may stray from its mean
+const WEIGHT_SIGMAS: f64 = 8.0;
+
+/// Public key (b, A) with b a vector of k ring elements and A a k x k matrix; k = 1 for ring-LWE
+type PublicKey = (Vec<Polynomial<i64>>, Vec<Vec<Polynomial<i64>>>);
+
+fn split_polys(coeffs: &[i64], n: usize) -> Vec<Polynomial<i64>> {
+    coeffs.chunks(n).map(|chunk| Polynomial::new(chunk.to_vec())).collect()
+}
+
+/// Decode and range-check a public key in the format of `keygen_string` or `module_keygen_string`
+fn parse_public_key(pk: &str, params: &Parameters) -> Result<PublicKey, String> {
+    let (n, q, k) = (params.n, params.q, params.k);
+    let coeffs = try_decompress(pk)?;
+    let compact = coeffs.len() == k * n + SEED_WORDS;
+    if !compact && coeffs.len() != k * n + k * k * n {
+        return Err(format!(
+            "public key has {} coefficients, expected {} (b and the seed of A) or {} (b and A) for n={} k={}",
+            coeffs.len(), k * n + SEED_WORDS, k * n + k * k * n, n, k
+        ));
+    }
+    // the seed words are arbitrary, every ring coefficient is reduced to (-q/2, q/2]
+    let ring_coeffs = if compact { &coeffs[..k * n] } else { &coeffs[..] };
+    if let Some(position) = ring_coeffs.iter().position(|&c| c <= -(q + 1) / 2 || c > q / 2) {
+        return Err(format!("public key coefficient {} = {} is out of range for q={}", position, ring_coeffs[position], q));
+    }
+
+    if k == 1 {
+        let [b, a] = public_key_from_coeffs(&coeffs, params)?;
+        return Ok((vec![b], vec![vec![a]]));
+    }
+    let b = split_polys(&coeffs[..k * n], n);
+    let a = if compact {
+        expand_matrix(&words_to_seed(&coeffs[k * n..]), params)
+    } else {
+        split_polys(&coeffs[k * n..], n).chunks(k).map(|row| row.to_vec()).collect()
+    };
+    Ok((b, a))
+}
+
+/// Check that a vector of k*n coefficients looks like a sample of the uniform ternary
+/// distribution keygen draws s and e from
+fn check_ternary(coeffs: &[i64], what: &str) -> Result<(), String> {
+    if let Some(position) = coeffs.iter().position(|c| c.abs() > 1) {
+        return Err(format!("{} coefficient {} = {} is not in {{-1, 0, 1}}", what, position, coeffs[position]));
+    }
+    // each coefficient is nonzero with probability 2/3
+    let len = coeffs.len() as f64;
+    let (mean, sigma) = (2.0 * len / 3.0, (2.0 * len / 9.0).sqrt());
+    let weight = coeffs.iter().filter(|&&c| c != 0).count() as f64;
+    if (weight - mean).abs() > WEIGHT_SIGMAS * sigma {
+        return Err(format!("{} has {} nonzero coefficients, far from the {:.0} keygen produces", what, weight, mean));
+    }
+    Ok(())
+}
+
+/// Check that a public key is well formed
+/// # Arguments:
+/// * `pk` - public key from `keygen_string`, or `module_keygen_string` when `params.k > 1`
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// `Ok(())`, or an error naming a wrong length or a coefficient outside (-q/2, q/2]
+/// # Example:
+/// ```
+/// use ring_lwe::validate::validate_public_key;
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// assert_eq!(validate_public_key(keys.get("public").unwrap(), &params), Ok(()));
+/// assert!(validate_public_key(keys.get("public").unwrap(), &ring_lwe::utils::Parameters::new(512, 12289, 2)).is_err());
+/// ```
+pub fn validate_public_key(pk: &str, params: &Parameters) -> Result<(), String> {
+    parse_public_key(pk, params).map(|_| ())
+}
+
+/// Check that a secret key belongs to a public key
+/// # Arguments:
+/// * `pk` - public key from `keygen_string`, or `module_keygen_string` when `params.k > 1`
+/// * `sk` - secret key from the same call
+/// * `params` - ring-LWE parameters
+/// # Returns:
+/// `Ok(())` when s is ternary and e = -(b + A*s) is a ternary vector of plausible weight, as
+/// keygen produces them; otherwise an error. A secret key of another key pair leaves e uniform
+/// mod q and is always caught.
+/// # Example:
+/// ```
+/// use ring_lwe::validate::validate_keypair;
+/// let params = ring_lwe::utils::Parameters::default();
+/// let keys = ring_lwe::keygen::keygen_string(&params, None);
+/// let other = ring_lwe::keygen::keygen_string(&params, None);
+/// assert_eq!(validate_keypair(keys.get("public").unwrap(), keys.get("secret").unwrap(), &params), Ok(()));
+/// assert!(validate_keypair(keys.get("public").unwrap(), other.get("secret").unwrap(), &params).is_err());
+/// ```
+pub fn validate_keypair(pk: &str, sk: &str, params: &Parameters) -> Result<(), String> {
+    let (n, q, k, f) = (params.n, params.q, params.k, &params.f);
+    let (b, a) = parse_public_key(pk, params)?;
+    let mut sk_coeffs = try_decompress(sk)?;
+    // keygen_string drops trailing zeros of a ring-LWE secret key, module keys are padded
+    let short_ring_key = k == 1 && sk_coeffs.len() < n;
+    if sk_coeffs.len() != k * n && !short_ring_key {
+        return Err(format!("secret key has {} coefficients, expected {} for n={} k={}", sk_coeffs.len(), k * n, n, k));
+    }
+    sk_coeffs.resize(k * n, 0);
+    check_ternary(&sk_coeffs, "secret key")?;
+
+    let s = split_polys(&sk_coeffs, n);
+    let mut e_coeffs = Vec::with_capacity(k * n);
+    for (b_i, row) in b.iter().zip(&a) {
+        let e_i = polyinv(&polyadd(b_i, &inner_product(row, &s, params), q, f), q);
+        let mut coeffs = e_i.coeffs().to_vec();
+        coeffs.resize(n, 0);
+        e_coeffs.extend(coeffs);
+    }
+    check_ternary(&e_coeffs, "public key noise b + A*s").map_err(|e| format!("the keys do not match: {}", e))
+}
@@ -4,6 +4,28 @@ pub use crypto::keygen;
 pub use crypto::encrypt;
 pub use crypto::decrypt;
 pub use crypto::utils;
+pub use crypto::noise;
+pub use crypto::security;
+pub use crypto::search;
+pub use crypto::module_lwe;
+pub use crypto::kdf;
+pub use crypto::secret_key;
+pub use crypto::armor;
+pub use crypto::seal;
+pub use crypto::signature;
+pub use crypto::signcrypt;
+pub use crypto::hybrid;
+pub use crypto::key_exchange;
+pub use crypto::channel;
+pub use crypto::prekey;
+pub use crypto::ratchet;
+pub use crypto::threshold;
+pub use crypto::dkg;
+pub use crypto::decryption_proof;
+pub use crypto::possession;
+pub use crypto::validate;
+pub use crypto::blind;
+pub use crypto::anonymous;
 
 pub mod models;
 pub mod api;
\ No newline at end of file
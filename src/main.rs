@@ -1,9 +1,453 @@
+use clap::{Args, Parser, Subcommand};
 use ring_lwe::api::routes::app;
+use ring_lwe::armor::{armor, dearmor, is_armored, ArmorKind, Armored};
+use ring_lwe::decrypt::{decrypt_string, decrypt_with_noise};
+use ring_lwe::encrypt::{encrypt, encrypt_string};
+use ring_lwe::keygen::{keygen, keygen_string};
+use ring_lwe::module_lwe::{module_decrypt_with_noise, module_encrypt, module_keygen};
+use ring_lwe::search::{search, SearchTarget};
+use ring_lwe::secret_key::SecretKey;
+use ring_lwe::security::DEFAULT_MIN_SECURITY_BITS;
+use ring_lwe::utils::{gen_binary_poly, Parameters};
+use ring_lwe::validate::{validate_keypair, validate_public_key};
+use std::fs;
 use tokio::net::TcpListener;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-#[tokio::main]
-async fn main() {
+/// Ring-LWE service and command line tools
+#[derive(Parser)]
+#[command(name = "ring-lwe", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP service (the default when no command is given)
+    Serve,
+    /// Generate a public/secret keypair
+    Keygen {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Save the keys to `public.key` and `secret.key`
+        #[arg(long)]
+        save_keys: bool,
+        /// Encrypt the secret key under this password
+        #[arg(long)]
+        password: Option<String>,
+        /// Output bare base64 instead of armored text
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Encrypt a message: `encrypt <public_key> <message>` or `encrypt --pubkey-file <file> <message>`
+    Encrypt {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Read the public key from a file
+        #[arg(long)]
+        pubkey_file: Option<String>,
+        /// Write the ciphertext to a file instead of the console
+        #[arg(long)]
+        ciphertext_file: Option<String>,
+        /// Output bare base64 instead of armored text
+        #[arg(long)]
+        raw: bool,
+        #[arg(num_args = 1..=2, required = true)]
+        args: Vec<String>,
+    },
+    /// Decrypt a ciphertext: `decrypt <secret_key> <ciphertext>`, keys and ciphertext may come from files
+    Decrypt {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Read the secret key from a file
+        #[arg(long)]
+        secret_file: Option<String>,
+        /// Read the ciphertext from a file
+        #[arg(long)]
+        ciphertext_file: Option<String>,
+        /// Password of a password-protected secret key
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(num_args = 0..=2)]
+        args: Vec<String>,
+    },
+    /// Estimate the core-SVP security of a parameter set against primal and dual attacks
+    Security {
+        #[command(flatten)]
+        params: ParamArgs,
+    },
+    /// Parameter set tools
+    Params {
+        #[command(subcommand)]
+        command: ParamsCommand,
+    },
+    /// Measure decryption noise and print the analytic failure probability
+    Noise {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Number of random encryptions to measure
+        #[arg(long, default_value_t = 100)]
+        trials: usize,
+    },
+    /// Check that a public key is well formed and, given a secret key, that the two belong together
+    VerifyKeys {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Read the public key from a file
+        #[arg(long)]
+        pubkey_file: Option<String>,
+        /// Read the secret key from a file
+        #[arg(long)]
+        secret_file: Option<String>,
+        /// Password of a password-protected secret key
+        #[arg(long)]
+        password: Option<String>,
+        /// `<public_key> [secret_key]`, for keys not read from files
+        #[arg(num_args = 0..=2)]
+        args: Vec<String>,
+    },
+    /// Split a secret key into recovery shares, or recover it from them
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ParamsCommand {
+    /// Search for NTT-friendly parameter sets meeting security, failure-rate and depth goals
+    Search {
+        /// Minimum classical security in bits
+        #[arg(long, default_value_t = DEFAULT_MIN_SECURITY_BITS)]
+        security: f64,
+        /// Maximum decryption failure probability per ciphertext block
+        #[arg(long, default_value_t = 1e-40)]
+        max_failure: f64,
+        /// Number of ciphertext multiplications to support
+        #[arg(long, default_value_t = 0)]
+        depth: u32,
+        /// Plaintext modulus
+        #[arg(long, default_value_t = 2)]
+        t: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupCommand {
+    /// Split a secret key into printable shares, one per line, any `--threshold` of which recover it
+    Split {
+        /// Read the secret key from a file instead of the argument
+        #[arg(long)]
+        secret_file: Option<String>,
+        /// Password of a password-protected secret key
+        #[arg(long)]
+        password: Option<String>,
+        /// Number of shares needed for recovery
+        #[arg(long)]
+        threshold: usize,
+        /// Number of shares to produce
+        #[arg(long)]
+        shares: usize,
+        secret_key: Option<String>,
+    },
+    /// Recover a secret key from shares given as arguments or one per line in files
+    Recover {
+        #[command(flatten)]
+        params: ParamArgs,
+        /// Read shares from a file, one per line
+        #[arg(long)]
+        shares_file: Vec<String>,
+        /// Check the recovered secret key against the public key in this file; shares carry
+        /// only the secret key, so without it a recovery from the wrong backup goes unnoticed
+        #[arg(long)]
+        pubkey_file: Option<String>,
+        /// Encrypt the recovered secret key under this new password
+        #[arg(long)]
+        password: Option<String>,
+        /// Output bare base64 instead of armored text
+        #[arg(long)]
+        raw: bool,
+        shares: Vec<String>,
+    },
+}
+
+/// Parameter overrides shared by every command; omitted values fall back to the defaults, or to
+/// the parameters named by armored inputs
+#[derive(Args)]
+struct ParamArgs {
+    /// Polynomial modulus degree
+    #[arg(long)]
+    n: Option<usize>,
+    /// Ciphertext modulus
+    #[arg(long)]
+    q: Option<i64>,
+    /// Plaintext modulus
+    #[arg(long)]
+    t: Option<i64>,
+    /// Module rank, 1 for ring-LWE
+    #[arg(long)]
+    k: Option<usize>,
+    /// Refuse parameter sets whose classical security falls below this many bits
+    #[arg(long)]
+    min_security: Option<f64>,
+}
+
+impl ParamArgs {
+    fn params(&self) -> Parameters {
+        let default = Parameters::default();
+        let (n, q, t) = (
+            self.n.unwrap_or(default.n),
+            self.q.unwrap_or(default.q),
+            self.t.unwrap_or(default.t),
+        );
+        self.guard(Parameters::new(n, q, t).with_rank(self.k.unwrap_or(default.k)))
+    }
+
+    /// Parameters for a command reading armored inputs: those named in their headers unless
+    /// given on the command line, and every input has to agree with them
+    fn params_for(&self, inputs: &[Option<&Armored>]) -> Parameters {
+        let headers: Vec<&Armored> = inputs.iter().flatten().copied().collect();
+        let overridden = self.n.is_some() || self.q.is_some() || self.t.is_some() || self.k.is_some();
+        let params = match headers.first() {
+            Some(armored) if !overridden => self.guard(armored.params()),
+            _ => self.params(),
+        };
+        if let Some(armored) = headers.iter().find(|armored| !armored.matches(&params)) {
+            fail(format!("{} uses n={} q={} t={} k={}, not n={} q={} t={} k={}",
+                armored.kind.label().to_lowercase(), armored.n, armored.q, armored.t, armored.k,
+                params.n, params.q, params.t, params.k));
+        }
+        params
+    }
+
+    fn guard(&self, params: Parameters) -> Parameters {
+        match self.min_security {
+            Some(bits) => params.require_security(bits).unwrap_or_else(|e| fail(e)),
+            None => params,
+        }
+    }
+}
+
+/// Report an error and exit
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1)
+}
+
+/// Read a key or ciphertext file, dropping the trailing newline
+fn read_trimmed(path: &str) -> String {
+    fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e))
+        .trim()
+        .to_string()
+}
+
+/// Unwrap an armored key or ciphertext of one of the expected types; bare base64 passes through
+fn unarmor(text: String, kinds: &[ArmorKind]) -> (String, Option<Armored>) {
+    if !is_armored(&text) {
+        return (text, None);
+    }
+    let armored = dearmor(&text).unwrap_or_else(|e| fail(e));
+    if !kinds.contains(&armored.kind) {
+        fail(format!("expected {}, found {}", kinds[0].label().to_lowercase(), armored.kind.label().to_lowercase()));
+    }
+    (armored.payload.clone(), Some(armored))
+}
+
+fn run_keygen(params: &Parameters, save_keys: bool, password: Option<String>, raw: bool) {
+    let keys = keygen_string(params, None);
+    let mut pk = keys.get("public").unwrap().clone();
+    let (mut sk, sk_kind) = match &password {
+        Some(password) => (SecretKey::from_base64(keys.get("secret").unwrap()).export_encrypted(password), ArmorKind::EncryptedSecretKey),
+        None => (keys.get("secret").unwrap().clone(), ArmorKind::SecretKey),
+    };
+    if !raw {
+        pk = armor(ArmorKind::PublicKey, &pk, params).unwrap_or_else(|e| fail(e));
+        sk = armor(sk_kind, &sk, params).unwrap_or_else(|e| fail(e));
+    }
+    if save_keys {
+        fs::write("public.key", pk).expect("Failed to write public.key");
+        fs::write("secret.key", sk).expect("Failed to write secret.key");
+        // read the files back, so a key pair that did not survive storage is caught now
+        let (pk, _) = unarmor(read_trimmed("public.key"), &[ArmorKind::PublicKey]);
+        let (sk, _) = unarmor(read_trimmed("secret.key"), &[sk_kind]);
+        let sk = match &password {
+            Some(password) => SecretKey::import_encrypted(&sk, password).unwrap_or_else(|e| fail(e)).to_base64(),
+            None => sk,
+        };
+        validate_keypair(&pk, &sk, params).unwrap_or_else(|e| fail(e));
+        println!("Keys saved to public.key and secret.key");
+    } else if raw {
+        println!("public key: {}", pk);
+        println!("secret key: {}", sk);
+    } else {
+        print!("{}{}", pk, sk);
+    }
+}
+
+fn run_encrypt(params: &ParamArgs, pubkey_file: Option<String>, ciphertext_file: Option<String>, raw: bool, args: Vec<String>) {
+    let (pk, message) = match (pubkey_file, args.as_slice()) {
+        (Some(path), [message]) => (read_trimmed(&path), message.clone()),
+        (None, [pk, message]) => (pk.clone(), message.clone()),
+        _ => panic!("expected `encrypt <public_key> <message>` or `encrypt --pubkey-file <file> <message>`"),
+    };
+    let (pk, pk_armor) = unarmor(pk, &[ArmorKind::PublicKey]);
+    let params = params.params_for(&[pk_armor.as_ref()]);
+    validate_public_key(&pk, &params).unwrap_or_else(|e| fail(e));
+    let mut ciphertext = encrypt_string(&pk, &message, &params, None);
+    if !raw {
+        ciphertext = armor(ArmorKind::Ciphertext, &ciphertext, &params).unwrap_or_else(|e| fail(e));
+    }
+    match ciphertext_file {
+        Some(path) => fs::write(&path, ciphertext).expect("Failed to write ciphertext file"),
+        None => println!("{}", ciphertext),
+    }
+}
+
+fn run_decrypt(params: &ParamArgs, secret_file: Option<String>, ciphertext_file: Option<String>, password: Option<String>, args: Vec<String>) {
+    let mut args = args.into_iter();
+    let sk = secret_file.map(|path| read_trimmed(&path)).or_else(|| args.next())
+        .expect("missing secret key: pass it as an argument or with --secret-file");
+    let ciphertext = ciphertext_file.map(|path| read_trimmed(&path)).or_else(|| args.next())
+        .expect("missing ciphertext: pass it as an argument or with --ciphertext-file");
+    let (sk, sk_armor) = unarmor(sk, &[ArmorKind::SecretKey, ArmorKind::EncryptedSecretKey]);
+    let (ciphertext, ct_armor) = unarmor(ciphertext, &[ArmorKind::Ciphertext]);
+    let params = params.params_for(&[sk_armor.as_ref(), ct_armor.as_ref()]);
+    let sk = match password {
+        Some(password) => SecretKey::import_encrypted(&sk, &password).unwrap_or_else(|e| fail(e)).to_base64(),
+        None if sk_armor.is_some_and(|a| a.kind == ArmorKind::EncryptedSecretKey) => {
+            fail(String::from("the secret key is password-protected, pass --password"))
+        }
+        None => sk,
+    };
+    println!("{}", decrypt_string(&sk, &ciphertext, &params));
+}
+
+fn run_verify_keys(params: &ParamArgs, pubkey_file: Option<String>, secret_file: Option<String>, password: Option<String>, args: Vec<String>) {
+    let mut args = args.into_iter();
+    let pk = pubkey_file.map(|path| read_trimmed(&path)).or_else(|| args.next())
+        .expect("missing public key: pass it as an argument or with --pubkey-file");
+    let sk = secret_file.map(|path| read_trimmed(&path)).or_else(|| args.next());
+    let (pk, pk_armor) = unarmor(pk, &[ArmorKind::PublicKey]);
+    let Some(sk) = sk else {
+        let params = params.params_for(&[pk_armor.as_ref()]);
+        validate_public_key(&pk, &params).unwrap_or_else(|e| fail(e));
+        println!("public key is well formed");
+        return;
+    };
+    let (sk, sk_armor) = unarmor(sk, &[ArmorKind::SecretKey, ArmorKind::EncryptedSecretKey]);
+    let params = params.params_for(&[pk_armor.as_ref(), sk_armor.as_ref()]);
+    let sk = match password {
+        Some(password) => SecretKey::import_encrypted(&sk, &password).unwrap_or_else(|e| fail(e)).to_base64(),
+        None if sk_armor.is_some_and(|a| a.kind == ArmorKind::EncryptedSecretKey) => {
+            fail(String::from("the secret key is password-protected, pass --password"))
+        }
+        None => sk,
+    };
+    validate_keypair(&pk, &sk, &params).unwrap_or_else(|e| fail(e));
+    println!("keys match");
+}
+
+fn run_backup_split(secret_file: Option<String>, password: Option<String>, threshold: usize, shares: usize, secret_key: Option<String>) {
+    let sk = secret_file.map(|path| read_trimmed(&path)).or(secret_key)
+        .expect("missing secret key: pass it as an argument or with --secret-file");
+    let (sk, sk_armor) = unarmor(sk, &[ArmorKind::SecretKey, ArmorKind::EncryptedSecretKey]);
+    let sk = match password {
+        Some(password) => SecretKey::import_encrypted(&sk, &password).unwrap_or_else(|e| fail(e)),
+        None if sk_armor.is_some_and(|a| a.kind == ArmorKind::EncryptedSecretKey) => {
+            fail(String::from("the secret key is password-protected, pass --password"))
+        }
+        None => SecretKey::from_base64(&sk),
+    };
+    for share in sk.split_backup(threshold, shares).unwrap_or_else(|e| fail(e)) {
+        println!("{}", share);
+    }
+}
+
+fn run_backup_recover(params: &ParamArgs, shares_file: Vec<String>, pubkey_file: Option<String>, password: Option<String>, raw: bool, mut shares: Vec<String>) {
+    for path in shares_file {
+        shares.extend(read_trimmed(&path).lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from));
+    }
+    let sk = SecretKey::recover_from_shares(&shares).unwrap_or_else(|e| fail(e));
+    let params = match pubkey_file {
+        Some(path) => {
+            let (pk, pk_armor) = unarmor(read_trimmed(&path), &[ArmorKind::PublicKey]);
+            let params = params.params_for(&[pk_armor.as_ref()]);
+            validate_keypair(&pk, &sk.to_base64(), &params).unwrap_or_else(|e| fail(e));
+            params
+        }
+        None => params.params(),
+    };
+    let (sk, kind) = match password {
+        Some(password) => (sk.export_encrypted(&password), ArmorKind::EncryptedSecretKey),
+        None => (sk.to_base64(), ArmorKind::SecretKey),
+    };
+    if raw {
+        println!("{}", sk);
+    } else {
+        print!("{}", armor(kind, &sk, &params).unwrap_or_else(|e| fail(e)));
+    }
+}
+
+fn run_noise(params: &Parameters, trials: usize) {
+    // (decrypted correctly, noise infinity norm) for each trial
+    let samples: Vec<(bool, i64)> = if params.k > 1 {
+        let (pk, sk) = module_keygen(params, None);
+        (0..trials).map(|_| {
+            let m = gen_binary_poly(params.n, None);
+            let ct = module_encrypt(&pk, &m, params, None);
+            let (decrypted_m, noise) = module_decrypt_with_noise(&sk, &ct, params);
+            (decrypted_m == m, noise)
+        }).collect()
+    } else {
+        let (pk, sk) = keygen(params, None);
+        (0..trials).map(|_| {
+            let m = gen_binary_poly(params.n, None);
+            let ct = encrypt(&pk, &m, params, None);
+            let (decrypted_m, noise) = decrypt_with_noise(&sk, &ct, params);
+            (decrypted_m == m, noise)
+        }).collect()
+    };
+    let max_noise = samples.iter().map(|&(_, noise)| noise).max().unwrap_or(0);
+    let failures = samples.iter().filter(|&&(ok, _)| !ok).count();
+    println!("parameters: n={} q={} t={} k={}", params.n, params.q, params.t, params.k);
+    println!("noise standard deviation (analytic): {:.2}", params.noise_variance().sqrt());
+    println!("noise bound: {}", params.noise_bound());
+    println!("max noise over {} trials: {}", trials, max_noise);
+    println!("failed decryptions: {}", failures);
+    println!("failure probability per block: 2^{:.1}", params.failure_probability_log2());
+}
+
+fn run_security(params: &Parameters) {
+    let estimate = params.security_estimate();
+    println!("parameters: n={} q={} t={} k={}", params.n, params.q, params.t, params.k);
+    println!("primal attack: block size {}, {:.1} bits classical, {:.1} bits quantum",
+        estimate.primal_block_size, estimate.primal_classical, estimate.primal_quantum);
+    println!("dual attack: block size {}, {:.1} bits classical, {:.1} bits quantum",
+        estimate.dual_block_size, estimate.dual_classical, estimate.dual_quantum);
+    println!("security: {:.1} bits classical, {:.1} bits quantum", estimate.classical_bits(), estimate.quantum_bits());
+    if estimate.classical_bits() < DEFAULT_MIN_SECURITY_BITS {
+        println!("warning: below the recommended {} bits", DEFAULT_MIN_SECURITY_BITS);
+    }
+}
+
+fn run_params_search(target: SearchTarget) {
+    let candidates = search(&target);
+    if candidates.is_empty() {
+        println!("no parameter set meets the target");
+        return;
+    }
+    println!("{:>6} {:>3} {:>12} {:>4} {:>10} {:>10} {:>10} {:>10}",
+        "n", "k", "q", "t", "security", "failure", "ct bytes", "packed");
+    for c in candidates {
+        println!("{:>6} {:>3} {:>12} {:>4} {:>10.1} {:>10} {:>10} {:>10}",
+            c.params.n, c.params.k, c.params.q, c.params.t, c.security_bits, format!("2^{:.0}", c.failure_log2),
+            c.ciphertext_bytes, c.packed_ciphertext_bytes);
+    }
+}
+
+async fn serve() {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "ring_lwe=debug,tower_http=debug".into()),
@@ -18,3 +462,32 @@ async fn main() {
     tracing::info!("listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
 }
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        Command::Keygen { params, save_keys, password, raw } => run_keygen(&params.params(), save_keys, password, raw),
+        Command::Encrypt { params, pubkey_file, ciphertext_file, raw, args } => {
+            run_encrypt(&params, pubkey_file, ciphertext_file, raw, args)
+        }
+        Command::Decrypt { params, secret_file, ciphertext_file, password, args } => {
+            run_decrypt(&params, secret_file, ciphertext_file, password, args)
+        }
+        Command::Security { params } => run_security(&params.params()),
+        Command::Params { command: ParamsCommand::Search { security, max_failure, depth, t } } => {
+            run_params_search(SearchTarget { security_bits: security, max_failure, depth, t })
+        }
+        Command::Noise { params, trials } => run_noise(&params.params(), trials),
+        Command::VerifyKeys { params, pubkey_file, secret_file, password, args } => {
+            run_verify_keys(&params, pubkey_file, secret_file, password, args)
+        }
+        Command::Backup { command: BackupCommand::Split { secret_file, password, threshold, shares, secret_key } } => {
+            run_backup_split(secret_file, password, threshold, shares, secret_key)
+        }
+        Command::Backup { command: BackupCommand::Recover { params, shares_file, pubkey_file, password, raw, shares } } => {
+            run_backup_recover(&params, shares_file, pubkey_file, password, raw, shares)
+        }
+    }
+}
@@ -1,15 +1,43 @@
 use serde::{Deserialize, Serialize};
 
+/// Encryption scheme selected by a request
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheme {
+    /// Plain ring-LWE, the default
+    #[default]
+    RingLwe,
+    /// X25519 and ring-LWE combined, secure while either one is
+    Hybrid,
+    /// Ring-LWE keys, with ciphertexts that do not reveal which key they are for
+    Anonymous,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct KeygenRequest {
+    /// When set, the secret key is returned wrapped under this password
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub scheme: Scheme,
+}
+
 #[derive(Serialize)]
 pub struct KeygenResponse {
     pub public_key: String,
-    pub secret_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted_secret_key: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct EncryptRequest {
     pub public_key: String,
     pub message: String,
+    #[serde(default)]
+    pub scheme: Scheme,
 }
 
 #[derive(Serialize)]
@@ -21,9 +49,156 @@ pub struct EncryptResponse {
 pub struct DecryptRequest {
     pub secret_key: String,
     pub ciphertext: String,
+    /// When set, `secret_key` is a password-protected container from `/keygen`
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub scheme: Scheme,
 }
 
 #[derive(Serialize)]
 pub struct DecryptResponse {
     pub message: String,
 }
+
+#[derive(Deserialize)]
+pub struct SealRequest {
+    /// Public keys of every To, Cc and Bcc recipient
+    pub public_keys: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct SealResponse {
+    pub sealed: String,
+}
+
+#[derive(Deserialize)]
+pub struct OpenRequest {
+    pub secret_key: String,
+    pub sealed: String,
+    /// When set, `secret_key` is a password-protected container from `/keygen`
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignRequest {
+    pub secret_key: String,
+    pub message: String,
+    /// When set, `secret_key` is a password-protected container from `/sign/keygen`
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SignResponse {
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyRequest {
+    pub public_key: String,
+    pub message: String,
+    pub signature: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyResponse {
+    pub valid: bool,
+}
+
+#[derive(Deserialize)]
+pub struct HandshakeRequest {
+    pub hello: String,
+}
+
+#[derive(Serialize)]
+pub struct HandshakeResponse {
+    pub reply: String,
+}
+
+#[derive(Deserialize)]
+pub struct PrekeyUploadRequest {
+    /// Long-term verifying key that signed the prekeys
+    pub verifying_key: String,
+    /// Signed one-time prekeys from `generate_prekeys`; their secrets stay with the owner
+    pub prekeys: Vec<String>,
+    /// Signed last-resort prekey from `generate_last_resort_prekey`, replacing an older one
+    #[serde(default)]
+    pub last_resort: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PrekeyUploadResponse {
+    /// One-time prekeys of this owner not yet handed to a sender
+    pub available: usize,
+}
+
+#[derive(Deserialize)]
+pub struct PrekeyFetchRequest {
+    pub verifying_key: String,
+}
+
+#[derive(Serialize)]
+pub struct PrekeyFetchResponse {
+    pub prekey: String,
+    /// Whether this is the owner's last-resort prekey rather than a one-time prekey
+    pub last_resort: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PrekeyEncryptRequest {
+    /// Long-term public key of the recipient
+    pub public_key: String,
+    /// Long-term verifying key of the recipient, checked against the prekey signature
+    pub verifying_key: String,
+    pub prekey: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct PrekeyDecryptRequest {
+    /// Long-term secret key of the recipient
+    pub secret_key: String,
+    /// Secret key of the prekey the message names, kept by the recipient
+    pub prekey_secret_key: String,
+    pub ciphertext: String,
+    /// When set, `secret_key` is a password-protected container from `/keygen`
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterChallengeRequest {
+    pub public_key: String,
+}
+
+#[derive(Serialize)]
+pub struct RegisterChallengeResponse {
+    /// Identifies the pending registration in `/keys/register/complete`
+    pub id: String,
+    /// Nonce encrypted to the submitted public key
+    pub challenge: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterCompleteRequest {
+    pub id: String,
+    /// Answer computed with `possession::possession_response`
+    pub response: String,
+}
+
+#[derive(Serialize)]
+pub struct RegisterCompleteResponse {
+    /// Fingerprint the key is registered under
+    pub fingerprint: String,
+}
+
+#[derive(Deserialize)]
+pub struct RerandomizeRequest {
+    /// Public key the ciphertext was encrypted to
+    pub public_key: String,
+    /// Ciphertext from `/encrypt` with the default scheme; the response carries a fresh copy
+    pub ciphertext: String,
+}